@@ -10,13 +10,42 @@
 --- @field source string | nil
 --- @field categories Category[]
 --- @field links Link[]
---- @field base string | nil
+--- @field base string | nil url relative links resolve against, the entry's xml:base or the feed's link
+--- @field media Media[] enclosures and media:content attached to the entry
+--- @field rights string | nil
+--- @field language string | nil
 local Entry = {}
 
 --- @param tag string
 function Entry:has_category(tag)
 end
 
+--- The content, or the summary when there is none, as plain text
+--- @return string
+function Entry:text() end
+
+--- The plain text cut on a word boundary
+--- @param len? number characters to keep, 200 by default
+--- @return string
+function Entry:excerpt(len) end
+
+--- The content, or the summary, reduced to safe html with absolute urls
+--- @return string | nil
+function Entry:html() end
+
+--- Absolute urls of the images in the content, in order
+--- @return string[]
+function Entry:images() end
+
+--- The first copy of this entry carried by another tracked feed, matched on its canonical link,
+--- title or content
+--- @return Duplicate | nil
+function Entry:duplicate_of() end
+
+--- Whether another tracked feed already carried this entry
+--- @return boolean
+function Entry:seen_elsewhere() end
+
 --- @class Feed
 --- @field id string
 --- @field title string | nil
@@ -26,12 +55,37 @@ end
 --- @field links Link[]
 --- @field categories Category[]
 --- @field ttl number | nil
+--- @field icon Image | nil
+--- @field logo Image | nil
+--- @field language string | nil
+--- @field rights string | nil
+--- @field generator Generator | nil
+--- @field feed_type "atom" | "json" | "rss0" | "rss1" | "rss2" | "source" `source` for feeds from a lua source
 local Feed = {}
 
 --- @param tag string
 function Feed:has_category(tag)
 end
 
+--- @class Duplicate
+--- @field feed string url of the feed the copy came from
+--- @field id string
+--- @field title string | nil
+--- @field link string | nil
+--- @field fetched number unix time the copy was first stored
+
+--- @class Image
+--- @field uri string
+--- @field title string | nil
+--- @field link string | nil
+--- @field width number | nil
+--- @field height number | nil
+
+--- @class Generator
+--- @field name string
+--- @field uri string | nil
+--- @field version string | nil
+
 --- @class Person
 --- @field name string
 --- @field uri string | nil
@@ -53,6 +107,14 @@ end
 --- @field body string | nil
 --- @field link Link | nil
 
+--- @class Media
+--- @field url string
+--- @field media_type string | nil
+--- @field length number | nil size in bytes
+--- @field duration number | nil play time in seconds
+--- @field thumbnail string | nil
+--- @field title string | nil
+
 --- @class AlertOpts
 --- @field summary? string | nil
 --- @field message? string | nil
@@ -64,10 +126,197 @@ function alert(opts) end
 --- Record the current event in a database
 function record() end
 
---- Log the message
---- @param msg string
-function log(msg) end
+--- Log through cynd's logger, under the feed and item being processed; `log(msg)` logs at info
+--- @class Log
+--- @overload fun(msg: string)
+--- @field trace fun(msg: string)
+--- @field debug fun(msg: string) shown with -vv
+--- @field info fun(msg: string) shown by default
+--- @field warn fun(msg: string)
+--- @field error fun(msg: string)
+log = {}
 
---- Run a command
+--- Run a command with `sh -c`, in cynd's environment plus `CYND_FEED`, `CYND_FEED_TITLE`,
+--- `CYND_ITEM_ID`, `CYND_ITEM_TITLE` and `CYND_ITEM_LINK`, those the feed or item lacks left unset.
+--- From `on_feed_error` it gets `CYND_FEED`, `CYND_ERROR_KIND` and `CYND_ERROR` instead.
 --- @param cmd string
 function exec(cmd) end
+
+--- Mark the current item as read
+function mark_read() end
+
+--- Star the current item
+function star() end
+
+--- @class DownloadOpts
+--- @field dir? string defaults to the user's download directory, `~` is expanded
+--- @field filename? string defaults to the last segment of the media url, after a short hash of the feed and item
+--- @field max_size? number largest download in bytes, 2 GiB by default
+
+--- Save the current item's first media enclosure, resuming partial downloads
+--- @param opts? DownloadOpts
+function download(opts) end
+
+--- @class FeedError
+--- @field url string
+--- @field status number | nil
+--- @field kind "fetch" | "parse" | "other" | "moved"
+--- @field message string
+--- @field failures number consecutive failed fetches, including this one
+--- @field paused boolean whether the feed was paused for being gone (410 or a long run of 404s)
+--- @field moved string | nil the new url, when a stable permanent redirect moved the feed
+
+--- @class Response
+--- @field url string the url fetched, after following redirects
+--- @field status number | nil
+--- @field body string
+local Response = {}
+
+--- Parse the body as html
+--- @return Document
+function Response:html() end
+
+--- Decode the body as json
+--- @return any
+function Response:json() end
+
+--- @class Document
+local Document = {}
+
+--- Elements matching a css selector
+--- @param css string
+--- @return Element[]
+function Document:select(css) end
+
+--- The text of the document without markup
+--- @return string
+function Document:text() end
+
+--- @class Element
+--- @field name string
+--- @field html string the inner html
+--- @field text string
+local Element = {}
+
+--- @param name string
+--- @return string | nil
+function Element:attr(name) end
+
+--- Descendants matching a css selector
+--- @param css string
+--- @return Element[]
+function Element:select(css) end
+
+--- @class Http
+--- @field get fun(url: string): Response fetch an http(s) url
+--- @field html fun(html: string): Document
+--- @field json fun(json: string): any
+
+--- @class SourceItem
+--- @field id? string defaults to the link, then the title
+--- @field title? string
+--- @field link? string
+--- @field summary? string
+--- @field content? string
+--- @field author? string
+--- @field authors? string[]
+--- @field categories? string[]
+--- @field published? string | number rfc3339 or rfc2822 date, or a unix timestamp
+--- @field updated? string | number
+
+--- @class SourceFeed
+--- @field title? string
+--- @field description? string
+--- @field link? string
+--- @field ttl? number
+--- @field items SourceItem[]
+
+--- Custom feeds, tracked as `lua://<name>`
+--- @type table<string, fun(http: Http): SourceItem[] | SourceFeed>
+sources = {}
+
+--- @class Regex
+local Regex = {}
+
+--- @param text string
+--- @return boolean
+function Regex:is_match(text) end
+
+--- The first match
+--- @param text string
+--- @return string | nil
+function Regex:find(text) end
+
+--- Every non-overlapping match
+--- @param text string
+--- @return string[]
+function Regex:find_all(text) end
+
+--- The groups of the first match, by position and by name
+--- @param text string
+--- @return table<integer | string, string> | nil
+function Regex:captures(text) end
+
+--- Replaces every match, `$1` or `${name}` in the replacement inserting a group
+--- @param text string
+--- @param replacement string
+--- @return string
+function Regex:replace(text, replacement) end
+
+--- @class Url
+--- @field href string
+--- @field scheme string
+--- @field host string | nil
+--- @field port number | nil the explicit port, else the scheme's default
+--- @field path string
+--- @field query string | nil
+--- @field fragment string | nil
+--- @field params table<string, string> decoded query parameters, the first of repeated ones
+
+--- @class CyndJson
+--- @field encode fun(value: any): string sequences become arrays, other tables objects
+--- @field decode fun(json: string): any
+
+--- @class CyndUrl
+--- @field parse fun(href: string): Url | nil nil when href isn't an absolute url
+--- @field join fun(base: string, href: string): string | nil
+
+--- @class CyndHtml
+--- @field escape fun(text: string): string
+--- @field text fun(html: string): string strips the markup, keeping block elements on separate lines
+
+--- Helpers for scripts, also returned by `require("cynd")`
+--- @class Cynd
+--- @field regex fun(pattern: string): Regex compile a regex, in the syntax of rust's regex crate
+--- @field json CyndJson
+--- @field url CyndUrl
+--- @field html CyndHtml
+--- @field template fun(template: string, vars: table): string replace `{name}` and `{name.field}` with values from vars, nil ones becoming empty; `{{` and `}}` are literal braces
+cynd = {}
+
+--- Values kept in the database, shared by every lua worker and every run of cynd; plain globals
+--- are private to one worker and gone when cynd exits
+--- @class Store
+--- @field get fun(key: string): any the saved value, nil when there is none
+--- @field set fun(key: string, value: any) save anything json can hold, nil removing the key
+--- @field add fun(key: string, by?: number): number add to a number in one step, 1 by default, so workers counting together don't lose updates
+store = {}
+
+--- @class UrlRules
+--- @field strip? string[] query parameters removed from item links, `*` ending a prefix; utm_*, fbclid, gclid and friends by default
+--- @field https? boolean rewrite http item links to https, off by default
+
+--- Caps on each call into the config; a call going over them fails for that item only
+--- @class Limits
+--- @field instructions? integer lua instructions per call, unlimited by default
+--- @field seconds? number wall time for process and on_feed_error, 10 by default
+--- @field source_seconds? number wall time for a source, 120 by default
+--- @field memory? integer bytes the lua state may allocate, 256 MiB by default, 0 for no cap
+
+--- The table returned from init.lua
+--- @class Config
+--- @field process fun(item: Entry, feed: Feed)
+--- @field on_feed_error? fun(err: FeedError) called when fetching a tracked feed fails, or it moves
+--- @field urls? UrlRules how item links are canonicalized for display and duplicate detection
+--- @field limits? Limits
+--- @field sandbox? boolean require `cynd --sandbox`, which removes io, os.execute and the other ways out of the process before init.lua loads; init.lua fails to load without it
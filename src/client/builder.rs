@@ -1,13 +1,20 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
-use crate::{fetcher::Fetcher, runtime::Runtime};
+use crate::{fetcher::Fetcher, interp::Mode, metrics::Metrics, runtime::Runtime};
+
+/// Most lua workers started when the count isn't given, each being a full copy of init.lua
+const MAX_DEFAULT_WORKERS: usize = 4;
 
 #[derive(Default)]
 pub struct ClientBuilder {
     client: Option<reqwest::Client>,
     runtime: Option<PathBuf>,
     database: Option<PathBuf>,
+    credentials: Option<PathBuf>,
     migrate: Option<bool>,
+    workers: Option<usize>,
+    sandbox: bool,
+    mode: Mode,
 }
 
 impl ClientBuilder {
@@ -36,11 +43,39 @@ impl ClientBuilder {
         self
     }
 
+    pub fn credentials(mut self, credentials: PathBuf) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
     pub fn migrate(mut self) -> Self {
         self.migrate = Some(true);
         self
     }
 
+    /// How many lua states evaluate items in parallel, at least one
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = Some(workers);
+        self
+    }
+
+    /// Strips io, os.execute and the other ways out of the process before init.lua loads
+    pub fn sandbox(mut self, sandbox: bool) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Whether programs are executed, described or recorded
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn workers_opt(mut self, workers: Option<usize>) -> Self {
+        self.workers = workers;
+        self
+    }
+
     pub async fn build(self) -> crate::Result<super::Client> {
         let rpath = self
             .runtime
@@ -62,15 +97,51 @@ impl ClientBuilder {
             })
             .ok_or(crate::Error::InvalidSetup)?;
 
-        let runtime = Runtime::new(rpath);
-        let client = self.client.unwrap_or_default();
+        let client = match self.client {
+            Some(client) => client,
+            None => reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()?,
+        };
+        let credentials = self.credentials.or_else(|| {
+            let mut dir = dirs::config_dir()?;
+            dir.push("cyndikator");
+            dir.push("credentials");
+            Some(dir)
+        });
+
+        let fetcher = Fetcher {
+            client,
+            credentials,
+        };
         let conn = rusqlite::Connection::open(dpath).map_err(|_| crate::Error::InvalidSetup)?;
         let conn = crate::db::Conn::new(conn, self.migrate.unwrap_or(false))?;
 
+        let workers = self.workers.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map_or(1, |n| n.get())
+                .min(MAX_DEFAULT_WORKERS)
+        });
+        let metrics = Metrics::new()?;
+        let runtime = Runtime::new(
+            rpath,
+            fetcher.clone(),
+            conn.clone(),
+            metrics.clone(),
+            workers.max(1),
+            self.sandbox,
+        );
+
+        // a broken init.lua shouldn't keep the commands that don't run it from working
+        let canonical = runtime.canonical().await.unwrap_or_default();
+
         let client = super::Client {
             runtime,
             conn,
-            fetcher: Fetcher { client },
+            fetcher,
+            canonical: Arc::new(canonical),
+            mode: self.mode,
+            metrics,
         };
 
         Ok(client)
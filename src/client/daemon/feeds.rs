@@ -1,65 +1,61 @@
 use std::sync::Arc;
 
-use chrono::{DateTime, Duration, Utc};
-use tokio::{
-    sync::{Mutex, Notify, mpsc::Sender},
-    time::Instant,
-};
+use tokio::sync::{Mutex, Notify, mpsc::Sender};
 use tokio_util::sync::CancellationToken;
 
-use crate::{client::daemon::Action, db::types::Feed};
+use crate::{
+    client::daemon::{
+        Action,
+        schedule::{Clock, MAX_SLEEP, Scheduler},
+    },
+    db::types::Feed,
+    metrics::Metrics,
+};
 
-pub(crate) struct CheckFeeds {
+pub(crate) struct CheckFeeds<C> {
     pub(crate) send: Sender<Action>,
     pub(crate) token: CancellationToken,
     pub(crate) notify: Arc<Notify>,
     pub(crate) feeds: Arc<Mutex<Vec<Feed>>>,
+    pub(crate) scheduler: Scheduler<C>,
+    pub(crate) metrics: Metrics,
 }
 
-impl CheckFeeds {
-    pub(crate) async fn run(self) {
-        let mut current = self.find_current().await;
-
+impl<C: Clock> CheckFeeds<C> {
+    pub(crate) async fn run(mut self) {
         loop {
+            let plan = {
+                let feeds = self.feeds.lock().await;
+                self.scheduler.plan(&feeds)
+            };
+
+            self.metrics.overdue(plan.overdue);
+
+            if let Some(drift) = plan.jumped {
+                tracing::warn!(
+                    drift = drift.num_seconds(),
+                    "clock moved, rescheduling overdue feeds"
+                );
+            }
+
+            for feed in plan.due {
+                let _ = self.send.send(Action::Fetch(feed)).await;
+            }
+
+            let sleep = (plan.wake - self.scheduler.now())
+                .clamp(chrono::Duration::zero(), MAX_SLEEP)
+                .to_std()
+                .unwrap_or_default();
+
             tokio::select! {
-                _ = self.notify.notified() => {
-                    current = self.find_current().await;
-                }
+                _ = self.notify.notified() => (),
 
                 _ = self.token.cancelled() => {
                     break;
                 }
 
-                _ = CheckFeeds::wait_for(current.clone().map(|(a, _)| a)) => {
-                    if let Some(current) = current.take() {
-                        let _ = self.send.send(Action::Fetch(current.1.clone())).await;
-                    }
-                }
+                _ = tokio::time::sleep(sleep) => (),
             }
         }
     }
-
-    async fn wait_for(date: Option<DateTime<Utc>>) {
-        if let Some(date) = date {
-            let dur = date - Utc::now();
-            let inst = Instant::now() + dur.to_std().unwrap();
-
-            tokio::time::sleep_until(inst.into()).await
-        } else {
-            futures::future::pending().await
-        }
-    }
-
-    async fn find_current(&self) -> Option<(DateTime<Utc>, Feed)> {
-        let feeds = self.feeds.lock().await;
-        feeds
-            .iter()
-            .map(|feed| {
-                let next = feed.last_fetch + Duration::minutes(feed.ttl.into());
-
-                (next, feed)
-            })
-            .min_by(|(a, _), (b, _)| a.cmp(b))
-            .map(|(date, feed)| (date, feed.clone()))
-    }
 }
@@ -1,24 +1,298 @@
+use std::{sync::Arc, time::Instant};
+
+use chrono::Utc;
+use tokio::sync::mpsc::Sender;
 use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 use url::Url;
 
-use crate::{db::types::Feed, fetcher::Fetcher};
+use crate::{
+    client::{
+        Client,
+        daemon::{Action, AsyncOp, limit::Limiter},
+    },
+    db::types::{Feed, FetchEntry},
+    feed::FeedError,
+};
+
+/// Consecutive 404s after which a feed is considered gone
+const NOT_FOUND_STREAK: u32 = 10;
 
 pub struct FetchFeed {
     pub(crate) feed: Feed,
     pub(crate) token: CancellationToken,
-    pub(crate) fetcher: Fetcher,
+    pub(crate) client: Client,
+    pub(crate) send: Sender<Action>,
+    pub(crate) limiter: Arc<Limiter>,
+}
+
+impl AsyncOp for FetchFeed {
+    async fn run(self) -> crate::Result<()> {
+        let span = tracing::info_span!("fetch", feed = %self.feed.url);
+        let res = tokio::select! {
+            _ = self.token.cancelled() => Ok(()),
+            res = self.fetch().instrument(span) => res,
+        };
+
+        let _ = self.send.send(Action::Reload).await;
+
+        res
+    }
 }
 
 impl FetchFeed {
-    pub(crate) async fn run(self) {
+    async fn fetch(&self) -> crate::Result<()> {
         let url = match Url::parse(&self.feed.url) {
             Ok(url) => url,
             Err(err) => {
-                eprintln!("invalid feed url {} {}", self.feed.url, err);
-                return;
+                tracing::error!("invalid feed url: {err}");
+                return Ok(());
+            }
+        };
+
+        self.client
+            .conn
+            .track(self.feed.url.clone(), Utc::now())
+            .await?;
+
+        let mut entry = FetchEntry {
+            time: Utc::now(),
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        let res = self.process(url, &mut entry).await;
+        let elapsed = start.elapsed();
+        entry.duration = elapsed.as_millis().try_into().unwrap_or(u32::MAX);
+
+        if let Err(err) = &res {
+            entry.status = entry.status.or(err.status());
+            entry.kind = Some(err.kind().to_string());
+            entry.error = Some(err.to_string());
+        }
+
+        let streak = self
+            .client
+            .conn
+            .log_fetch(self.feed.url.clone(), entry.clone())
+            .await?;
+
+        let outcome = match &res {
+            Ok(_) => "ok",
+            Err(err) => err.kind(),
+        };
+        self.client
+            .metrics
+            .fetched(&self.feed.url, outcome, elapsed, entry.bytes);
+
+        match &res {
+            Ok(_) => tracing::info!(
+                status = entry.status,
+                bytes = entry.bytes,
+                items = entry.items,
+                new = entry.new_items,
+                ms = entry.duration,
+                "fetched"
+            ),
+            Err(err) => tracing::warn!(
+                status = entry.status,
+                failures = streak.failures,
+                ms = entry.duration,
+                "fetch failed: {err}"
+            ),
+        }
+
+        let err = match res {
+            Ok(None) => return Ok(()),
+            Ok(Some(target)) => return self.moved(target).await,
+            Err(err) => err,
+        };
+
+        let reason = match entry.status {
+            Some(410) => Some("gone (410)".to_string()),
+            Some(404) if streak.not_found >= NOT_FOUND_STREAK => {
+                Some(format!("not found (404) {} times", streak.not_found))
             }
+            _ => None,
+        };
+
+        if let Some(reason) = &reason {
+            self.client
+                .conn
+                .pause(self.feed.url.clone(), reason.clone())
+                .await?;
+        }
+
+        let paused = reason.is_some();
+        let error = FeedError {
+            url: self.feed.url.clone(),
+            status: entry.status,
+            kind: err.kind().to_string(),
+            message: reason.unwrap_or_else(|| err.to_string()),
+            failures: streak.failures,
+            paused,
+            moved: None,
+        };
+
+        // the hook failing shouldn't hide what went wrong with the fetch
+        if let Err(report) = self.client.report(error).await {
+            tracing::warn!("failed to report the fetch error: {report}");
+        }
+
+        Err(err)
+    }
+
+    /// Lets the config know a stable permanent redirect moved the feed
+    async fn moved(&self, target: String) -> crate::Result<()> {
+        tracing::warn!(to = %target, "feed moved");
+
+        let error = FeedError {
+            url: self.feed.url.clone(),
+            status: None,
+            kind: "moved".to_string(),
+            message: format!("moved permanently to {target}"),
+            failures: 0,
+            paused: false,
+            moved: Some(target),
+        };
+
+        self.client.report(error).await
+    }
+
+    /// Fetches and ingests the feed, returning where it moved to when it did
+    async fn process(&self, url: Url, entry: &mut FetchEntry) -> crate::Result<Option<String>> {
+        let permit = self.limiter.acquire(url.host_str()).await;
+        let (feed, moved) = if url.scheme() == "lua" {
+            (self.client.runtime.source(url).await?, None)
+        } else {
+            let options = self
+                .client
+                .conn
+                .request_options(self.feed.url.clone())
+                .await?
+                .unwrap_or_default();
+
+            let fetched = self.client.fetcher.fetch_with(url, &options).await?;
+            entry.status = fetched.status;
+            entry.bytes = Some(fetched.body.len().try_into().unwrap_or(u32::MAX));
+
+            (fetched.parse()?, fetched.moved.as_ref().map(Url::to_string))
         };
+        drop(permit);
+
+        self.client
+            .conn
+            .set_hints(
+                self.feed.url.clone(),
+                feed.meta.ttl,
+                feed.meta.hints.clone(),
+            )
+            .await?;
+
+        entry.items = Some(feed.items.len().try_into().unwrap_or(u32::MAX));
+        let hub = feed.meta.hub(&self.feed.url);
+
+        let fresh = self.client.ingest(self.feed.url.clone(), feed).await?;
+        entry.new_items = Some(fresh.try_into().unwrap_or(u32::MAX));
+
+        self.client
+            .conn
+            .note_hub(self.feed.url.clone(), hub)
+            .await?;
+
+        self.client
+            .conn
+            .note_redirect(self.feed.url.clone(), moved)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{client::daemon::limit::FetchLimits, testing::Scratch};
+
+    async fn fetch(client: &Client, url: &str) -> crate::Result<()> {
+        client.conn.insert(None, url.to_string(), 60).await.unwrap();
+        client
+            .conn
+            .track(url.to_string(), Utc::now())
+            .await
+            .unwrap();
+        let feed = client
+            .conn
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|feed| feed.url == url)
+            .unwrap();
+
+        let (send, _recv) = mpsc::channel(8);
+        FetchFeed {
+            feed,
+            token: CancellationToken::new(),
+            client: client.clone(),
+            send,
+            limiter: Arc::new(Limiter::new(FetchLimits::default())),
+        }
+        .run()
+        .await
+    }
+
+    async fn state(client: &Client, key: &str) -> Option<serde_json::Value> {
+        let value = client.conn.state(key.to_string()).await.unwrap()?;
+        Some(serde_json::from_str(&value).unwrap())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reports_failures_to_on_feed_error() {
+        let scratch = Scratch::new();
+        let client = scratch
+            .client(
+                r#"return {
+                    process = function() end,
+                    on_feed_error = function(err)
+                        store.set("error", {
+                            url = err.url, kind = err.kind, failures = err.failures,
+                            paused = err.paused,
+                        })
+                    end,
+                }"#,
+                1,
+            )
+            .await;
+        let url = format!("file://{}/missing.xml", scratch.dir("feeds").display());
+
+        assert!(fetch(&client, &url).await.is_err());
+        assert_eq!(
+            state(&client, "error").await,
+            Some(serde_json::json!({
+                "url": url, "kind": "fetch", "failures": 1, "paused": false,
+            }))
+        );
+
+        fetch(&client, &url).await.unwrap_err();
+        assert_eq!(state(&client, "error").await.unwrap()["failures"], 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keeps_the_fetch_error_when_the_hook_fails() {
+        let scratch = Scratch::new();
+        let client = scratch
+            .client(
+                r#"return {
+                    process = function() end,
+                    on_feed_error = function() error("hook broke") end,
+                }"#,
+                1,
+            )
+            .await;
+        let url = format!("file://{}/missing.xml", scratch.dir("feeds").display());
 
-        self.fetcher.fetch_items(url).await;
+        let err = fetch(&client, &url).await.unwrap_err();
+        assert_eq!(err.kind(), "fetch", "{err}");
     }
 }
@@ -0,0 +1,198 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use tokio::{
+    sync::{Mutex, OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+
+/// Fetches allowed to run at once across every host, by default
+const MAX_FETCHES: usize = 8;
+
+/// Fetches allowed to run at once against a single host, by default
+const MAX_HOST_FETCHES: usize = 2;
+
+/// Minimum time between starting requests to the same host, by default
+const HOST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a host goes without fetches before it is forgotten
+const HOST_IDLE: Duration = Duration::from_secs(10 * 60);
+
+/// How many fetches may run at once and how often a host may be hit
+#[derive(Clone, Copy, Debug)]
+pub struct FetchLimits {
+    /// fetches running at once across every host
+    pub fetches: usize,
+    /// fetches running at once against a single host
+    pub host_fetches: usize,
+    /// minimum time between starting requests to the same host
+    pub host_interval: Duration,
+}
+
+impl Default for FetchLimits {
+    fn default() -> Self {
+        FetchLimits {
+            fetches: MAX_FETCHES,
+            host_fetches: MAX_HOST_FETCHES,
+            host_interval: HOST_INTERVAL,
+        }
+    }
+}
+
+/// Bounds how many fetches run at once, overall and per host
+pub(crate) struct Limiter {
+    limits: FetchLimits,
+    global: Arc<Semaphore>,
+    hosts: std::sync::Mutex<HashMap<String, Arc<Host>>>,
+}
+
+struct Host {
+    conns: Arc<Semaphore>,
+    next: Mutex<Instant>,
+}
+
+/// Held for the duration of a fetch
+pub(crate) struct Permit {
+    _host: Option<OwnedSemaphorePermit>,
+    _global: Option<OwnedSemaphorePermit>,
+}
+
+impl Limiter {
+    pub(crate) fn new(limits: FetchLimits) -> Limiter {
+        let limits = FetchLimits {
+            fetches: limits.fetches.max(1),
+            host_fetches: limits.host_fetches.max(1),
+            ..limits
+        };
+
+        Limiter {
+            limits,
+            global: Arc::new(Semaphore::new(limits.fetches)),
+            hosts: Default::default(),
+        }
+    }
+
+    /// Waits for a slot on the host, and for the host's rate limit, before taking a global slot
+    pub(crate) async fn acquire(&self, host: Option<&str>) -> Permit {
+        let host = host.map(|host| {
+            let mut hosts = self.hosts.lock().unwrap_or_else(|err| err.into_inner());
+            self.evict(&mut hosts, Instant::now());
+
+            hosts
+                .entry(host.to_string())
+                .or_insert_with(|| {
+                    Arc::new(Host {
+                        conns: Arc::new(Semaphore::new(self.limits.host_fetches)),
+                        next: Mutex::new(Instant::now()),
+                    })
+                })
+                .clone()
+        });
+
+        let host_permit = match host {
+            Some(host) => {
+                let permit = host.conns.clone().acquire_owned().await.ok();
+
+                let mut next = host.next.lock().await;
+                tokio::time::sleep_until(*next).await;
+                *next = Instant::now() + self.limits.host_interval;
+
+                permit
+            }
+            None => None,
+        };
+
+        Permit {
+            _host: host_permit,
+            _global: self.global.clone().acquire_owned().await.ok(),
+        }
+    }
+
+    /// Forgets hosts nothing is fetching from or waiting on, whose last request is long past
+    fn evict(&self, hosts: &mut HashMap<String, Arc<Host>>, now: Instant) {
+        hosts.retain(|_, host| {
+            let idle = Arc::strong_count(host) == 1
+                && host.conns.available_permits() == self.limits.host_fetches
+                && host
+                    .next
+                    .try_lock()
+                    .is_ok_and(|next| now.saturating_duration_since(*next) > HOST_IDLE);
+
+            !idle
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHORT: Duration = Duration::from_millis(50);
+
+    fn limiter(fetches: usize, host_fetches: usize, host_interval: Duration) -> Limiter {
+        Limiter::new(FetchLimits {
+            fetches,
+            host_fetches,
+            host_interval,
+        })
+    }
+
+    async fn blocked(limiter: &Limiter, host: &str) -> bool {
+        tokio::time::timeout(SHORT, limiter.acquire(Some(host)))
+            .await
+            .is_err()
+    }
+
+    #[tokio::test]
+    async fn bounds_fetches_per_host() {
+        let limiter = limiter(8, 1, Duration::ZERO);
+
+        let held = limiter.acquire(Some("a.example")).await;
+        assert!(blocked(&limiter, "a.example").await);
+        assert!(!blocked(&limiter, "b.example").await);
+
+        drop(held);
+        assert!(!blocked(&limiter, "a.example").await);
+    }
+
+    #[tokio::test]
+    async fn bounds_fetches_overall() {
+        let limiter = limiter(1, 2, Duration::ZERO);
+
+        let held = limiter.acquire(Some("a.example")).await;
+        assert!(blocked(&limiter, "b.example").await);
+
+        drop(held);
+        assert!(!blocked(&limiter, "b.example").await);
+    }
+
+    #[tokio::test]
+    async fn spaces_requests_to_a_host() {
+        let limiter = limiter(8, 2, SHORT * 2);
+
+        let start = Instant::now();
+        drop(limiter.acquire(Some("a.example")).await);
+        drop(limiter.acquire(Some("b.example")).await);
+        assert!(start.elapsed() < SHORT * 2);
+
+        drop(limiter.acquire(Some("a.example")).await);
+        assert!(start.elapsed() >= SHORT * 2);
+    }
+
+    #[tokio::test]
+    async fn forgets_idle_hosts() {
+        let limiter = limiter(8, 2, Duration::ZERO);
+
+        let held = limiter.acquire(Some("busy.example")).await;
+        drop(limiter.acquire(Some("idle.example")).await);
+
+        let mut hosts = limiter.hosts.lock().unwrap();
+        limiter.evict(&mut hosts, Instant::now());
+        assert_eq!(hosts.len(), 2);
+
+        limiter.evict(&mut hosts, Instant::now() + HOST_IDLE * 2);
+        assert!(hosts.contains_key("busy.example"));
+        assert!(!hosts.contains_key("idle.example"));
+
+        drop(held);
+    }
+}
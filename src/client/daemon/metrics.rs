@@ -0,0 +1,42 @@
+use axum::{
+    Router, extract::State, http::StatusCode, http::header, response::IntoResponse, routing::get,
+};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+use crate::metrics::Metrics;
+
+/// Content type of the prometheus text exposition format
+const TEXT_FORMAT: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Serves the metrics for prometheus to scrape
+pub(crate) struct Serve {
+    pub(crate) metrics: Metrics,
+    pub(crate) listener: TcpListener,
+    pub(crate) token: CancellationToken,
+}
+
+impl Serve {
+    pub(crate) async fn run(self) {
+        let app = Router::new()
+            .route("/metrics", get(scrape))
+            .with_state(self.metrics);
+
+        if let Err(err) = axum::serve(self.listener, app)
+            .with_graceful_shutdown(self.token.cancelled_owned())
+            .await
+        {
+            tracing::error!("metrics listener failed: {err}");
+        }
+    }
+}
+
+async fn scrape(State(metrics): State<Metrics>) -> impl IntoResponse {
+    match metrics.render() {
+        Ok(body) => (StatusCode::OK, [(header::CONTENT_TYPE, TEXT_FORMAT)], body).into_response(),
+        Err(err) => {
+            tracing::error!("failed to render metrics: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
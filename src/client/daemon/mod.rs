@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use crate::db::types::Feed;
 
@@ -8,15 +8,28 @@ use tokio::sync::{
     mpsc::{Receiver, Sender},
 };
 use tokio_util::sync::CancellationToken;
+use url::Url;
 
 mod feeds;
 mod fetch;
+mod limit;
+mod metrics;
+mod prune;
+mod schedule;
 mod signals;
+mod websub;
+
+pub use limit::FetchLimits;
+
+const PRUNE_PERIOD: Duration = Duration::from_secs(60 * 60);
 
 pub struct Daemon {
     client: Client,
     send: Sender<Action>,
     recv: Receiver<Action>,
+    websub: Option<websub::WebSub>,
+    metrics: Option<SocketAddr>,
+    limits: FetchLimits,
 }
 
 enum Action {
@@ -31,7 +44,7 @@ trait AsyncOp: Send + Sized + 'static {
     fn spawn(self) {
         tokio::spawn(async move {
             if let Err(e) = self.run().await {
-                eprintln!("error in async {}", e);
+                tracing::error!("{e}");
             }
         });
     }
@@ -40,7 +53,32 @@ trait AsyncOp: Send + Sized + 'static {
 impl Daemon {
     pub(crate) fn new(client: Client) -> Self {
         let (send, recv) = tokio::sync::mpsc::channel::<Action>(16);
-        Daemon { client, send, recv }
+        Daemon {
+            client,
+            send,
+            recv,
+            websub: None,
+            metrics: None,
+            limits: FetchLimits::default(),
+        }
+    }
+
+    /// Listens for websub pushes on `listen`, which hubs reach through `callback`
+    pub fn websub(mut self, listen: SocketAddr, callback: Url) -> Self {
+        self.websub = Some(websub::WebSub { listen, callback });
+        self
+    }
+
+    /// Bounds how many fetches run at once and how often a host is hit
+    pub fn fetch_limits(mut self, limits: FetchLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Serves prometheus metrics at `/metrics` on `listen`
+    pub fn metrics(mut self, listen: SocketAddr) -> Self {
+        self.metrics = Some(listen);
+        self
     }
 
     pub async fn run(self) -> crate::Result<()> {
@@ -48,11 +86,15 @@ impl Daemon {
             ref client,
             send,
             mut recv,
+            websub,
+            metrics,
+            limits,
         } = self;
 
         let feeds = Arc::new(Mutex::new(client.conn.list().await?));
         let token = CancellationToken::default();
         let notify = Arc::new(Notify::default());
+        let limiter = Arc::new(limit::Limiter::new(limits));
 
         let check_signals = signals::CheckSignals {
             send: send.clone(),
@@ -65,9 +107,44 @@ impl Daemon {
             token: token.clone(),
             notify: notify.clone(),
             feeds: feeds.clone(),
+            scheduler: schedule::Scheduler::new(schedule::SystemClock::new()),
+            metrics: client.metrics.clone(),
         };
         tokio::spawn(async move { check_feeds.run().await });
 
+        let prune_items = prune::PruneItems {
+            conn: client.conn.clone(),
+            token: token.clone(),
+            period: PRUNE_PERIOD,
+        };
+        tokio::spawn(async move { prune_items.run().await });
+
+        if let Some(listen) = metrics {
+            let serve = metrics::Serve {
+                metrics: client.metrics.clone(),
+                listener: tokio::net::TcpListener::bind(listen).await?,
+                token: token.clone(),
+            };
+            tokio::spawn(async move { serve.run().await });
+        }
+
+        if let Some(websub) = websub {
+            let callbacks = websub::Callbacks {
+                client: client.clone(),
+                listener: tokio::net::TcpListener::bind(websub.listen).await?,
+                path: websub.callback.path().to_string(),
+                token: token.clone(),
+            };
+            tokio::spawn(async move { callbacks.run().await });
+
+            let subscribe = websub::Subscribe {
+                client: client.clone(),
+                callback: websub.callback,
+                token: token.clone(),
+            };
+            tokio::spawn(async move { subscribe.run().await });
+        }
+
         while let Some(action) = recv.recv().await {
             match action {
                 Action::Reload => {
@@ -81,12 +158,14 @@ impl Daemon {
 
                 Action::Fetch(feed) => {
                     let fetch = fetch::FetchFeed {
-                        fetcher: self.client.fetcher.clone(),
+                        client: client.clone(),
+                        send: send.clone(),
 
                         feed,
                         token: token.clone(),
+                        limiter: limiter.clone(),
                     };
-                    tokio::spawn(async move { fetch.run().await });
+                    fetch.spawn();
                 }
             }
         }
@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+
+use crate::db::Conn;
+
+pub(crate) struct PruneItems {
+    pub(crate) conn: Conn,
+    pub(crate) token: CancellationToken,
+    pub(crate) period: Duration,
+}
+
+impl PruneItems {
+    pub(crate) async fn run(self) {
+        let mut interval = tokio::time::interval(self.period);
+
+        loop {
+            tokio::select! {
+                _ = self.token.cancelled() => {
+                    break;
+                }
+
+                _ = interval.tick() => {
+                    if let Err(err) = self.conn.prune(false).await {
+                        tracing::error!("failed to prune items: {err}");
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,460 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use chrono::{DateTime, Datelike, Duration, DurationRound, Timelike, Utc};
+
+use crate::db::types::Feed;
+
+/// Upper bound on the delay added to spread out feeds whose schedules line up
+const MAX_JITTER: Duration = Duration::minutes(5);
+
+/// Polling interval, in minutes, of feeds a websub hub pushes updates for
+const PUSHED_INTERVAL: u32 = 6 * 60;
+
+/// Window overdue feeds are spread across when many are due at once
+const CATCH_UP_WINDOW: Duration = Duration::minutes(2);
+
+/// Largest gap between two overdue feeds
+const CATCH_UP_SPACING: Duration = Duration::seconds(5);
+
+/// Longest the scheduler sleeps before looking at the wall clock again
+pub(crate) const MAX_SLEEP: Duration = Duration::seconds(60);
+
+/// Difference between wall clock and monotonic time treated as a jump
+const JUMP_THRESHOLD: Duration = Duration::seconds(30);
+
+/// How long a dispatched feed is held back if its fetch never reports in
+const IN_FLIGHT_TIMEOUT: Duration = Duration::minutes(15);
+
+/// Source of time for the scheduler
+pub(crate) trait Clock {
+    /// wall clock time, which may jump on suspend or when the clock is set
+    fn now(&self) -> DateTime<Utc>;
+
+    /// time that only moves forward, used to notice wall clock jumps
+    fn monotonic(&self) -> std::time::Duration;
+}
+
+pub(crate) struct SystemClock {
+    start: std::time::Instant,
+}
+
+/// Decides which feeds to fetch and when to look again
+pub(crate) struct Scheduler<C> {
+    clock: C,
+    /// feeds handed out, with the last fetch they had and when they were handed out
+    in_flight: HashMap<String, (DateTime<Utc>, DateTime<Utc>)>,
+    /// times given to overdue feeds so they don't all fire at once
+    slots: HashMap<String, DateTime<Utc>>,
+    last: Option<(DateTime<Utc>, std::time::Duration)>,
+}
+
+pub(crate) struct Plan {
+    pub(crate) due: Vec<Feed>,
+    /// when to plan again, at most `MAX_SLEEP` away
+    pub(crate) wake: DateTime<Utc>,
+    /// how far the wall clock moved apart from monotonic time since the last plan
+    pub(crate) jumped: Option<Duration>,
+    /// feeds past their next fetch left waiting for a catch up slot
+    pub(crate) overdue: usize,
+}
+
+impl SystemClock {
+    pub(crate) fn new() -> SystemClock {
+        SystemClock {
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn monotonic(&self) -> std::time::Duration {
+        self.start.elapsed()
+    }
+}
+
+impl<C: Clock> Scheduler<C> {
+    pub(crate) fn new(clock: C) -> Scheduler<C> {
+        Scheduler {
+            clock,
+            in_flight: HashMap::new(),
+            slots: HashMap::new(),
+            last: None,
+        }
+    }
+
+    pub(crate) fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    pub(crate) fn plan(&mut self, feeds: &[Feed]) -> Plan {
+        let now = self.clock.now();
+        let mono = self.clock.monotonic();
+
+        let jumped = self.last.and_then(|(wall, last_mono)| {
+            let mono = Duration::from_std(mono.saturating_sub(last_mono)).ok()?;
+            let drift = (now - wall) - mono;
+
+            (drift.abs() > JUMP_THRESHOLD).then_some(drift)
+        });
+        self.last = Some((now, mono));
+
+        if jumped.is_some() {
+            self.slots.clear();
+        }
+
+        // a feed is back once its fetch touched last_fetch, or it has been gone too long
+        self.in_flight.retain(|url, (last_fetch, sent)| {
+            now - *sent < IN_FLIGHT_TIMEOUT
+                && feeds
+                    .iter()
+                    .any(|feed| &feed.url == url && feed.last_fetch == *last_fetch)
+        });
+
+        let mut pending: Vec<(DateTime<Utc>, &Feed)> = feeds
+            .iter()
+            .filter(|feed| feed.paused.is_none() && !self.in_flight.contains_key(&feed.url))
+            .map(|feed| (next_fetch(feed), feed))
+            .collect();
+        pending.sort_by_key(|(next, _)| *next);
+
+        self.slots
+            .retain(|url, _| pending.iter().any(|(_, feed)| &feed.url == url));
+
+        let overdue: Vec<&Feed> = pending
+            .iter()
+            .filter(|(next, feed)| *next <= now && !self.slots.contains_key(&feed.url))
+            .map(|(_, feed)| *feed)
+            .collect();
+
+        if !overdue.is_empty() {
+            let spacing = (CATCH_UP_WINDOW / overdue.len() as i32).min(CATCH_UP_SPACING);
+            let start = self.slots.values().max().copied().unwrap_or(now).max(now);
+
+            for (i, feed) in overdue.into_iter().enumerate() {
+                self.slots
+                    .insert(feed.url.clone(), start + spacing * i as i32);
+            }
+        }
+
+        let mut due = Vec::new();
+        let mut wake = now + MAX_SLEEP;
+        let mut overdue = 0;
+
+        for (next, feed) in pending {
+            let at = self.slots.get(&feed.url).copied().unwrap_or(next);
+
+            if at <= now {
+                self.slots.remove(&feed.url);
+                self.in_flight
+                    .insert(feed.url.clone(), (feed.last_fetch, now));
+                due.push(feed.clone());
+            } else {
+                wake = wake.min(at);
+                if next <= now {
+                    overdue += 1;
+                }
+            }
+        }
+
+        Plan {
+            due,
+            wake,
+            jumped,
+            overdue,
+        }
+    }
+}
+
+/// When a feed is next due, never sooner than the interval the feed itself asks for
+fn next_fetch(feed: &Feed) -> DateTime<Utc> {
+    let mut interval = feed.ttl.max(feed.min_interval.unwrap_or(0));
+    if feed.pushed {
+        interval = interval.max(PUSHED_INTERVAL);
+    }
+
+    let interval = Duration::minutes(interval.into());
+
+    let next = feed.last_fetch + interval + jitter(feed, interval);
+
+    skip(next, feed.skip_hours, feed.skip_days)
+}
+
+/// Up to a tenth of the interval, picked from the feed and its last fetch so it stays put between
+/// reloads but differs from feed to feed and fetch to fetch
+fn jitter(feed: &Feed, interval: Duration) -> Duration {
+    let max = (interval / 10).min(MAX_JITTER).num_milliseconds();
+    if max <= 0 {
+        return Duration::zero();
+    }
+
+    let mut hasher = DefaultHasher::new();
+    (&feed.url, feed.last_fetch.timestamp()).hash(&mut hasher);
+
+    Duration::milliseconds((hasher.finish() % max as u64) as i64)
+}
+
+/// Moves a fetch out of the hours and days the feed asks not to be polled in
+fn skip(mut next: DateTime<Utc>, hours: u32, days: u32) -> DateTime<Utc> {
+    if hours & 0xff_ffff == 0xff_ffff || days & 0x7f == 0x7f {
+        return next;
+    }
+
+    for _ in 0..24 * 7 {
+        let hour = next.hour();
+        let day = next.weekday().num_days_from_monday();
+
+        if hours & 1 << hour == 0 && days & 1 << day == 0 {
+            break;
+        }
+
+        next = next.duration_trunc(Duration::hours(1)).unwrap_or(next) + Duration::hours(1);
+    }
+
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /// A clock moved by hand, wall and monotonic time apart
+    #[derive(Clone)]
+    struct FakeClock {
+        time: Arc<Mutex<(DateTime<Utc>, std::time::Duration)>>,
+    }
+
+    impl FakeClock {
+        fn new(now: DateTime<Utc>) -> FakeClock {
+            FakeClock {
+                time: Arc::new(Mutex::new((now, std::time::Duration::ZERO))),
+            }
+        }
+
+        /// Moves both clocks forward, as time passing normally does
+        fn advance(&self, by: Duration) {
+            self.shift(by, by);
+        }
+
+        fn shift(&self, wall: Duration, mono: Duration) {
+            let mut time = self.time.lock().unwrap();
+            time.0 += wall;
+            time.1 += mono.to_std().unwrap();
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> DateTime<Utc> {
+            self.time.lock().unwrap().0
+        }
+
+        fn monotonic(&self) -> std::time::Duration {
+            self.time.lock().unwrap().1
+        }
+    }
+
+    /// A monday at noon
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap()
+    }
+
+    fn feed(url: &str, last_fetch: DateTime<Utc>) -> Feed {
+        Feed {
+            url: url.to_string(),
+            ttl: 10,
+            last_fetch,
+            tracking: 0,
+            unread: 0,
+            paused: None,
+            min_interval: None,
+            skip_hours: 0,
+            skip_days: 0,
+            pushed: false,
+        }
+    }
+
+    fn urls(plan: &Plan) -> Vec<&str> {
+        plan.due.iter().map(|feed| feed.url.as_str()).collect()
+    }
+
+    #[test]
+    fn spreads_overdue_feeds_into_slots() {
+        let clock = FakeClock::new(start());
+        let mut scheduler = Scheduler::new(clock.clone());
+        let feeds: Vec<Feed> = (0..5)
+            .map(|i| {
+                feed(
+                    &format!("https://example.com/{i}"),
+                    start() - Duration::days(1),
+                )
+            })
+            .collect();
+
+        let plan = scheduler.plan(&feeds);
+        assert_eq!(plan.due.len(), 1);
+        assert_eq!(plan.overdue, 4);
+        assert_eq!(plan.wake, start() + CATCH_UP_SPACING);
+
+        let mut fetched = plan.due.len();
+        for _ in 0..4 {
+            clock.advance(CATCH_UP_SPACING);
+            let plan = scheduler.plan(&feeds);
+            assert_eq!(plan.due.len(), 1);
+            fetched += 1;
+        }
+
+        assert_eq!(fetched, feeds.len());
+    }
+
+    #[test]
+    fn counts_overdue_feeds_until_their_slot() {
+        let clock = FakeClock::new(start());
+        let mut scheduler = Scheduler::new(clock.clone());
+        let mut feeds: Vec<Feed> = (0..3)
+            .map(|i| {
+                feed(
+                    &format!("https://example.com/{i}"),
+                    start() - Duration::hours(1),
+                )
+            })
+            .collect();
+        // neither due yet nor fetched while paused, so never overdue
+        feeds.push(feed("https://example.com/later", start()));
+        let mut paused = feed("https://example.com/paused", start() - Duration::hours(1));
+        paused.paused = Some("gone".to_string());
+        feeds.push(paused);
+
+        let plan = scheduler.plan(&feeds);
+        assert_eq!(plan.due.len(), 1);
+        assert_eq!(plan.overdue, 2);
+
+        // waiting on the next slot doesn't change the count
+        clock.advance(Duration::seconds(1));
+        assert_eq!(scheduler.plan(&feeds).overdue, 2);
+
+        clock.advance(CATCH_UP_SPACING);
+        assert_eq!(scheduler.plan(&feeds).overdue, 1);
+
+        clock.advance(CATCH_UP_SPACING);
+        let plan = scheduler.plan(&feeds);
+        assert_eq!(plan.due.len(), 1);
+        assert_eq!(plan.overdue, 0);
+    }
+
+    #[test]
+    fn detects_wall_clock_jumps() {
+        let clock = FakeClock::new(start());
+        let mut scheduler = Scheduler::new(clock.clone());
+
+        assert!(scheduler.plan(&[]).jumped.is_none());
+
+        clock.advance(Duration::minutes(5));
+        assert!(scheduler.plan(&[]).jumped.is_none());
+
+        // suspended for an hour, which monotonic time doesn't count
+        clock.shift(Duration::hours(1), Duration::seconds(1));
+        let jumped = scheduler.plan(&[]).jumped.unwrap();
+        assert!(jumped > Duration::minutes(59));
+
+        clock.shift(Duration::minutes(-10), Duration::seconds(1));
+        assert!(scheduler.plan(&[]).jumped.unwrap() < Duration::minutes(-9));
+    }
+
+    #[test]
+    fn holds_back_feeds_in_flight() {
+        let clock = FakeClock::new(start());
+        let mut scheduler = Scheduler::new(clock.clone());
+        let mut feeds = vec![feed("https://example.com/a", start() - Duration::hours(1))];
+
+        assert_eq!(urls(&scheduler.plan(&feeds)), ["https://example.com/a"]);
+
+        clock.advance(Duration::seconds(30));
+        assert!(scheduler.plan(&feeds).due.is_empty());
+
+        // the fetch reports in, so the feed is next due a ttl later
+        feeds[0].last_fetch = clock.now();
+        clock.advance(Duration::seconds(30));
+        assert!(scheduler.plan(&feeds).due.is_empty());
+
+        clock.advance(Duration::minutes(12));
+        assert_eq!(urls(&scheduler.plan(&feeds)), ["https://example.com/a"]);
+    }
+
+    #[test]
+    fn replans_feeds_whose_fetch_never_reports() {
+        let clock = FakeClock::new(start());
+        let mut scheduler = Scheduler::new(clock.clone());
+        let feeds = vec![feed("https://example.com/a", start() - Duration::hours(1))];
+
+        assert_eq!(scheduler.plan(&feeds).due.len(), 1);
+
+        clock.advance(IN_FLIGHT_TIMEOUT - Duration::seconds(1));
+        assert!(scheduler.plan(&feeds).due.is_empty());
+
+        clock.advance(Duration::seconds(2));
+        assert_eq!(scheduler.plan(&feeds).due.len(), 1);
+    }
+
+    #[test]
+    fn skips_hours_and_days() {
+        let monday = |h, m| Utc.with_ymd_and_hms(2024, 1, 1, h, m, 0).unwrap();
+        let saturday = Utc.with_ymd_and_hms(2024, 1, 6, 9, 30, 0).unwrap();
+        let hours = 1 << 10 | 1 << 11;
+        let weekend = 1 << 5 | 1 << 6;
+
+        assert_eq!(skip(monday(10, 30), hours, 0), monday(12, 0));
+        assert_eq!(skip(monday(9, 30), hours, 0), monday(9, 30));
+        assert_eq!(
+            skip(saturday, 0, weekend),
+            Utc.with_ymd_and_hms(2024, 1, 8, 0, 0, 0).unwrap()
+        );
+
+        // skipping everything would never fetch, so it's ignored
+        assert_eq!(skip(monday(10, 30), 0xff_ffff, 0), monday(10, 30));
+        assert_eq!(skip(saturday, 0, 0x7f), saturday);
+    }
+
+    #[test]
+    fn plans_around_skipped_hours() {
+        let clock = FakeClock::new(start());
+        let mut scheduler = Scheduler::new(clock.clone());
+        // next due at five past noon, which the feed asks to skip
+        let mut skipping = feed("https://example.com/a", start() - Duration::minutes(5));
+        skipping.skip_hours = 1 << 12;
+
+        assert!(scheduler.plan(&[skipping.clone()]).due.is_empty());
+
+        clock.advance(Duration::hours(1));
+        assert_eq!(scheduler.plan(&[skipping]).due.len(), 1);
+    }
+
+    #[test]
+    fn sleeps_at_most_max_sleep() {
+        let clock = FakeClock::new(start());
+        let mut scheduler = Scheduler::new(clock.clone());
+
+        assert_eq!(scheduler.plan(&[]).wake, start() + MAX_SLEEP);
+
+        let mut later = feed("https://example.com/a", start());
+        later.ttl = 6 * 60;
+        assert_eq!(scheduler.plan(&[later.clone()]).wake, start() + MAX_SLEEP);
+
+        // sooner than the cap, the feed wakes the scheduler itself
+        later.ttl = 0;
+        later.last_fetch = start() + Duration::seconds(10);
+        assert_eq!(
+            scheduler.plan(&[later]).wake,
+            start() + Duration::seconds(10)
+        );
+    }
+}
@@ -12,7 +12,7 @@ pub(crate) struct CheckSignals {
 
 impl CheckSignals {
     pub(crate) async fn run(self) {
-        let mut signals = Signals::new(&[Signal::Usr1, Signal::Int]).unwrap();
+        let mut signals = Signals::new([Signal::Usr1, Signal::Int]).unwrap();
 
         loop {
             let action = tokio::select! {
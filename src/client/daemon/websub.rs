@@ -0,0 +1,476 @@
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+
+use axum::{
+    Router,
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    routing::get,
+};
+use hmac::{Hmac, Mac, digest::KeyInit};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+use url::Url;
+
+use crate::{Feed, client::Client, db::types::Subscription};
+
+/// Lease asked of hubs, in seconds
+const LEASE: u32 = 7 * 24 * 60 * 60;
+
+/// Leases ending sooner than this are renewed
+const RENEW_MARGIN: chrono::Duration = chrono::Duration::days(1);
+
+/// How often to look for feeds to subscribe to or renew
+const SUBSCRIBE_PERIOD: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Clone)]
+pub(crate) struct WebSub {
+    pub(crate) listen: SocketAddr,
+    /// public url of the listener, each feed gets a callback beneath it
+    pub(crate) callback: Url,
+}
+
+/// Serves the callbacks hubs verify subscriptions with and push content to
+pub(crate) struct Callbacks {
+    pub(crate) client: Client,
+    pub(crate) listener: TcpListener,
+    pub(crate) path: String,
+    pub(crate) token: CancellationToken,
+}
+
+/// Subscribes to the hubs of tracked feeds and renews their leases
+pub(crate) struct Subscribe {
+    pub(crate) client: Client,
+    pub(crate) callback: Url,
+    pub(crate) token: CancellationToken,
+}
+
+impl WebSub {
+    fn callback(base: &Url, feed: i64) -> String {
+        format!("{}/{feed}", base.as_str().trim_end_matches('/'))
+    }
+}
+
+impl Callbacks {
+    pub(crate) async fn run(self) {
+        let route = format!("{}/{{feed}}", self.path.trim_end_matches('/'));
+        let app = Router::new()
+            .route(&route, get(verify).post(deliver))
+            .with_state(self.client);
+
+        if let Err(err) = axum::serve(self.listener, app)
+            .with_graceful_shutdown(self.token.cancelled_owned())
+            .await
+        {
+            tracing::error!("websub listener failed: {err}");
+        }
+    }
+}
+
+impl Subscribe {
+    pub(crate) async fn run(self) {
+        let mut interval = tokio::time::interval(SUBSCRIBE_PERIOD);
+
+        loop {
+            tokio::select! {
+                _ = self.token.cancelled() => {
+                    break;
+                }
+
+                _ = interval.tick() => {
+                    if let Err(err) = self.subscribe_due().await {
+                        tracing::error!("failed to check websub subscriptions: {err}");
+                    }
+                }
+            }
+        }
+    }
+
+    async fn subscribe_due(&self) -> crate::Result<()> {
+        let due = self.client.conn.due_subscriptions(RENEW_MARGIN).await?;
+
+        for sub in due {
+            if let Err(err) = self.subscribe(&sub).await {
+                tracing::warn!(hub = %sub.hub, feed = %sub.url, "failed to subscribe: {err}");
+                self.client
+                    .conn
+                    .subscription_denied(sub.feed, err.to_string())
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn subscribe(&self, sub: &Subscription) -> crate::Result<()> {
+        let mut secret = [0u8; 32];
+        getrandom::fill(&mut secret).map_err(|err| std::io::Error::other(err.to_string()))?;
+
+        // marked pending before asking, as hubs may verify before answering
+        let Some(secret) = self
+            .client
+            .conn
+            .subscription_requested(sub.feed, hex::encode(secret))
+            .await?
+        else {
+            return Ok(());
+        };
+
+        let resp = self
+            .client
+            .fetcher
+            .client
+            .post(&sub.hub)
+            .form(&[
+                ("hub.mode", "subscribe"),
+                ("hub.topic", &sub.topic),
+                ("hub.callback", &WebSub::callback(&self.callback, sub.feed)),
+                ("hub.lease_seconds", &LEASE.to_string()),
+                ("hub.secret", &secret),
+            ])
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            self.client
+                .conn
+                .subscription_denied(sub.feed, format!("hub answered {}", resp.status()))
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Answers a hub's verification of intent with its challenge when the request is expected
+async fn verify(
+    State(client): State<Client>,
+    Path(feed): Path<i64>,
+    Query(params): Query<HashMap<String, String>>,
+) -> (StatusCode, String) {
+    let (Some(mode), Some(topic)) = (params.get("hub.mode"), params.get("hub.topic")) else {
+        return (StatusCode::BAD_REQUEST, String::new());
+    };
+
+    // hubs needn't say, in which case the lease asked for is the best guess of when to renew
+    let lease = params
+        .get("hub.lease_seconds")
+        .and_then(|lease| lease.parse().ok())
+        .unwrap_or(LEASE);
+
+    let verified = client
+        .conn
+        .verify_subscription(
+            feed,
+            topic.clone(),
+            mode.clone(),
+            lease,
+            params.get("hub.reason").cloned(),
+        )
+        .await;
+
+    match verified {
+        Ok(true) => (
+            StatusCode::OK,
+            params.get("hub.challenge").cloned().unwrap_or_default(),
+        ),
+        Ok(false) => (StatusCode::NOT_FOUND, String::new()),
+        Err(err) => {
+            tracing::error!(%feed, "failed to verify websub {mode}: {err}");
+            (StatusCode::INTERNAL_SERVER_ERROR, String::new())
+        }
+    }
+}
+
+/// Takes content pushed by a hub through the same pipeline as a fetch
+async fn deliver(
+    State(client): State<Client>,
+    Path(feed): Path<i64>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let sub = match client.conn.subscription(feed).await {
+        Ok(Some(sub)) => sub,
+        Ok(None) => return StatusCode::GONE,
+        Err(err) => {
+            tracing::error!(%feed, "failed to look up websub subscription: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    };
+
+    let signature = headers
+        .get("x-hub-signature")
+        .and_then(|value| value.to_str().ok());
+
+    // bad signatures are acknowledged so the hub doesn't retry, but dropped
+    if let Some(secret) = &sub.secret
+        && !signed(secret.as_bytes(), signature, &body)
+    {
+        tracing::warn!(feed = %sub.url, "dropping websub delivery with a bad signature");
+        return StatusCode::ACCEPTED;
+    }
+
+    let span = tracing::info_span!("push", feed = %sub.url);
+    let delivery = async move {
+        let res = match Feed::parse(&body) {
+            Ok(mut feed) => {
+                client.resolve(&sub.url, &mut feed);
+                client.ingest(sub.url.clone(), feed).await.map(|_| ())
+            }
+            Err(err) => Err(err),
+        };
+
+        if let Err(err) = res {
+            tracing::error!("failed to process websub delivery: {err}");
+        }
+    };
+    tokio::spawn(delivery.instrument(span));
+
+    StatusCode::ACCEPTED
+}
+
+/// Checks an `X-Hub-Signature` header of the form `method=hexdigest`
+fn signed(secret: &[u8], signature: Option<&str>, body: &[u8]) -> bool {
+    let Some((method, digest)) = signature.and_then(|sig| sig.split_once('=')) else {
+        return false;
+    };
+
+    let Ok(digest) = hex::decode(digest) else {
+        return false;
+    };
+
+    match method {
+        "sha1" => verify_mac::<Hmac<sha1::Sha1>>(secret, body, &digest),
+        "sha256" => verify_mac::<Hmac<sha2::Sha256>>(secret, body, &digest),
+        "sha384" => verify_mac::<Hmac<sha2::Sha384>>(secret, body, &digest),
+        "sha512" => verify_mac::<Hmac<sha2::Sha512>>(secret, body, &digest),
+        _ => false,
+    }
+}
+
+fn verify_mac<M: Mac + KeyInit>(secret: &[u8], body: &[u8], digest: &[u8]) -> bool {
+    let Ok(mut mac) = <M as KeyInit>::new_from_slice(secret) else {
+        return false;
+    };
+
+    mac.update(body);
+    mac.verify_slice(digest).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{Form, response::IntoResponse, routing::post};
+
+    use super::*;
+    use crate::{ItemFilter, RequestOptions, testing::Scratch};
+
+    const INIT: &str = "return { process = function(item, feed) end }";
+
+    /// Subscription requests the stand-in hub got
+    type Requests = Arc<Mutex<Vec<HashMap<String, String>>>>;
+
+    fn atom(base: &str, entries: &[&str]) -> String {
+        let entries: String = entries
+            .iter()
+            .map(|id| {
+                format!(
+                    "<entry><id>{id}</id><title>{id}</title><updated>2024-01-01T00:00:00Z</updated></entry>"
+                )
+            })
+            .collect();
+
+        format!(
+            r#"<?xml version="1.0"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+              <title>Pushed</title>
+              <id>urn:pushed</id>
+              <updated>2024-01-01T00:00:00Z</updated>
+              <link rel="hub" href="{base}/hub"/>
+              <link rel="self" href="{base}/feed.xml"/>
+              {entries}
+            </feed>"#
+        )
+    }
+
+    /// Serves a feed advertising itself as the hub, remembering the subscription requests
+    async fn hub() -> (String, Requests) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Requests::default();
+
+        let feed = atom(&base, &["first"]);
+        let app = Router::new()
+            .route(
+                "/feed.xml",
+                get(move || async move { ([("content-type", "application/atom+xml")], feed) }),
+            )
+            .route(
+                "/hub",
+                post(
+                    |State(requests): State<Requests>,
+                     Form(form): Form<HashMap<String, String>>| async move {
+                        requests.lock().unwrap().push(form);
+                        StatusCode::ACCEPTED.into_response()
+                    },
+                ),
+            )
+            .with_state(requests.clone());
+
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        (base, requests)
+    }
+
+    fn sign(secret: &str, body: &str) -> String {
+        let mut mac = <Hmac<sha2::Sha256> as KeyInit>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body.as_bytes());
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    async fn guids(client: &Client) -> Vec<String> {
+        let items = client.items(ItemFilter::default()).await.unwrap();
+        items.into_iter().map(|item| item.guid).collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn subscribes_verifies_and_takes_deliveries() {
+        let scratch = Scratch::new();
+        let client = scratch.client(INIT, 1).await;
+        let (base, requests) = hub().await;
+        let topic = format!("{base}/feed.xml");
+
+        client
+            .track(Url::parse(&topic).unwrap(), None, RequestOptions::default())
+            .await
+            .unwrap();
+
+        let token = CancellationToken::new();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let callback =
+            Url::parse(&format!("http://{}/websub", listener.local_addr().unwrap())).unwrap();
+
+        let callbacks = Callbacks {
+            client: client.clone(),
+            listener,
+            path: callback.path().to_string(),
+            token: token.clone(),
+        };
+        tokio::spawn(callbacks.run());
+
+        Subscribe {
+            client: client.clone(),
+            callback: callback.clone(),
+            token: token.clone(),
+        }
+        .subscribe_due()
+        .await
+        .unwrap();
+
+        let request = requests.lock().unwrap().pop().unwrap();
+        assert_eq!(request["hub.mode"], "subscribe");
+        assert_eq!(request["hub.topic"], topic);
+        assert_eq!(request["hub.lease_seconds"], LEASE.to_string());
+        let endpoint = request["hub.callback"].clone();
+        let secret = request["hub.secret"].clone();
+        assert!(endpoint.starts_with(callback.as_str()));
+
+        let http = reqwest::Client::new();
+
+        // the hub leaves out the lease, so the one asked for is assumed
+        let resp = http
+            .get(&endpoint)
+            .query(&[
+                ("hub.mode", "subscribe"),
+                ("hub.topic", &topic),
+                ("hub.challenge", "c4ll3ng3"),
+            ])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.text().await.unwrap(), "c4ll3ng3");
+        assert!(
+            client
+                .conn
+                .due_subscriptions(RENEW_MARGIN)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let resp = http
+            .get(&endpoint)
+            .query(&[
+                ("hub.mode", "subscribe"),
+                ("hub.topic", "https://elsewhere.example/feed"),
+                ("hub.challenge", "nope"),
+            ])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let signed = atom(&base, &["first", "second"]);
+        let resp = http
+            .post(&endpoint)
+            .header("x-hub-signature", sign(&secret, &signed))
+            .body(signed)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+        let mut delivered = false;
+        for _ in 0..50 {
+            if guids(&client).await.contains(&"second".to_string()) {
+                delivered = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert!(delivered);
+
+        // acknowledged so the hub doesn't retry, but dropped
+        let forged = atom(&base, &["third"]);
+        let resp = http
+            .post(&endpoint)
+            .header("x-hub-signature", sign("not the secret", &forged))
+            .body(forged)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!guids(&client).await.contains(&"third".to_string()));
+
+        token.cancel();
+    }
+
+    #[test]
+    fn checks_signatures() {
+        let body = b"<feed/>";
+        let sha256 = sign("secret", "<feed/>");
+
+        let mut mac = <Hmac<sha1::Sha1> as KeyInit>::new_from_slice(b"secret").unwrap();
+        mac.update(body);
+        let sha1 = format!("sha1={}", hex::encode(mac.finalize().into_bytes()));
+
+        assert!(signed(b"secret", Some(&sha256), body));
+        assert!(signed(b"secret", Some(&sha1), body));
+
+        assert!(!signed(b"other", Some(&sha256), body));
+        assert!(!signed(b"secret", Some(&sha256), b"<feed></feed>"));
+        assert!(!signed(b"secret", None, body));
+        assert!(!signed(b"secret", Some("sha256"), body));
+        assert!(!signed(b"secret", Some("sha256=zz"), body));
+        assert!(!signed(
+            b"secret",
+            Some(&sha256.replace("sha256", "md5")),
+            body
+        ));
+    }
+}
@@ -1,12 +1,19 @@
-use crate::db::Conn;
+use std::sync::Arc;
+
+use crate::db::{Conn, Target};
 use chrono::Utc;
 use url::Url;
 
 use crate::{
-    FeedItem, Result,
+    FeedItem, Fixture, Mismatch, Outcome, Result, Spec,
     client::daemon::Daemon,
-    feed::{Feed, FeedMeta},
-    interp::{Interp, Program},
+    db::types::{
+        Health, Item, ItemFilter, Mark, RequestOptions, Retention, SearchHit, SearchQuery,
+    },
+    feed::{Canonical, Feed, FeedError, FeedMeta},
+    fetcher::{Candidate, Source},
+    interp::{Alert, Interp, Mode, Program, Recorded},
+    metrics::Metrics,
     runtime::Runtime,
 };
 
@@ -14,11 +21,18 @@ mod builder;
 mod daemon;
 
 pub use builder::ClientBuilder;
+pub use daemon::FetchLimits;
+
+const DEFAULT_TTL: u32 = 60;
 
+#[derive(Clone)]
 pub struct Client {
     runtime: Runtime,
     conn: Conn,
     fetcher: crate::fetcher::Fetcher,
+    canonical: Arc<Canonical>,
+    mode: Mode,
+    metrics: Metrics,
 }
 
 impl Client {
@@ -26,17 +40,61 @@ impl Client {
         ClientBuilder::default()
     }
 
-    pub async fn fetch_items(&self, url: Url) -> Result<Feed> {
-        self.fetcher.fetch_items(url).await
+    pub async fn fetch_items(&self, source: Source) -> Result<Feed> {
+        let endpoint = source.to_string();
+
+        let mut feed = match source {
+            Source::Url(url) => {
+                let options = self
+                    .request_options(&url, RequestOptions::default())
+                    .await?;
+                self.fetch_url(url, &options).await?
+            }
+            source => {
+                let mut feed = self.fetcher.fetch_source(source).await?;
+                feed.resolve(None, &self.canonical);
+                feed
+            }
+        };
+
+        self.find_duplicates(&endpoint, &mut feed.items).await?;
+        Ok(feed)
+    }
+
+    pub async fn discover(&self, url: Url, options: RequestOptions) -> Result<Vec<Candidate>> {
+        if url.scheme() == "lua" {
+            let feed = self.runtime.source(url.clone()).await?;
+            return Ok(vec![Candidate {
+                url,
+                title: feed.meta.title,
+                media_type: None,
+            }]);
+        }
+
+        let options = self.request_options(&url, options).await?;
+        self.fetcher.discover(url, &options).await
     }
 
-    pub async fn eval(&self, feed: Feed) -> Result<(FeedMeta, Vec<(FeedItem, Program)>)> {
+    /// Evaluates every item of the feed at `url`, an item the script fails on getting an empty
+    /// program
+    pub async fn eval(
+        &self,
+        url: &str,
+        feed: Feed,
+    ) -> Result<(FeedMeta, Vec<(FeedItem, Program)>)> {
+        let progs = self.programs(url, &feed.meta, &feed.items).await;
+
         let mut res = Vec::new();
-        for item in feed.items {
-            let prog = self
-                .runtime
-                .process(feed.meta.clone(), item.clone())
-                .await?;
+        for (item, prog) in feed.items.into_iter().zip(progs) {
+            // a script failing on one item shouldn't hold back the rest of the feed
+            let prog = match prog {
+                Ok(prog) => prog,
+                Err(crate::Error::Eval(err)) => {
+                    tracing::warn!(item = %item.id, "failed to process: {err}");
+                    Program::default()
+                }
+                Err(err) => return Err(err),
+            };
 
             res.push((item, prog));
         }
@@ -44,21 +102,221 @@ impl Client {
         Ok((feed.meta, res))
     }
 
+    /// Runs an item's program the way the client's mode says to
+    pub async fn run(
+        &self,
+        url: String,
+        meta: &FeedMeta,
+        item: &FeedItem,
+        prog: &Program,
+    ) -> Result<()> {
+        self.interp(url).run(meta, item, prog).await
+    }
+
+    /// Runs the programs saved in record mode, or evaluates the saved items again with the
+    /// current config when `reeval` is set.
+    ///
+    /// Returns how many items were replayed.
+    pub async fn replay(&self, path: &std::path::Path, reeval: bool) -> Result<usize> {
+        let body = tokio::fs::read_to_string(path).await?;
+        let mut count = 0;
+
+        for (n, line) in body.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let recorded: Recorded = serde_json::from_str(line).map_err(|err| {
+                crate::Error::Replay(format!("{}:{}: {err}", path.display(), n + 1))
+            })?;
+
+            let prog = if reeval {
+                match self
+                    .runtime
+                    .process(
+                        recorded.url.clone(),
+                        recorded.meta.clone(),
+                        recorded.item.clone(),
+                    )
+                    .await
+                {
+                    Ok(prog) => prog,
+                    Err(crate::Error::Eval(err)) => {
+                        tracing::warn!(item = %recorded.item.id, "failed to process: {err}");
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                }
+            } else {
+                recorded.program
+            };
+
+            self.interp(recorded.url)
+                .run(&recorded.meta, &recorded.item, &prog)
+                .await?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Runs a spec case through the runtime, comparing programs instead of running them
+    pub async fn check(&self, spec: &Spec) -> Result<Outcome> {
+        // an inline body comes from nowhere, like a feed read from stdin by `cynd eval`
+        let (url, mut feed) = match &spec.fixture {
+            Fixture::File(path) => {
+                let path = std::path::absolute(path)?;
+                let url = Url::from_file_path(&path)
+                    .map_err(|_| crate::Error::InvalidPath(path.display().to_string()))?;
+
+                let feed = self
+                    .fetch_url(url.clone(), &RequestOptions::default())
+                    .await?;
+                (url.to_string(), feed)
+            }
+            Fixture::Body(body) => {
+                let mut feed = Feed::parse(body.as_bytes())?;
+                feed.resolve(None, &self.canonical);
+                (Source::Stdin.to_string(), feed)
+            }
+        };
+
+        spec.meta.apply(&mut feed.meta);
+
+        let mut outcome = Outcome::default();
+
+        if let Some(ids) = &spec.items {
+            feed.items.retain(|item| ids.contains(&item.id));
+        }
+
+        let ids = spec.items.iter().flatten().chain(spec.expect.keys());
+        for id in ids {
+            if !feed.items.iter().any(|item| &item.id == id) && !outcome.missing.contains(id) {
+                outcome.missing.push(id.clone());
+            }
+        }
+
+        let progs = self.programs(&url, &feed.meta, &feed.items).await;
+        for (item, prog) in feed.items.into_iter().zip(progs) {
+            let (prog, error) = match prog {
+                Ok(prog) => (prog, None),
+                Err(crate::Error::Eval(err)) => (Program::default(), Some(err)),
+                Err(err) => return Err(err),
+            };
+
+            let diff = spec.check(&item.id, &prog);
+            if diff.is_some() || error.is_some() {
+                outcome.mismatches.push(Mismatch {
+                    id: item.id,
+                    title: item.title,
+                    error,
+                    diff: diff.unwrap_or_default(),
+                });
+            }
+        }
+
+        Ok(outcome)
+    }
+
+    /// Queues every item at once for the runtime workers, the programs coming back in order
+    async fn programs(
+        &self,
+        url: &str,
+        meta: &FeedMeta,
+        items: &[FeedItem],
+    ) -> Vec<Result<Program>> {
+        futures::future::join_all(items.iter().map(|item| {
+            self.runtime
+                .process(url.to_string(), meta.clone(), item.clone())
+        }))
+        .await
+    }
+
+    pub async fn list(&self) -> Result<Vec<crate::TrackedFeed>> {
+        self.conn.list().await
+    }
+
+    pub async fn items(&self, filter: ItemFilter) -> Result<Vec<Item>> {
+        self.conn.items(filter).await
+    }
+
+    pub async fn mark(&self, id: i64, mark: Mark) -> Result<bool> {
+        self.conn.mark(Target::Id(id), mark).await
+    }
+
+    pub async fn search(&self, query: SearchQuery) -> Result<Vec<SearchHit>> {
+        self.conn.search(query).await
+    }
+
+    pub async fn reindex(&self) -> Result<usize> {
+        self.conn.reindex().await
+    }
+
+    pub async fn retention(&self, feed: Option<Url>) -> Result<Option<Retention>> {
+        self.conn.retention(feed.map(|url| url.to_string())).await
+    }
+
+    pub async fn set_retention(
+        &self,
+        feed: Option<Url>,
+        retention: Retention,
+        clear: bool,
+    ) -> Result<bool> {
+        self.conn
+            .set_retention(feed.map(|url| url.to_string()), retention, clear)
+            .await
+    }
+
+    pub async fn prune(&self, dry_run: bool) -> Result<Vec<Item>> {
+        self.conn.prune(dry_run).await
+    }
+
+    pub async fn health(&self) -> Result<Vec<Health>> {
+        self.conn.health().await
+    }
+
     pub async fn untrack(&self, url: Url, purge: bool) -> crate::Result<()> {
         self.conn.untrack(url.to_string(), purge).await?;
         Ok(())
     }
 
-    pub async fn track(&self, url: Url, ttl: Option<u32>) -> crate::Result<()> {
+    /// Tracks a feed, keeping the request options it already has unless new ones are given.
+    ///
+    /// A dry run or recording only previews the programs of the feed's items, storing nothing.
+    #[tracing::instrument(skip_all, fields(feed = %url))]
+    pub async fn track(
+        &self,
+        url: Url,
+        ttl: Option<u32>,
+        options: RequestOptions,
+    ) -> crate::Result<()> {
         let endpoint = url.to_string();
-        let feed = self.fetch_items(url).await?;
+        let options = self.request_options(&url, options).await?;
+        let mut feed = self.fetch_url(url, &options).await?;
+        let ttl = ttl.or(feed.meta.ttl).unwrap_or(DEFAULT_TTL);
 
-        self.conn.track(endpoint, Utc::now()).await?;
-        let (meta, instructions) = self.eval(feed).await?;
+        if matches!(self.mode, Mode::Execute) {
+            self.conn.insert(None, endpoint.clone(), ttl).await?;
+            self.conn
+                .set_request_options(endpoint.clone(), options)
+                .await?;
+            self.conn
+                .set_hints(endpoint.clone(), feed.meta.ttl, feed.meta.hints.clone())
+                .await?;
+            self.conn
+                .note_hub(endpoint.clone(), feed.meta.hub(&endpoint))
+                .await?;
+            self.conn.track(endpoint.clone(), Utc::now()).await?;
+            self.conn.resume(endpoint.clone()).await?;
+            self.conn.store(endpoint.clone(), &feed.items).await?;
+        }
 
-        let interp = Interp {};
+        self.find_duplicates(&endpoint, &mut feed.items).await?;
+        let (meta, instructions) = self.eval(&endpoint, feed).await?;
+
+        let interp = self.interp(endpoint);
         for (item, prog) in instructions {
-            interp.run(&meta, &item, &prog)?;
+            interp.run(&meta, &item, &prog).await?;
         }
 
         Ok(())
@@ -67,4 +325,180 @@ impl Client {
     pub fn daemon(self) -> Daemon {
         Daemon::new(self)
     }
+
+    pub(crate) async fn report(&self, error: FeedError) -> Result<()> {
+        let prog = match self.runtime.feed_error(error.clone()).await? {
+            Some(prog) => prog,
+            None if error.paused || error.moved.is_some() => Program {
+                instructions: vec![
+                    Alert {
+                        summary: Some(
+                            if error.paused {
+                                "Cynd feed paused"
+                            } else {
+                                "Cynd feed moved"
+                            }
+                            .to_string(),
+                        ),
+                        message: None,
+                    }
+                    .into(),
+                ],
+            },
+            None => return Ok(()),
+        };
+
+        self.interp(error.url.clone())
+            .run_error(&error, &prog)
+            .await
+    }
+
+    /// Stores a feed's items, then evaluates and runs the program of each one not seen before.
+    ///
+    /// Returns how many items were new.
+    pub(crate) async fn ingest(&self, url: String, feed: Feed) -> Result<usize> {
+        let fresh = self.conn.store(url.clone(), &feed.items).await?;
+        let total = feed.items.len();
+
+        let mut items: Vec<_> = feed
+            .items
+            .into_iter()
+            .zip(fresh)
+            .filter_map(|(item, fresh)| fresh.then_some(item))
+            .collect();
+        let count = items.len();
+        self.metrics.ingested(&url, total, count);
+
+        self.find_duplicates(&url, &mut items).await?;
+
+        let (meta, instructions) = self
+            .eval(
+                &url,
+                Feed {
+                    meta: feed.meta,
+                    items,
+                },
+            )
+            .await?;
+
+        let interp = self.interp(url);
+        for (item, prog) in instructions {
+            interp.run(&meta, &item, &prog).await?;
+        }
+
+        Ok(count)
+    }
+
+    /// The given request options, or the ones stored for the url when none are given
+    async fn request_options(&self, url: &Url, options: RequestOptions) -> Result<RequestOptions> {
+        if !options.is_empty() {
+            return Ok(options);
+        }
+
+        let stored = self.conn.request_options(url.to_string()).await?;
+        Ok(stored.unwrap_or_default())
+    }
+
+    /// Fetches a feed by url, running `lua://` urls through the sources registered in `init.lua`
+    pub(crate) async fn fetch_url(&self, url: Url, options: &RequestOptions) -> Result<Feed> {
+        let mut feed = if url.scheme() == "lua" {
+            self.runtime.source(url.clone()).await?
+        } else {
+            self.fetcher.fetch_items(url.clone(), options).await?
+        };
+
+        feed.resolve(Some(&url), &self.canonical);
+        Ok(feed)
+    }
+
+    /// Points each item at the first copy of it carried by another tracked feed
+    async fn find_duplicates(&self, url: &str, items: &mut [FeedItem]) -> Result<()> {
+        let found = self.conn.duplicates(url.to_string(), items).await?;
+        for (item, duplicate) in items.iter_mut().zip(found) {
+            item.duplicate_of = duplicate;
+        }
+
+        Ok(())
+    }
+
+    /// Resolves and canonicalizes the links of a feed delivered to us rather than fetched
+    pub(crate) fn resolve(&self, url: &str, feed: &mut Feed) {
+        feed.resolve(Url::parse(url).ok().as_ref(), &self.canonical);
+    }
+
+    pub(crate) fn interp(&self, url: String) -> Interp {
+        Interp {
+            conn: self.conn.clone(),
+            url,
+            fetcher: self.fetcher.clone(),
+            mode: self.mode.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Scratch;
+
+    const INIT: &str = r#"return {
+        process = function()
+            store.add("processed")
+            record()
+        end,
+    }"#;
+
+    const FEED: &str = r#"<rss version="2.0"><channel><title>Feed</title>
+        <item><guid>one</guid><title>One</title></item>
+        <item><guid>two</guid><title>Two</title></item>
+    </channel></rss>"#;
+
+    async fn track(scratch: &Scratch, mode: Mode) -> Client {
+        let url = Url::from_file_path(scratch.write("feed.xml", FEED)).unwrap();
+        let client = scratch.builder(INIT).mode(mode).build().await.unwrap();
+
+        client
+            .track(url, None, RequestOptions::default())
+            .await
+            .unwrap();
+
+        client
+    }
+
+    async fn processed(client: &Client) -> Option<String> {
+        client.conn.state("processed".to_string()).await.unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tracks_and_stores_items() {
+        let scratch = Scratch::new();
+        let client = track(&scratch, Mode::Execute).await;
+
+        assert_eq!(client.list().await.unwrap().len(), 1);
+        assert_eq!(client.items(ItemFilter::default()).await.unwrap().len(), 2);
+        assert_eq!(processed(&client).await.as_deref(), Some("2"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn previews_without_tracking() {
+        let scratch = Scratch::new();
+        let recording = scratch.dir("out").join("rec.jsonl");
+
+        for mode in [Mode::DryRun, Mode::Record(recording.clone())] {
+            let client = track(&scratch, mode).await;
+
+            assert!(client.list().await.unwrap().is_empty());
+            assert!(
+                client
+                    .items(ItemFilter::default())
+                    .await
+                    .unwrap()
+                    .is_empty()
+            );
+        }
+
+        let recorded = std::fs::read_to_string(recording).unwrap();
+        assert_eq!(recorded.lines().count(), 2);
+    }
 }
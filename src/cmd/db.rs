@@ -0,0 +1,103 @@
+use clap::{Parser, Subcommand};
+use cyndikator::{ClientBuilder, Retention};
+use url::Url;
+
+use crate::Runner;
+
+#[derive(Parser)]
+pub struct Db {
+    #[clap(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Subcommand)]
+enum Cmd {
+    /// Rebuild the full text search index
+    Reindex,
+
+    /// Delete items outside of the retention policy
+    Prune {
+        #[clap(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// Show or set the retention policy, globally or for a feed
+    Retention {
+        #[clap(short, long)]
+        feed: Option<Url>,
+
+        /// keep at most this many items per feed
+        #[clap(short, long)]
+        items: Option<u32>,
+
+        /// keep items newer than this many days
+        #[clap(short, long)]
+        days: Option<u32>,
+
+        /// reset the policy to the given values
+        #[clap(long)]
+        clear: bool,
+    },
+}
+
+impl Runner for Db {
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()> {
+        let client = client.migrate().build().await?;
+
+        match self.cmd {
+            Cmd::Reindex => {
+                let count = client.reindex().await?;
+                println!("reindexed {count} items");
+            }
+
+            Cmd::Prune { dry_run } => {
+                let pruned = client.prune(dry_run).await?;
+
+                for item in &pruned {
+                    println!(
+                        "{:>6} {} {}",
+                        item.id,
+                        item.published.unwrap_or(item.fetched).format("%Y-%m-%d"),
+                        item.title.as_deref().unwrap_or(item.guid.as_str()),
+                    );
+                }
+
+                if dry_run {
+                    println!("would prune {} items", pruned.len());
+                } else {
+                    println!("pruned {} items", pruned.len());
+                }
+            }
+
+            Cmd::Retention {
+                feed,
+                items,
+                days,
+                clear,
+            } => {
+                if items.is_some() || days.is_some() || clear {
+                    let retention = Retention { items, days };
+                    if !client.set_retention(feed.clone(), retention, clear).await? {
+                        eyre::bail!("feed is not tracked");
+                    }
+                }
+
+                let retention = client.retention(feed).await?.unwrap_or_default();
+                println!(
+                    "items: {}",
+                    retention
+                        .items
+                        .map_or("unlimited".to_string(), |n| n.to_string())
+                );
+                println!(
+                    "days: {}",
+                    retention
+                        .days
+                        .map_or("unlimited".to_string(), |n| n.to_string())
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+use clap::Parser;
+use cyndikator::ClientBuilder;
+use url::Url;
+
+use crate::{Runner, cmd::track::RequestArgs};
+
+#[derive(Parser)]
+pub struct Discover {
+    url: Url,
+
+    #[clap(short, long)]
+    json: bool,
+
+    #[clap(flatten)]
+    request: RequestArgs,
+}
+
+impl Runner for Discover {
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()> {
+        let candidates = client
+            .build()
+            .await?
+            .discover(self.url, self.request.options())
+            .await?;
+
+        if self.json {
+            serde_json::to_writer_pretty(std::io::stdout(), &candidates)?;
+            return Ok(());
+        }
+
+        for candidate in candidates {
+            match &candidate.title {
+                Some(title) => println!("{} ({title})", candidate.url),
+                None => println!("{}", candidate.url),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,10 +1,9 @@
 use std::path::PathBuf;
 
 use clap::Parser;
-use cyndikator::Client;
-use url::Url;
+use cyndikator::{ClientBuilder, Mode, Source};
 
-use crate::Runner;
+use crate::{Runner, cmd::run::ModeArgs};
 
 #[derive(Parser)]
 pub struct Eval {
@@ -14,16 +13,37 @@ pub struct Eval {
     #[clap(short, long, default_value = "false")]
     all: bool,
 
-    url: Url,
+    /// lua states evaluating items in parallel
+    #[clap(long, env = "CYND_WORKERS")]
+    workers: Option<usize>,
+
+    #[clap(flatten)]
+    mode: ModeArgs,
+
+    /// feed url, local path, `exec:<command>`, `lua://<source>` or - for stdin
+    source: Source,
 }
 
 impl Runner for Eval {
-    async fn run(self) -> eyre::Result<()> {
-        let client = Client::builder().runtime_opt(self.file).build().await?;
-        let feed = client.fetch_items(self.url).await?;
-
-        let (_, items) = client.eval(feed).await?;
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()> {
+        let mode = self.mode.mode();
+        let client = client
+            .runtime_opt(self.file)
+            .workers_opt(self.workers)
+            .mode(mode.clone())
+            .build()
+            .await?;
+        let endpoint = self.source.to_string();
+        let feed = client.fetch_items(self.source).await?;
+
+        let (meta, items) = client.eval(&endpoint, feed).await?;
         for (item, prog) in items {
+            // executing would be track without storing anything, so only the other modes run
+            if !matches!(mode, Mode::Execute) {
+                client.run(endpoint.clone(), &meta, &item, &prog).await?;
+                continue;
+            }
+
             if !prog.is_empty() || self.all {
                 println!(
                     "[{}]({})",
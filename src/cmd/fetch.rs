@@ -1,21 +1,17 @@
 use clap::Parser;
-use cyndikator::Client;
-use url::Url;
+use cyndikator::{ClientBuilder, Source};
 
 use crate::Runner;
 
 #[derive(Parser)]
 pub struct Fetch {
-    url: Url,
+    /// feed url, local path, `exec:<command>`, `lua://<source>` or - for stdin
+    source: Source,
 }
 
 impl Runner for Fetch {
-    async fn run(self) -> eyre::Result<()> {
-        let feed = Client::builder()
-            .build()
-            .await?
-            .fetch_items(self.url)
-            .await?;
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()> {
+        let feed = client.build().await?.fetch_items(self.source).await?;
 
         serde_json::to_writer_pretty(std::io::stdout(), &feed)?;
 
@@ -0,0 +1,80 @@
+use chrono::{Duration, Utc};
+use clap::Parser;
+use cyndikator::ClientBuilder;
+
+use crate::Runner;
+
+#[derive(Parser)]
+pub struct Health {
+    /// flag feeds with at least this many consecutive failed fetches
+    #[clap(short, long, default_value = "3")]
+    failures: u32,
+
+    /// flag feeds without new items for this many days
+    #[clap(short, long, default_value = "30")]
+    days: u32,
+
+    /// show healthy feeds as well
+    #[clap(short, long)]
+    all: bool,
+}
+
+impl Runner for Health {
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()> {
+        let health = client.migrate().build().await?.health().await?;
+
+        let stale = Utc::now() - Duration::days(self.days.into());
+
+        for feed in health {
+            let mut issues = Vec::new();
+
+            if let Some(reason) = &feed.paused {
+                issues.push(format!("paused: {reason}"));
+            }
+
+            if let Some((target, count)) = &feed.moving {
+                issues.push(format!("moving to {target} ({count} permanent redirects)"));
+            }
+
+            if feed.failures >= self.failures {
+                issues.push(format!("{} consecutive failures", feed.failures));
+            }
+
+            if feed.failures > 0 && feed.kind.as_deref() == Some("parse") {
+                issues.push("parse error".to_string());
+            }
+
+            match feed.last_new {
+                Some(last_new) if last_new < stale => issues.push(format!(
+                    "no new items since {}",
+                    last_new.format("%Y-%m-%d")
+                )),
+                None if feed.last_attempt.is_some() => issues.push("no items".to_string()),
+                _ => (),
+            }
+
+            if issues.is_empty() && !self.all {
+                continue;
+            }
+
+            let status = if issues.is_empty() {
+                "ok".to_string()
+            } else {
+                issues.join(", ")
+            };
+
+            println!("{}: {}", feed.url, status);
+
+            if feed.failures > 0
+                && let Some(error) = &feed.error
+            {
+                match feed.status {
+                    Some(code) => println!("  last error ({code}): {error}"),
+                    None => println!("  last error: {error}"),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
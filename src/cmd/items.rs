@@ -0,0 +1,70 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::Parser;
+use cyndikator::{ClientBuilder, ItemFilter, ItemState};
+use url::Url;
+
+use crate::Runner;
+
+#[derive(Parser)]
+pub struct Items {
+    /// unread, read, starred, archived or all
+    #[clap(short, long, default_value = "unread")]
+    state: ItemState,
+
+    #[clap(short, long)]
+    feed: Option<Url>,
+
+    #[clap(long, value_parser = parse_date)]
+    since: Option<DateTime<Utc>>,
+
+    #[clap(long, value_parser = parse_date)]
+    until: Option<DateTime<Utc>>,
+
+    #[clap(short = 'n', long)]
+    limit: Option<u32>,
+}
+
+impl Runner for Items {
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()> {
+        let filter = ItemFilter {
+            state: Some(self.state),
+            feed: self.feed.map(|url| url.to_string()),
+            since: self.since,
+            until: self.until,
+            limit: self.limit,
+        };
+
+        let items = client.migrate().build().await?.items(filter).await?;
+
+        for item in items {
+            let flags = format!(
+                "{}{}{}",
+                if item.read.is_none() { 'N' } else { ' ' },
+                if item.starred.is_some() { '*' } else { ' ' },
+                if item.archived.is_some() { 'A' } else { ' ' },
+            );
+
+            let date = item.published.unwrap_or(item.fetched);
+
+            println!(
+                "{:>6} {} {} {}",
+                item.id,
+                flags,
+                date.format("%Y-%m-%d"),
+                item.title.as_deref().unwrap_or(item.guid.as_str()),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) fn parse_date(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+        return Ok(date.to_utc());
+    }
+
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+        .map_err(|_| format!("invalid date {s} (expected YYYY-MM-DD or RFC 3339)"))
+}
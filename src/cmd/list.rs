@@ -0,0 +1,21 @@
+use cyndikator::ClientBuilder;
+
+use crate::Runner;
+
+#[derive(clap::Parser)]
+pub struct List {}
+
+impl Runner for List {
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()> {
+        let feeds = client.migrate().build().await?.list().await?;
+
+        for feed in feeds {
+            match feed.paused {
+                Some(reason) => println!("{:>5} {} (paused: {reason})", feed.unread, feed.url),
+                None => println!("{:>5} {}", feed.unread, feed.url),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,29 @@
+use clap::Parser;
+use cyndikator::{ClientBuilder, Mark as State};
+
+use crate::Runner;
+
+#[derive(Parser)]
+pub struct Mark {
+    id: i64,
+
+    /// read, unread, star, unstar, archive or unarchive
+    state: State,
+}
+
+impl Runner for Mark {
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()> {
+        let found = client
+            .migrate()
+            .build()
+            .await?
+            .mark(self.id, self.state)
+            .await?;
+
+        if !found {
+            eyre::bail!("no item with id {}", self.id);
+        }
+
+        Ok(())
+    }
+}
@@ -1,30 +1,91 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use cyndikator::ClientBuilder;
 
-use crate::Runner;
+use crate::{
+    Runner,
+    logging::{LogArgs, Sink},
+};
 
+mod db;
+mod discover;
 mod eval;
 mod fetch;
+mod health;
+mod items;
+mod list;
+mod mark;
+mod replay;
 mod run;
+mod search;
+mod test;
 mod track;
 mod untrack;
 
 #[derive(Parser)]
-pub enum Cli {
+pub struct Cli {
+    #[clap(flatten)]
+    pub log: LogArgs,
+
+    /// remove io, os.execute and the other ways out of the process before init.lua loads
+    #[clap(
+        long,
+        global = true,
+        env = "CYND_SANDBOX",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    sandbox: bool,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
     Fetch(fetch::Fetch),
     Eval(eval::Eval),
     Track(track::Track),
     Untrack(untrack::Untrack),
     Run(run::Run),
+    List(list::List),
+    Items(items::Items),
+    Mark(mark::Mark),
+    Search(search::Search),
+    Db(db::Db),
+    Health(health::Health),
+    Discover(discover::Discover),
+    Test(test::Test),
+    Replay(replay::Replay),
+}
+
+impl Cli {
+    /// Where logs go, only the daemon writing them anywhere but stderr
+    pub fn sink(&self) -> Sink {
+        match &self.command {
+            Command::Run(run) => run.sink(),
+            _ => Sink::Stderr,
+        }
+    }
 }
 
 impl Runner for Cli {
-    async fn run(self) -> eyre::Result<()> {
-        match self {
-            Cli::Eval(eval) => eval.run().await,
-            Cli::Fetch(fetch) => fetch.run().await,
-            Cli::Track(track) => track.run().await,
-            Cli::Untrack(untrack) => untrack.run().await,
-            Cli::Run(run) => run.run().await,
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()> {
+        let client = client.sandbox(self.sandbox);
+
+        match self.command {
+            Command::Eval(eval) => eval.run(client).await,
+            Command::Fetch(fetch) => fetch.run(client).await,
+            Command::Track(track) => track.run(client).await,
+            Command::Untrack(untrack) => untrack.run(client).await,
+            Command::Run(run) => run.run(client).await,
+            Command::List(list) => list.run(client).await,
+            Command::Items(items) => items.run(client).await,
+            Command::Mark(mark) => mark.run(client).await,
+            Command::Search(search) => search.run(client).await,
+            Command::Db(db) => db.run(client).await,
+            Command::Health(health) => health.run(client).await,
+            Command::Discover(discover) => discover.run(client).await,
+            Command::Test(test) => test.run(client).await,
+            Command::Replay(replay) => replay.run(client).await,
         }
     }
 }
@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use cyndikator::{ClientBuilder, Mode};
+
+use crate::Runner;
+
+#[derive(Parser)]
+pub struct Replay {
+    /// file written by `--record`
+    recording: PathBuf,
+
+    #[clap(short, long)]
+    file: Option<PathBuf>,
+
+    /// evaluate the recorded items again with the current init.lua instead of using the
+    /// recorded programs
+    #[clap(short, long)]
+    eval: bool,
+
+    /// log what each program would do instead of doing it
+    #[clap(long)]
+    dry_run: bool,
+}
+
+impl Runner for Replay {
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()> {
+        let mode = if self.dry_run {
+            Mode::DryRun
+        } else {
+            Mode::Execute
+        };
+
+        let count = client
+            .runtime_opt(self.file)
+            .migrate()
+            .mode(mode)
+            .build()
+            .await?
+            .replay(&self.recording, self.eval)
+            .await?;
+
+        eprintln!("replayed {count} items");
+
+        Ok(())
+    }
+}
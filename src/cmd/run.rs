@@ -1,19 +1,126 @@
-use cyndikator::Client;
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
-use crate::Runner;
+use cyndikator::{ClientBuilder, FetchLimits, Mode};
+use url::Url;
+
+use crate::{Runner, logging::Sink};
 
 #[derive(clap::Parser)]
-pub struct Run {}
+pub struct Run {
+    /// address to receive websub pushes on
+    #[clap(long, requires = "websub_url")]
+    websub_listen: Option<SocketAddr>,
+
+    /// public url hubs reach the websub listener at
+    #[clap(long, requires = "websub_listen")]
+    websub_url: Option<Url>,
+
+    /// address to serve prometheus metrics at /metrics on, such as 127.0.0.1:9464
+    #[clap(long, value_name = "ADDR")]
+    metrics_listen: Option<SocketAddr>,
+
+    /// lua states evaluating items in parallel
+    #[clap(long, env = "CYND_WORKERS")]
+    workers: Option<usize>,
+
+    /// fetches running at once across every host [default: 8]
+    #[clap(long)]
+    max_fetches: Option<usize>,
+
+    /// fetches running at once against a single host [default: 2]
+    #[clap(long)]
+    max_host_fetches: Option<usize>,
+
+    /// seconds between starting requests to the same host [default: 1]
+    #[clap(long, value_name = "SECONDS")]
+    host_interval: Option<f64>,
+
+    #[clap(flatten)]
+    mode: ModeArgs,
+
+    /// log to the systemd journal instead of stderr
+    #[clap(long, conflicts_with = "log_file")]
+    journald: bool,
+
+    /// append logs to a file instead of stderr
+    #[clap(long, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+}
+
+impl Run {
+    fn fetch_limits(&self) -> eyre::Result<FetchLimits> {
+        let mut limits = FetchLimits::default();
+
+        if let Some(fetches) = self.max_fetches {
+            limits.fetches = fetches;
+        }
+
+        if let Some(fetches) = self.max_host_fetches {
+            limits.host_fetches = fetches;
+        }
+
+        if let Some(seconds) = self.host_interval {
+            limits.host_interval = Duration::try_from_secs_f64(seconds)
+                .map_err(|_| eyre::eyre!("invalid host interval {seconds}"))?;
+        }
+
+        Ok(limits)
+    }
+
+    pub(crate) fn sink(&self) -> Sink {
+        match (&self.log_file, self.journald) {
+            (Some(path), _) => Sink::File(path.clone()),
+            (None, true) => Sink::Journald,
+            (None, false) => Sink::Stderr,
+        }
+    }
+}
+
+// What happens to the programs of new items, run by default.
+#[derive(clap::Args)]
+pub(crate) struct ModeArgs {
+    /// log what each program would do instead of doing it; `cynd run` still stores the items as
+    /// seen, so they won't come up again
+    #[clap(long, conflicts_with = "record")]
+    dry_run: bool,
+
+    /// append items and their programs to a file for `cynd replay` instead of running them;
+    /// `cynd run` still stores the items as seen
+    #[clap(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+}
+
+impl ModeArgs {
+    pub(crate) fn mode(self) -> Mode {
+        match (self.dry_run, self.record) {
+            (_, Some(path)) => Mode::Record(path),
+            (true, None) => Mode::DryRun,
+            (false, None) => Mode::Execute,
+        }
+    }
+}
 
 impl Runner for Run {
-    async fn run(self) -> eyre::Result<()> {
-        Client::builder()
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()> {
+        let limits = self.fetch_limits()?;
+        let mut daemon = client
             .migrate()
+            .workers_opt(self.workers)
+            .mode(self.mode.mode())
             .build()
             .await?
             .daemon()
-            .run()
-            .await?;
+            .fetch_limits(limits);
+
+        if let (Some(listen), Some(url)) = (self.websub_listen, self.websub_url) {
+            daemon = daemon.websub(listen, url);
+        }
+
+        if let Some(listen) = self.metrics_listen {
+            daemon = daemon.metrics(listen);
+        }
+
+        daemon.run().await?;
         Ok(())
     }
 }
@@ -0,0 +1,70 @@
+use std::io::IsTerminal;
+
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use cyndikator::{ClientBuilder, SearchQuery};
+use url::Url;
+
+use crate::{Runner, cmd::items::parse_date};
+
+#[derive(Parser)]
+pub struct Search {
+    query: String,
+
+    #[clap(short, long)]
+    feed: Option<Url>,
+
+    #[clap(long, value_parser = parse_date)]
+    since: Option<DateTime<Utc>>,
+
+    #[clap(long, value_parser = parse_date)]
+    until: Option<DateTime<Utc>>,
+
+    #[clap(short = 'n', long)]
+    limit: Option<u32>,
+
+    #[clap(short, long)]
+    json: bool,
+}
+
+impl Runner for Search {
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()> {
+        let highlight = if self.json {
+            ("<mark>", "</mark>")
+        } else if std::io::stdout().is_terminal() {
+            ("\x1b[1m", "\x1b[0m")
+        } else {
+            ("[", "]")
+        };
+
+        let query = SearchQuery {
+            query: self.query,
+            feed: self.feed.map(|url| url.to_string()),
+            since: self.since,
+            until: self.until,
+            limit: self.limit,
+            highlight: (highlight.0.to_string(), highlight.1.to_string()),
+        };
+
+        let hits = client.migrate().build().await?.search(query).await?;
+
+        if self.json {
+            serde_json::to_writer_pretty(std::io::stdout(), &hits)?;
+            return Ok(());
+        }
+
+        for hit in hits {
+            let date = hit.item.published.unwrap_or(hit.item.fetched);
+
+            println!(
+                "{:>6} {} {}",
+                hit.item.id,
+                date.format("%Y-%m-%d"),
+                hit.item.title.as_deref().unwrap_or(hit.item.guid.as_str()),
+            );
+            println!("       {}", hit.snippet.replace('\n', " "));
+        }
+
+        Ok(())
+    }
+}
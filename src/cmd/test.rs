@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use cyndikator::{ClientBuilder, Spec};
+
+use crate::Runner;
+
+#[derive(Parser)]
+pub struct Test {
+    #[clap(short, long)]
+    file: Option<PathBuf>,
+
+    /// spec files, every .lua file of the tests directory next to init.lua by default
+    specs: Vec<PathBuf>,
+}
+
+impl Runner for Test {
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()> {
+        let specs = if self.specs.is_empty() {
+            let dir = match &self.file {
+                Some(file) => file.parent().map(|dir| dir.join("tests")),
+                None => dirs::config_dir().map(|dir| dir.join("cyndikator").join("tests")),
+            };
+
+            let Some(dir) = dir else {
+                eyre::bail!("no tests directory, give the spec files to run");
+            };
+
+            let mut specs = Vec::new();
+            for entry in std::fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.extension().is_some_and(|ext| ext == "lua") {
+                    specs.push(path);
+                }
+            }
+
+            specs.sort();
+            specs
+        } else {
+            self.specs
+        };
+
+        // an in-memory database keeps scripts from touching the real store
+        let client = client
+            .runtime_opt(self.file)
+            .database(":memory:".into())
+            .migrate()
+            .build()
+            .await?;
+
+        let (mut passed, mut failed) = (0, 0);
+
+        for path in specs {
+            for spec in Spec::load(&path)? {
+                let outcome = client.check(&spec).await?;
+
+                if outcome.passed() {
+                    passed += 1;
+                    println!("ok   {}: {}", path.display(), spec.name);
+                    continue;
+                }
+
+                failed += 1;
+                println!("FAIL {}: {}", path.display(), spec.name);
+
+                for id in &outcome.missing {
+                    println!("  no item {id} in the fixture");
+                }
+
+                for mismatch in &outcome.mismatches {
+                    print!("{mismatch}");
+                }
+            }
+        }
+
+        println!("\n{passed} passed, {failed} failed");
+
+        if failed > 0 {
+            eyre::bail!("{failed} spec cases failed");
+        }
+
+        Ok(())
+    }
+}
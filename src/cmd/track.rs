@@ -1,26 +1,113 @@
+use std::io::{BufRead, IsTerminal, Write};
+
 use clap::Parser;
-use cyndikator::Client;
+use cyndikator::{Basic, Candidate, ClientBuilder, Header, RequestOptions, Secret, Source};
 use url::Url;
 
-use crate::Runner;
+use crate::{Runner, cmd::run::ModeArgs};
 
 #[derive(Parser)]
 pub struct Track {
-    url: Url,
+    /// feed or website url, local path, `exec:<command>` or `lua://<source>`
+    source: Source,
 
     #[clap(short, long)]
     ttl: Option<u32>,
+
+    /// track the first feed found instead of asking when a page links to several
+    #[clap(short, long)]
+    first: bool,
+
+    #[clap(flatten)]
+    request: RequestArgs,
+
+    #[clap(flatten)]
+    mode: ModeArgs,
+}
+
+// Request options, replacing the ones stored for the feed when any are given.
+#[derive(clap::Args)]
+pub(crate) struct RequestArgs {
+    /// extra header sent with each request, as `name: value`
+    #[clap(short = 'H', long = "header")]
+    headers: Vec<Header>,
+
+    /// basic auth as `user:<secret>`, secrets being `env:NAME` or `cred:KEY` from the credentials file
+    #[clap(long)]
+    basic: Option<Basic>,
+
+    /// secret holding a bearer token
+    #[clap(long)]
+    bearer: Option<Secret>,
+
+    /// secret holding cookies, as `name=value; ...`
+    #[clap(long = "cookie")]
+    cookies: Vec<Secret>,
+}
+
+impl RequestArgs {
+    pub(crate) fn options(self) -> RequestOptions {
+        RequestOptions {
+            headers: self.headers,
+            basic: self.basic,
+            bearer: self.bearer,
+            cookies: self.cookies,
+        }
+    }
 }
 
 impl Runner for Track {
-    async fn run(self) -> eyre::Result<()> {
-        Client::builder()
-            .migrate()
-            .build()
-            .await?
-            .track(self.url, self.ttl)
-            .await?;
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()> {
+        let Some(url) = self.source.into_url() else {
+            eyre::bail!("stdin can't be tracked, save the feed to a file and track its path");
+        };
+
+        let client = client.migrate().mode(self.mode.mode()).build().await?;
+
+        let options = self.request.options();
+        let mut candidates = client.discover(url, options.clone()).await?;
+        let url = match candidates.len() {
+            0 => eyre::bail!("no feeds found"),
+            1 => candidates.remove(0).url,
+            _ if self.first => candidates.remove(0).url,
+            _ => choose(candidates)?,
+        };
+
+        client.track(url, self.ttl, options).await?;
 
         Ok(())
     }
 }
+
+fn choose(candidates: Vec<Candidate>) -> eyre::Result<Url> {
+    if !std::io::stdin().is_terminal() {
+        let urls: Vec<String> = candidates.iter().map(|c| c.url.to_string()).collect();
+        eyre::bail!(
+            "found several feeds, track one of:\n  {}",
+            urls.join("\n  ")
+        );
+    }
+
+    for (i, candidate) in candidates.iter().enumerate() {
+        match &candidate.title {
+            Some(title) => println!("{:>3}) {} ({title})", i + 1, candidate.url),
+            None => println!("{:>3}) {}", i + 1, candidate.url),
+        }
+    }
+
+    loop {
+        print!("feed to track [1-{}]: ", candidates.len());
+        std::io::stdout().flush()?;
+
+        let mut line = String::new();
+        if std::io::stdin().lock().read_line(&mut line)? == 0 {
+            eyre::bail!("no feed chosen");
+        }
+
+        if let Ok(n) = line.trim().parse::<usize>()
+            && (1..=candidates.len()).contains(&n)
+        {
+            return Ok(candidates[n - 1].url.clone());
+        }
+    }
+}
@@ -1,24 +1,27 @@
 use clap::Parser;
-use cyndikator::Client;
-use url::Url;
+use cyndikator::{ClientBuilder, Source};
 
 use crate::Runner;
 
 #[derive(Parser)]
 pub struct Untrack {
-    url: Url,
+    source: Source,
 
     #[clap(short, long)]
     purge: bool,
 }
 
 impl Runner for Untrack {
-    async fn run(self) -> eyre::Result<()> {
-        Client::builder()
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()> {
+        let Some(url) = self.source.into_url() else {
+            eyre::bail!("stdin is never tracked");
+        };
+
+        client
             .migrate()
             .build()
             .await?
-            .untrack(self.url, self.purge)
+            .untrack(url, self.purge)
             .await?;
 
         Ok(())
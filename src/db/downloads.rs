@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, named_params};
+use tokio::sync::oneshot;
+
+use crate::db::{Operation, types::Download};
+
+pub struct GetDownload {
+    pub(crate) send: oneshot::Sender<Option<Download>>,
+    pub(crate) feed: String,
+    pub(crate) guid: String,
+    pub(crate) url: String,
+}
+
+pub struct RecordDownload {
+    pub(crate) send: oneshot::Sender<()>,
+    pub(crate) feed: String,
+    pub(crate) guid: String,
+    pub(crate) url: String,
+    pub(crate) download: Download,
+    pub(crate) time: DateTime<Utc>,
+}
+
+impl Operation for GetDownload {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let download = conn
+            .query_row(
+                r#"
+                select path, bytes, sha256 from downloads
+                inner join feeds on feeds.id = downloads.feed
+                where feeds.url = :feed and guid = :guid and downloads.url = :url
+                "#,
+                named_params! { ":feed": self.feed, ":guid": self.guid, ":url": self.url },
+                |row| {
+                    Ok(Download {
+                        path: row.get(0)?,
+                        bytes: row.get(1)?,
+                        sha256: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+
+        let _ = self.send.send(download);
+
+        Ok(())
+    }
+}
+
+impl Operation for RecordDownload {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        conn.execute(
+            r#"
+            insert into downloads (feed, guid, url, path, bytes, sha256, time)
+            select id, :guid, :url, :path, :bytes, :sha256, :time from feeds where feeds.url = :feed
+            on conflict (feed, guid, url) do update set
+              path = excluded.path,
+              bytes = excluded.bytes,
+              sha256 = excluded.sha256,
+              time = excluded.time
+            "#,
+            named_params! {
+                ":feed": self.feed,
+                ":guid": self.guid,
+                ":url": self.url,
+                ":path": self.download.path,
+                ":bytes": self.download.bytes,
+                ":sha256": self.download.sha256,
+                ":time": self.time,
+            },
+        )?;
+
+        let _ = self.send.send(());
+
+        Ok(())
+    }
+}
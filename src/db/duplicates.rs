@@ -0,0 +1,344 @@
+use rusqlite::{Connection, OptionalExtension, named_params};
+use sha2::{Digest, Sha256};
+use tokio::sync::oneshot;
+
+use crate::{FeedItem, db::Operation, feed::Duplicate};
+
+/// Titles shorter than this many words, like "Links" or "Weekly update", say too little to match on
+const MIN_TITLE_WORDS: usize = 4;
+
+/// Bodies shorter than this many words are too generic to hash, teasers and "read more" links
+const MIN_CONTENT_WORDS: usize = 50;
+
+/// What identifies an item across feeds: its canonical link, normalized title or content hash
+#[derive(Debug, Clone)]
+pub(crate) struct Fingerprint {
+    pub(crate) kind: &'static str,
+    pub(crate) value: String,
+}
+
+pub struct Find {
+    pub(crate) send: oneshot::Sender<Vec<Option<Duplicate>>>,
+    pub(crate) url: String,
+    pub(crate) items: Vec<Vec<Fingerprint>>,
+}
+
+pub(crate) fn fingerprints(item: &FeedItem, link: Option<&str>) -> Vec<Fingerprint> {
+    let mut prints = Vec::new();
+
+    if let Some(link) = link {
+        prints.push(Fingerprint {
+            kind: "link",
+            value: link.to_string(),
+        });
+    }
+
+    if let Some(title) = &item.title {
+        let words = words(title);
+        if words.len() >= MIN_TITLE_WORDS {
+            prints.push(Fingerprint {
+                kind: "title",
+                value: words.join(" "),
+            });
+        }
+    }
+
+    let words = words(&item.text());
+    if words.len() >= MIN_CONTENT_WORDS {
+        prints.push(Fingerprint {
+            kind: "content",
+            value: hex::encode(Sha256::digest(words.join(" "))),
+        });
+    }
+
+    prints
+}
+
+/// Lowercased words with punctuation dropped, so markup and typography changes still match
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Saves an item's fingerprints and links it to the first copy seen in another feed
+pub(crate) fn note(
+    conn: &Connection,
+    url: &str,
+    id: i64,
+    prints: &[Fingerprint],
+) -> crate::Result<()> {
+    let mut insert = conn.prepare_cached(
+        r#"
+        insert into fingerprints (item, kind, value) values (:item, :kind, :value)
+        on conflict (item, kind) do update set value = excluded.value
+        "#,
+    )?;
+
+    for print in prints {
+        insert.execute(named_params! {
+            ":item": id,
+            ":kind": print.kind,
+            ":value": print.value,
+        })?;
+    }
+
+    if let Some((original, _)) = original(conn, url, prints)? {
+        conn.execute(
+            r#"
+            insert into duplicates (item, original) values (:item, :original)
+            on conflict (item) do update set original = excluded.original
+            "#,
+            named_params! { ":item": id, ":original": original },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The earliest item from a feed other than `url` sharing any of the fingerprints
+fn original(
+    conn: &Connection,
+    url: &str,
+    prints: &[Fingerprint],
+) -> crate::Result<Option<(i64, Duplicate)>> {
+    let mut prep = conn.prepare_cached(
+        r#"
+        select items.id, feeds.url, items.guid, items.title, items.link, items.fetched
+        from fingerprints
+        inner join items on items.id = fingerprints.item
+        inner join feeds on feeds.id = items.feed
+        where fingerprints.kind = :kind and fingerprints.value = :value and feeds.url != :url
+        order by items.fetched, items.id
+        limit 1
+        "#,
+    )?;
+
+    let mut first: Option<(i64, Duplicate)> = None;
+
+    for print in prints {
+        let found = prep
+            .query_row(
+                named_params! { ":kind": print.kind, ":value": print.value, ":url": url },
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        Duplicate {
+                            feed: row.get(1)?,
+                            id: row.get(2)?,
+                            title: row.get(3)?,
+                            link: row.get(4)?,
+                            fetched: row.get(5)?,
+                        },
+                    ))
+                },
+            )
+            .optional()?;
+
+        if let Some(found) = found
+            && first
+                .as_ref()
+                .is_none_or(|(id, dup)| (found.1.fetched, found.0) < (dup.fetched, *id))
+        {
+            first = Some(found);
+        }
+    }
+
+    Ok(first)
+}
+
+impl Operation for Find {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let found = self
+            .items
+            .iter()
+            .map(|prints| Ok(original(conn, &self.url, prints)?.map(|(_, dup)| dup)))
+            .collect::<crate::Result<Vec<_>>>()?;
+
+        let _ = self.send.send(found);
+
+        Ok(())
+    }
+}
+
+/// Drops the fingerprints of an item and any duplicate links to or from it
+pub(crate) fn forget(conn: &Connection, id: i64) -> crate::Result<()> {
+    conn.execute(
+        "delete from fingerprints where item = :id",
+        named_params! { ":id": id },
+    )?;
+
+    conn.execute(
+        "delete from duplicates where item = :id or original = :id",
+        named_params! { ":id": id },
+    )?;
+
+    Ok(())
+}
+
+/// Drops the fingerprints and duplicate links of every item belonging to the feed
+pub(crate) fn forget_feed(conn: &Connection, url: &str) -> crate::Result<()> {
+    let items = r#"
+        (select items.id from items inner join feeds on feeds.id = items.feed where feeds.url = :url)
+    "#;
+
+    conn.execute(
+        &format!("delete from fingerprints where item in {items}"),
+        named_params! { ":url": url },
+    )?;
+
+    conn.execute(
+        &format!("delete from duplicates where item in {items} or original in {items}"),
+        named_params! { ":url": url },
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::{
+        db::items::{Row, Store},
+        feed::Content,
+        testing::item,
+    };
+
+    const BODY: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod \
+        tempor incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis \
+        nostrud exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat. Duis \
+        aute irure dolor in reprehenderit in voluptate velit esse cillum dolore eu fugiat \
+        nulla pariatur.";
+
+    fn kinds(prints: &[Fingerprint]) -> Vec<&str> {
+        prints.iter().map(|print| print.kind).collect()
+    }
+
+    fn value<'a>(prints: &'a [Fingerprint], kind: &str) -> Option<&'a str> {
+        prints
+            .iter()
+            .find(|print| print.kind == kind)
+            .map(|print| &print.value[..])
+    }
+
+    #[test]
+    fn skips_what_says_too_little() {
+        let mut bare = item("a");
+        bare.title = Some("Weekly links, vol. 3".to_string());
+        assert_eq!(kinds(&fingerprints(&bare, None)), ["title"]);
+
+        bare.title = Some("Weekly links".to_string());
+        bare.summary = Some("<p>Read more…</p>".to_string());
+        assert!(fingerprints(&bare, None).is_empty());
+
+        assert_eq!(
+            kinds(&fingerprints(&bare, Some("https://example.com/a"))),
+            ["link"]
+        );
+    }
+
+    #[test]
+    fn normalizes_titles_and_bodies() {
+        let mut one = item("a");
+        one.title = Some("The Quick, Brown  Fox!".to_string());
+        one.summary = Some(format!("<p>{BODY}</p>"));
+
+        let mut other = item("b");
+        other.title = Some("the quick brown fox".to_string());
+        other.content = Some(Content::Body(format!(
+            "<div><em>{}</em></div>",
+            BODY.to_uppercase()
+        )));
+
+        let one = fingerprints(&one, Some("https://example.com/a"));
+        let other = fingerprints(&other, None);
+
+        assert_eq!(kinds(&one), ["link", "title", "content"]);
+        assert_eq!(value(&one, "link"), Some("https://example.com/a"));
+        assert_eq!(value(&one, "title"), Some("the quick brown fox"));
+        assert_eq!(value(&one, "title"), value(&other, "title"));
+        assert_eq!(value(&one, "content"), value(&other, "content"));
+    }
+
+    fn store(conn: &Connection, url: &str, item: &FeedItem, link: &str, age: i64) {
+        let mut row = Row::from(item);
+        row.fingerprints = fingerprints(item, Some(link));
+
+        let (send, _) = oneshot::channel();
+        Store {
+            send,
+            url: url.to_string(),
+            rows: vec![row],
+            time: Utc::now() - Duration::minutes(age),
+        }
+        .perform(conn)
+        .unwrap();
+    }
+
+    fn find(conn: &Connection, url: &str, prints: Vec<Fingerprint>) -> Option<Duplicate> {
+        let (send, mut recv) = oneshot::channel();
+        Find {
+            send,
+            url: url.to_string(),
+            items: vec![prints],
+        }
+        .perform(conn)
+        .unwrap();
+
+        recv.try_recv().unwrap().pop().unwrap()
+    }
+
+    #[test]
+    fn finds_the_first_copy_in_other_feeds() {
+        let conn = crate::db::memory();
+        conn.execute(
+            "insert into feeds (id, url, ttl) values (2, 'https://example.org/feed', 60), (3, 'https://example.net/feed', 60)",
+            [],
+        )
+        .unwrap();
+
+        let mut first = item("first");
+        first.title = Some("An article syndicated everywhere".to_string());
+        store(
+            &conn,
+            "https://example.org/feed",
+            &first,
+            "https://a.example/1",
+            20,
+        );
+
+        let mut second = item("second");
+        second.title = first.title.clone();
+        store(
+            &conn,
+            "https://example.net/feed",
+            &second,
+            "https://b.example/1",
+            10,
+        );
+
+        let mut copy = item("copy");
+        copy.title = Some("An Article Syndicated Everywhere".to_string());
+        let prints = fingerprints(&copy, Some("https://c.example/1"));
+
+        let found = find(&conn, "https://example.com/feed", prints.clone()).unwrap();
+        assert_eq!(found.feed, "https://example.org/feed");
+        assert_eq!(found.id, "first");
+
+        let found = find(&conn, "https://example.org/feed", prints).unwrap();
+        assert_eq!(found.id, "second");
+
+        let mut other = item("other");
+        other.title = Some("Nothing like the others at all".to_string());
+        let prints = fingerprints(&other, Some("https://a.example/2"));
+        assert!(find(&conn, "https://example.com/feed", prints).is_none());
+
+        let linked: i64 = conn
+            .query_row("select count(*) from duplicates", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(linked, 1);
+    }
+}
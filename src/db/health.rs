@@ -0,0 +1,268 @@
+use rusqlite::fallible_iterator::FallibleIterator;
+use rusqlite::{Connection, named_params};
+use tokio::sync::oneshot;
+
+use crate::db::{
+    Operation,
+    types::{FetchEntry, Health, Streak},
+};
+
+const LOG_LENGTH: u32 = 200;
+
+pub struct LogFetch {
+    pub(crate) send: oneshot::Sender<Streak>,
+    pub(crate) url: String,
+    pub(crate) entry: FetchEntry,
+}
+
+pub struct Check {
+    pub(crate) send: oneshot::Sender<Vec<Health>>,
+}
+
+impl Operation for LogFetch {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let entry = self.entry;
+
+        conn.execute(
+            r#"
+            insert into fetch_log (feed, time, status, duration, bytes, items, new_items, kind, error)
+            select id, :time, :status, :duration, :bytes, :items, :new_items, :kind, :error
+            from feeds where feeds.url = :url
+            "#,
+            named_params! {
+                ":url": self.url,
+                ":time": entry.time,
+                ":status": entry.status,
+                ":duration": entry.duration,
+                ":bytes": entry.bytes,
+                ":items": entry.items,
+                ":new_items": entry.new_items,
+                ":kind": entry.kind,
+                ":error": entry.error,
+            },
+        )?;
+
+        conn.execute(
+            r#"
+            delete from fetch_log
+            where feed in (select id from feeds where feeds.url = :url)
+            and id not in (
+              select fetch_log.id from fetch_log inner join feeds on feeds.id = fetch_log.feed
+              where feeds.url = :url order by fetch_log.id desc limit :keep
+            )
+            "#,
+            named_params! {
+                ":url": self.url,
+                ":keep": LOG_LENGTH,
+            },
+        )?;
+
+        let failures = conn.query_row(
+            r#"
+            select count(*) from fetch_log inner join feeds on feeds.id = fetch_log.feed
+            where feeds.url = :url and error is not null
+            and fetch_log.id > coalesce(
+              (select max(ok.id) from fetch_log ok where ok.feed = feeds.id and ok.error is null), 0
+            )
+            "#,
+            named_params! { ":url": self.url },
+            |row| row.get(0),
+        )?;
+
+        let not_found = conn.query_row(
+            r#"
+            select count(*) from fetch_log inner join feeds on feeds.id = fetch_log.feed
+            where feeds.url = :url and status = 404
+            and fetch_log.id > coalesce(
+              (select max(other.id) from fetch_log other
+               where other.feed = feeds.id and other.status is not 404), 0
+            )
+            "#,
+            named_params! { ":url": self.url },
+            |row| row.get(0),
+        )?;
+
+        let _ = self.send.send(Streak {
+            failures,
+            not_found,
+        });
+
+        Ok(())
+    }
+}
+
+impl Operation for Check {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let mut prep = conn.prepare(
+            r#"
+            with latest as (
+              select * from fetch_log where id in (select max(id) from fetch_log group by feed)
+            )
+            select feeds.url, latest.time, latest.status, latest.kind, latest.error,
+              (select count(*) from fetch_log f
+               where f.feed = feeds.id and f.error is not null
+               and f.id > coalesce(
+                 (select max(ok.id) from fetch_log ok where ok.feed = feeds.id and ok.error is null), 0
+               )) failures,
+              coalesce(
+                (select max(time) from fetch_log f where f.feed = feeds.id and f.new_items > 0),
+                (select max(fetched) from items where items.feed = feeds.id)
+              ) last_new,
+              paused.reason, redirects.target, redirects.count
+            from feeds
+            inner join tracking on tracking.feed = feeds.id
+            left join latest on latest.feed = feeds.id
+            left join paused on paused.feed = feeds.id
+            left join redirects on redirects.feed = feeds.id
+            order by feeds.url
+            "#,
+        )?;
+
+        let health: Vec<Health> = prep
+            .query([])?
+            .map(|row| {
+                Ok(Health {
+                    url: row.get(0)?,
+                    last_attempt: row.get(1)?,
+                    status: row.get(2)?,
+                    kind: row.get(3)?,
+                    error: row.get(4)?,
+                    failures: row.get(5)?,
+                    last_new: row.get(6)?,
+                    paused: row.get(7)?,
+                    moving: match (row.get(8)?, row.get(9)?) {
+                        (Some(target), Some(count)) => Some((target, count)),
+                        _ => None,
+                    },
+                })
+            })
+            .collect()?;
+
+        let _ = self.send.send(health);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(conn: &Connection, status: Option<u16>, error: bool) -> Streak {
+        let (send, mut recv) = oneshot::channel();
+        let entry = FetchEntry {
+            status,
+            error: error.then(|| "failed".to_string()),
+            ..Default::default()
+        };
+
+        LogFetch {
+            send,
+            url: "https://example.com/feed".to_string(),
+            entry,
+        }
+        .perform(conn)
+        .unwrap();
+
+        recv.try_recv().unwrap()
+    }
+
+    #[test]
+    fn not_found_streak_only_counts_404s() {
+        let conn = crate::db::memory();
+
+        for _ in 0..9 {
+            log(&conn, None, true);
+        }
+
+        let streak = log(&conn, Some(404), true);
+        assert_eq!(streak.failures, 10);
+        assert_eq!(streak.not_found, 1);
+
+        log(&conn, Some(404), true);
+        let streak = log(&conn, Some(404), true);
+        assert_eq!(streak.not_found, 3);
+
+        let streak = log(&conn, Some(200), false);
+        assert_eq!(streak.failures, 0);
+        assert_eq!(streak.not_found, 0);
+    }
+
+    fn check(conn: &Connection) -> Vec<Health> {
+        let (send, mut recv) = oneshot::channel();
+        Check { send }.perform(conn).unwrap();
+
+        recv.try_recv().unwrap()
+    }
+
+    #[test]
+    fn keeps_the_latest_fetches() {
+        let conn = crate::db::memory();
+
+        for _ in 0..LOG_LENGTH + 5 {
+            log(&conn, Some(200), false);
+        }
+
+        let kept: u32 = conn
+            .query_row("select count(*) from fetch_log", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(kept, LOG_LENGTH);
+    }
+
+    #[test]
+    fn reports_tracked_feeds() {
+        let conn = crate::db::memory();
+        let new = chrono::Utc::now() - chrono::Duration::hours(1);
+
+        assert!(check(&conn).is_empty());
+
+        conn.execute_batch(
+            r#"
+            insert into feeds (id, url, ttl) values (2, 'https://example.org/feed', 60);
+            insert into tracking (feed, last_fetch) values (1, 0), (2, 0);
+            insert into paused (feed, time, reason) values (2, 0, 'gone (410)');
+            insert into redirects (feed, target, count, since) values (2, 'https://example.net/feed', 3, 0);
+            "#,
+        )
+        .unwrap();
+
+        let (send, _) = oneshot::channel();
+        LogFetch {
+            send,
+            url: "https://example.com/feed".to_string(),
+            entry: FetchEntry {
+                time: new,
+                status: Some(200),
+                new_items: Some(2),
+                ..Default::default()
+            },
+        }
+        .perform(&conn)
+        .unwrap();
+        log(&conn, Some(500), true);
+        log(&conn, Some(503), true);
+
+        let [fetched, paused] = &check(&conn)[..] else {
+            panic!("expected both tracked feeds");
+        };
+
+        assert_eq!(fetched.url, "https://example.com/feed");
+        assert_eq!(fetched.status, Some(503));
+        assert_eq!(fetched.error.as_deref(), Some("failed"));
+        assert_eq!(fetched.failures, 2);
+        assert_eq!(fetched.last_new, Some(new));
+        assert!(fetched.paused.is_none() && fetched.moving.is_none());
+
+        assert_eq!(paused.url, "https://example.org/feed");
+        assert!(paused.last_attempt.is_none());
+        assert_eq!(paused.failures, 0);
+        assert_eq!(paused.paused.as_deref(), Some("gone (410)"));
+        assert_eq!(
+            paused.moving,
+            Some(("https://example.net/feed".to_string(), 3))
+        );
+
+        log(&conn, Some(200), false);
+        assert_eq!(check(&conn)[0].failures, 0);
+    }
+}
@@ -0,0 +1,50 @@
+use rusqlite::{Connection, named_params};
+use tokio::sync::oneshot;
+
+use crate::{db::Operation, feed::UpdateHints};
+
+pub struct SetHints {
+    pub(crate) send: oneshot::Sender<()>,
+    pub(crate) url: String,
+    pub(crate) ttl: Option<u32>,
+    pub(crate) hints: UpdateHints,
+}
+
+impl Operation for SetHints {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let skip_hours = self
+            .hints
+            .skip_hours
+            .iter()
+            .fold(0u32, |mask, hour| mask | 1 << hour);
+
+        let skip_days = self
+            .hints
+            .skip_days
+            .iter()
+            .fold(0u32, |mask, day| mask | 1 << day);
+
+        conn.execute(
+            r#"
+            insert into hints (feed, ttl, update_period, skip_hours, skip_days)
+            select id, :ttl, :period, :hours, :days from feeds where feeds.url = :url
+            on conflict (feed) do update set
+              ttl = excluded.ttl,
+              update_period = excluded.update_period,
+              skip_hours = excluded.skip_hours,
+              skip_days = excluded.skip_days
+            "#,
+            named_params! {
+                ":url": self.url,
+                ":ttl": self.ttl,
+                ":period": self.hints.update_period,
+                ":hours": skip_hours,
+                ":days": skip_days,
+            },
+        )?;
+
+        let _ = self.send.send(());
+
+        Ok(())
+    }
+}
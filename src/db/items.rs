@@ -0,0 +1,481 @@
+use chrono::{DateTime, Utc};
+use rusqlite::fallible_iterator::FallibleIterator;
+use rusqlite::{Connection, ToSql, named_params};
+use tokio::sync::oneshot;
+
+use crate::{
+    FeedItem,
+    db::{
+        Operation,
+        duplicates::{self, Fingerprint},
+        search,
+        types::{Item, ItemFilter, ItemState, Mark},
+    },
+    feed::Content,
+};
+
+pub struct Row {
+    pub(crate) guid: String,
+    pub(crate) title: Option<String>,
+    pub(crate) link: Option<String>,
+    pub(crate) summary: Option<String>,
+    pub(crate) content: Option<String>,
+    pub(crate) authors: String,
+    pub(crate) categories: String,
+    pub(crate) published: Option<DateTime<Utc>>,
+    pub(crate) updated: Option<DateTime<Utc>>,
+    pub(crate) fingerprints: Vec<Fingerprint>,
+}
+
+pub enum Target {
+    Id(i64),
+    Guid { url: String, guid: String },
+}
+
+pub struct Store {
+    pub(crate) send: oneshot::Sender<Vec<bool>>,
+    pub(crate) url: String,
+    pub(crate) rows: Vec<Row>,
+    pub(crate) time: DateTime<Utc>,
+}
+
+pub struct Query {
+    pub(crate) send: oneshot::Sender<Vec<Item>>,
+    pub(crate) filter: ItemFilter,
+}
+
+pub struct SetMark {
+    pub(crate) send: oneshot::Sender<bool>,
+    pub(crate) target: Target,
+    pub(crate) mark: Mark,
+    pub(crate) time: DateTime<Utc>,
+}
+
+pub struct Record {
+    pub(crate) send: oneshot::Sender<bool>,
+    pub(crate) target: Target,
+    pub(crate) time: DateTime<Utc>,
+}
+
+impl From<&FeedItem> for Row {
+    fn from(item: &FeedItem) -> Self {
+        let link = item.link().map(String::from);
+
+        let content = match &item.content {
+            Some(Content::Body(body)) => Some(body.clone()),
+            _ => None,
+        };
+
+        let authors = item
+            .authors
+            .iter()
+            .map(|author| author.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let categories = item
+            .categories
+            .iter()
+            .map(|category| category.term.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        Row {
+            guid: item.id.clone(),
+            title: item.title.clone(),
+            summary: item.summary.clone(),
+            content,
+            authors,
+            categories,
+            published: item.published,
+            updated: item.updated,
+            fingerprints: duplicates::fingerprints(item, link.as_deref()),
+            link,
+        }
+    }
+}
+
+impl Operation for Store {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let mut seen = conn.prepare(
+            r#"
+            insert into seen (feed, guid)
+            select id, :guid from feeds where feeds.url = :url
+            on conflict (feed, guid) do nothing
+            "#,
+        )?;
+
+        let mut prep = conn.prepare(
+            r#"
+            insert into items (feed, guid, title, link, summary, content, authors, categories, published, updated, fetched)
+            select id, :guid, :title, :link, :summary, :content, :authors, :categories, :published, :updated, :fetched
+            from feeds where feeds.url = :url
+            on conflict (feed, guid) do nothing
+            "#,
+        )?;
+
+        let mut fresh = Vec::with_capacity(self.rows.len());
+        for row in &self.rows {
+            let unseen = seen.execute(named_params! {
+                ":url": self.url,
+                ":guid": row.guid,
+            })?;
+
+            if unseen == 0 {
+                fresh.push(false);
+                continue;
+            }
+
+            let changed = prep.execute(named_params! {
+                ":url": self.url,
+                ":guid": row.guid,
+                ":title": row.title,
+                ":link": row.link,
+                ":summary": row.summary,
+                ":content": row.content,
+                ":authors": row.authors,
+                ":categories": row.categories,
+                ":published": row.published,
+                ":updated": row.updated,
+                ":fetched": self.time,
+            })?;
+
+            if changed > 0 {
+                let id = conn.last_insert_rowid();
+                search::index(conn, id)?;
+                duplicates::note(conn, &self.url, id, &row.fingerprints)?;
+            }
+
+            fresh.push(changed > 0);
+        }
+
+        let _ = self.send.send(fresh);
+
+        Ok(())
+    }
+}
+
+impl Operation for Query {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let mut sql = String::from(
+            r#"
+            select items.id, feeds.url, guid, title, link, published, fetched, read_at, starred_at, archived_at
+            from items inner join feeds on feeds.id = items.feed
+            where 1 = 1
+            "#,
+        );
+
+        let mut params: Vec<(&str, &dyn ToSql)> = Vec::new();
+
+        match self.filter.state.unwrap_or(ItemState::Unread) {
+            ItemState::Unread => sql.push_str(" and read_at is null and archived_at is null"),
+            ItemState::Read => sql.push_str(" and read_at is not null"),
+            ItemState::Starred => sql.push_str(" and starred_at is not null"),
+            ItemState::Archived => sql.push_str(" and archived_at is not null"),
+            ItemState::All => (),
+        }
+
+        if let Some(feed) = &self.filter.feed {
+            sql.push_str(" and feeds.url = :feed");
+            params.push((":feed", feed));
+        }
+
+        if let Some(since) = &self.filter.since {
+            sql.push_str(" and coalesce(published, updated, fetched) >= :since");
+            params.push((":since", since));
+        }
+
+        if let Some(until) = &self.filter.until {
+            sql.push_str(" and coalesce(published, updated, fetched) < :until");
+            params.push((":until", until));
+        }
+
+        sql.push_str(" order by coalesce(published, updated, fetched) desc, items.id desc");
+
+        if let Some(limit) = &self.filter.limit {
+            sql.push_str(" limit :limit");
+            params.push((":limit", limit));
+        }
+
+        let mut prep = conn.prepare(&sql)?;
+        let rows = prep.query(&*params)?;
+
+        let items: Vec<Item> = rows.map(item).collect()?;
+
+        let _ = self.send.send(items);
+
+        Ok(())
+    }
+}
+
+impl Operation for SetMark {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let (column, time) = match self.mark {
+            Mark::Read => ("read_at", Some(self.time)),
+            Mark::Unread => ("read_at", None),
+            Mark::Star => ("starred_at", Some(self.time)),
+            Mark::Unstar => ("starred_at", None),
+            Mark::Archive => ("archived_at", Some(self.time)),
+            Mark::Unarchive => ("archived_at", None),
+        };
+
+        let changed = update(conn, column, time, &self.target)?;
+        let _ = self.send.send(changed);
+
+        Ok(())
+    }
+}
+
+impl Operation for Record {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let changed = update(conn, "recorded_at", Some(self.time), &self.target)?;
+        let _ = self.send.send(changed);
+
+        Ok(())
+    }
+}
+
+/// Maps a row starting with `id, url, guid, title, link, published, fetched, read_at, starred_at, archived_at`
+pub(crate) fn item(row: &rusqlite::Row) -> rusqlite::Result<Item> {
+    Ok(Item {
+        id: row.get(0)?,
+        feed: row.get(1)?,
+        guid: row.get(2)?,
+        title: row.get(3)?,
+        link: row.get(4)?,
+        published: row.get(5)?,
+        fetched: row.get(6)?,
+        read: row.get(7)?,
+        starred: row.get(8)?,
+        archived: row.get(9)?,
+    })
+}
+
+fn update(
+    conn: &Connection,
+    column: &str,
+    time: Option<DateTime<Utc>>,
+    target: &Target,
+) -> crate::Result<bool> {
+    let changed = match target {
+        Target::Id(id) => conn.execute(
+            &format!("update items set {column} = :time where id = :id"),
+            named_params! {
+                ":time": time,
+                ":id": id,
+            },
+        )?,
+
+        Target::Guid { url, guid } => conn.execute(
+            &format!(
+                r#"
+                update items set {column} = :time
+                where guid = :guid and feed in (select id from feeds where feeds.url = :url)
+                "#
+            ),
+            named_params! {
+                ":time": time,
+                ":url": url,
+                ":guid": guid,
+            },
+        )?,
+    };
+
+    Ok(changed > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::testing::item as feed_item;
+
+    const FEED: &str = "https://example.com/feed";
+
+    fn store(conn: &Connection, guids: &[&str], time: DateTime<Utc>) -> Vec<bool> {
+        let rows = guids
+            .iter()
+            .map(|guid| {
+                let mut item = feed_item(guid);
+                item.title = Some(format!("title {guid}"));
+                Row::from(&item)
+            })
+            .collect();
+
+        let (send, mut recv) = oneshot::channel();
+        Store {
+            send,
+            url: FEED.to_string(),
+            rows,
+            time,
+        }
+        .perform(conn)
+        .unwrap();
+
+        recv.try_recv().unwrap()
+    }
+
+    fn query(conn: &Connection, filter: ItemFilter) -> Vec<String> {
+        let (send, mut recv) = oneshot::channel();
+        Query { send, filter }.perform(conn).unwrap();
+
+        recv.try_recv()
+            .unwrap()
+            .into_iter()
+            .map(|item| item.guid)
+            .collect()
+    }
+
+    fn mark(conn: &Connection, target: Target, mark: Mark) -> bool {
+        let (send, mut recv) = oneshot::channel();
+        SetMark {
+            send,
+            target,
+            mark,
+            time: Utc::now(),
+        }
+        .perform(conn)
+        .unwrap();
+
+        recv.try_recv().unwrap()
+    }
+
+    fn guid(guid: &str) -> Target {
+        Target::Guid {
+            url: FEED.to_string(),
+            guid: guid.to_string(),
+        }
+    }
+
+    fn filter(state: ItemState) -> ItemFilter {
+        ItemFilter {
+            state: Some(state),
+            ..ItemFilter::default()
+        }
+    }
+
+    #[test]
+    fn stores_each_item_once() {
+        let conn = crate::db::memory();
+        let now = Utc::now();
+
+        assert_eq!(store(&conn, &["a", "b"], now), [true, true]);
+        assert_eq!(store(&conn, &["b", "c"], now), [false, true]);
+
+        // gone from items, but still seen
+        conn.execute("delete from items where guid = 'a'", [])
+            .unwrap();
+        assert_eq!(store(&conn, &["a"], now), [false]);
+
+        assert_eq!(store(&conn, &["d"], now), [true]);
+        let (send, _) = oneshot::channel();
+        Store {
+            send,
+            url: "https://example.com/untracked".to_string(),
+            rows: vec![Row::from(&feed_item("e"))],
+            time: now,
+        }
+        .perform(&conn)
+        .unwrap();
+        assert_eq!(query(&conn, filter(ItemState::All)).len(), 3);
+    }
+
+    #[test]
+    fn queries_newest_first_within_bounds() {
+        let conn = crate::db::memory();
+        let now = Utc::now();
+
+        store(&conn, &["old"], now - Duration::days(2));
+        store(&conn, &["mid"], now - Duration::days(1));
+        store(&conn, &["new"], now);
+
+        assert_eq!(query(&conn, ItemFilter::default()), ["new", "mid", "old"]);
+        assert_eq!(
+            query(
+                &conn,
+                ItemFilter {
+                    since: Some(now - Duration::hours(36)),
+                    until: Some(now - Duration::hours(1)),
+                    ..ItemFilter::default()
+                }
+            ),
+            ["mid"]
+        );
+        assert_eq!(
+            query(
+                &conn,
+                ItemFilter {
+                    limit: Some(2),
+                    feed: Some(FEED.to_string()),
+                    ..ItemFilter::default()
+                }
+            ),
+            ["new", "mid"]
+        );
+        assert!(
+            query(
+                &conn,
+                ItemFilter {
+                    feed: Some("https://example.com/other".to_string()),
+                    ..ItemFilter::default()
+                }
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn marks_by_id_or_guid() {
+        let conn = crate::db::memory();
+        store(&conn, &["a", "b", "c"], Utc::now());
+
+        let id: i64 = conn
+            .query_row("select id from items where guid = 'a'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+
+        assert!(mark(&conn, Target::Id(id), Mark::Read));
+        assert!(mark(&conn, guid("b"), Mark::Star));
+        assert!(mark(&conn, guid("c"), Mark::Archive));
+        assert!(!mark(&conn, guid("missing"), Mark::Read));
+        assert!(!mark(&conn, Target::Id(id + 100), Mark::Read));
+
+        assert_eq!(query(&conn, ItemFilter::default()), ["b"]);
+        assert_eq!(query(&conn, filter(ItemState::Read)), ["a"]);
+        assert_eq!(query(&conn, filter(ItemState::Starred)), ["b"]);
+        assert_eq!(query(&conn, filter(ItemState::Archived)), ["c"]);
+
+        assert!(mark(&conn, Target::Id(id), Mark::Unread));
+        assert!(mark(&conn, guid("b"), Mark::Unstar));
+        assert!(mark(&conn, guid("c"), Mark::Unarchive));
+        assert_eq!(query(&conn, filter(ItemState::Unread)).len(), 3);
+        assert!(query(&conn, filter(ItemState::Starred)).is_empty());
+    }
+
+    #[test]
+    fn records_items() {
+        let conn = crate::db::memory();
+        store(&conn, &["a", "b"], Utc::now());
+
+        let (send, mut recv) = oneshot::channel();
+        Record {
+            send,
+            target: guid("a"),
+            time: Utc::now(),
+        }
+        .perform(&conn)
+        .unwrap();
+        assert!(recv.try_recv().unwrap());
+
+        let recorded: Vec<String> = conn
+            .prepare("select guid from items where recorded_at is not null")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(recorded, ["a"]);
+    }
+}
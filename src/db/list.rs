@@ -10,7 +10,19 @@ pub struct List(pub(crate) oneshot::Sender<Vec<Feed>>);
 impl Operation for List {
     fn perform(self, conn: &Connection) -> crate::Result<()> {
         let mut prep = conn.prepare(
-            "select url, ttl, last_fetch, tracking.id from feeds inner join tracking on feeds.id = tracking.feed",
+            r#"
+            select url, feeds.ttl, last_fetch, tracking.id,
+              (select count(*) from items
+               where items.feed = feeds.id and read_at is null and archived_at is null) unread,
+              paused.reason,
+              max(coalesce(hints.ttl, 0), coalesce(hints.update_period, 0)) min_interval,
+              coalesce(hints.skip_hours, 0), coalesce(hints.skip_days, 0),
+              exists(select 1 from websub where websub.feed = feeds.id and state = 'active'
+                     and julianday(expires) > julianday('now')) pushed
+            from feeds inner join tracking on feeds.id = tracking.feed
+            left join paused on paused.feed = feeds.id
+            left join hints on hints.feed = feeds.id
+            "#,
         )?;
 
         let rows = prep.query([])?;
@@ -22,6 +34,12 @@ impl Operation for List {
                     ttl: row.get(1)?,
                     last_fetch: row.get(2)?,
                     tracking: row.get(3)?,
+                    unread: row.get(4)?,
+                    paused: row.get(5)?,
+                    min_interval: row.get::<_, Option<u32>>(6)?.filter(|min| *min > 0),
+                    skip_hours: row.get(7)?,
+                    skip_days: row.get(8)?,
+                    pushed: row.get(9)?,
                 })
             })
             .collect()?;
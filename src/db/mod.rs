@@ -1,27 +1,84 @@
-use crate::{Error, Result};
+use crate::{Error, FeedItem, Result, feed::Duplicate};
 use chrono::{DateTime, Utc};
 use rusqlite::Connection;
 use tokio::sync::oneshot;
 
 pub mod types;
 
+mod downloads;
+mod duplicates;
 mod feeds;
+mod health;
+mod hints;
+mod items;
 mod list;
+mod redirects;
+mod request;
+mod retention;
+mod search;
+mod state;
 mod tracking;
+mod websub;
+
+pub use items::Target;
 
 const BASE_SCHEMA: &str = include_str!("schema.sql");
 
+/// A step upgrading an existing database, in sql or, for what sql can't do, in code
+enum Migration {
+    Sql(&'static str),
+    Code(fn(&Connection) -> Result<()>),
+}
+
+/// Run once each, in order, on databases whose `user_version` is below their position
+const MIGRATIONS: &[Migration] = &[
+    // seen came after items, so remember what was stored before it
+    Migration::Sql("insert or ignore into seen (feed, guid) select feed, guid from items;"),
+    // items_fts came after items too, and indexing strips html, which sql can't
+    Migration::Code(|conn| search::reindex(conn).map(drop)),
+];
+
 enum Request {
     List(list::List),
     Insert(feeds::Insert),
     Track(tracking::Track),
     Untrack(tracking::Untrack),
+    Store(items::Store),
+    Query(items::Query),
+    SetMark(items::SetMark),
+    Record(items::Record),
+    Search(search::Search),
+    Reindex(search::Reindex),
+    GetRetention(retention::GetRetention),
+    SetRetention(retention::SetRetention),
+    Prune(retention::Prune),
+    LogFetch(health::LogFetch),
+    Health(health::Check),
+    NoteRedirect(redirects::NoteRedirect),
+    Pause(redirects::Pause),
+    Resume(redirects::Resume),
+    GetRequestOptions(request::GetRequestOptions),
+    SetRequestOptions(request::SetRequestOptions),
+    SetHints(hints::SetHints),
+    NoteHub(websub::NoteHub),
+    DueSubscriptions(websub::Due),
+    Requested(websub::Requested),
+    Verify(websub::Verify),
+    Denied(websub::Denied),
+    Subscription(websub::Lookup),
+    FindDuplicates(duplicates::Find),
+    GetDownload(downloads::GetDownload),
+    RecordDownload(downloads::RecordDownload),
+    GetState(state::GetState),
+    SetState(state::SetState),
+    AddState(state::AddState),
 }
 
 trait Operation {
     fn perform(self, conn: &Connection) -> Result<()>;
 }
 
+#[derive(Clone)]
 pub struct Conn {
     send: std::sync::mpsc::Sender<Request>,
 }
@@ -30,6 +87,7 @@ impl Conn {
     pub fn new(conn: Connection, migrate: bool) -> crate::Result<Self> {
         if migrate {
             conn.execute_batch(BASE_SCHEMA)?;
+            Conn::upgrade(&conn)?;
         }
 
         let (send, recv) = std::sync::mpsc::channel();
@@ -38,6 +96,23 @@ impl Conn {
         Ok(Self { send })
     }
 
+    fn upgrade(conn: &Connection) -> crate::Result<()> {
+        let version: i64 = conn.query_row("pragma user_version", [], |row| row.get(0))?;
+        let version = usize::try_from(version).unwrap_or(0);
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.unchecked_transaction()?;
+            match migration {
+                Migration::Sql(sql) => tx.execute_batch(sql)?,
+                Migration::Code(run) => run(&tx)?,
+            }
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+        }
+
+        Ok(())
+    }
+
     pub async fn list(&self) -> crate::Result<Vec<types::Feed>> {
         let (send, recv) = oneshot::channel();
         self.send
@@ -81,9 +156,462 @@ impl Conn {
         Ok(recv.await?)
     }
 
+    pub async fn store(&self, url: String, items: &[FeedItem]) -> crate::Result<Vec<bool>> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::Store(items::Store {
+                send,
+                url,
+                rows: items.iter().map(Into::into).collect(),
+                time: Utc::now(),
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    /// The first copy of each item carried by a feed other than `url`
+    pub async fn duplicates(
+        &self,
+        url: String,
+        items: &[FeedItem],
+    ) -> crate::Result<Vec<Option<Duplicate>>> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::FindDuplicates(duplicates::Find {
+                send,
+                url,
+                items: items
+                    .iter()
+                    .map(|item| duplicates::fingerprints(item, item.link()))
+                    .collect(),
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    pub async fn items(&self, filter: types::ItemFilter) -> crate::Result<Vec<types::Item>> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::Query(items::Query { send, filter }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    pub async fn mark(&self, target: Target, mark: types::Mark) -> crate::Result<bool> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::SetMark(items::SetMark {
+                send,
+                target,
+                mark,
+                time: Utc::now(),
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    pub async fn record(&self, target: Target) -> crate::Result<bool> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::Record(items::Record {
+                send,
+                target,
+                time: Utc::now(),
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    pub async fn search(&self, query: types::SearchQuery) -> crate::Result<Vec<types::SearchHit>> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::Search(search::Search { send, query }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        recv.await?
+    }
+
+    pub async fn reindex(&self) -> crate::Result<usize> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::Reindex(search::Reindex { send }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    pub async fn retention(&self, feed: Option<String>) -> crate::Result<Option<types::Retention>> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::GetRetention(retention::GetRetention {
+                send,
+                feed,
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    pub async fn set_retention(
+        &self,
+        feed: Option<String>,
+        retention: types::Retention,
+        clear: bool,
+    ) -> crate::Result<bool> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::SetRetention(retention::SetRetention {
+                send,
+                feed,
+                retention,
+                clear,
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    pub async fn prune(&self, dry_run: bool) -> crate::Result<Vec<types::Item>> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::Prune(retention::Prune {
+                send,
+                dry_run,
+                now: Utc::now(),
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    /// Records a fetch attempt, returning the runs of failures it ends
+    pub async fn log_fetch(
+        &self,
+        url: String,
+        entry: types::FetchEntry,
+    ) -> crate::Result<types::Streak> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::LogFetch(health::LogFetch { send, url, entry }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    pub async fn health(&self) -> crate::Result<Vec<types::Health>> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::Health(health::Check { send }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    /// Records where the feed permanently redirects to (if anywhere), returning its new url once
+    /// the redirect has been stable long enough to move the feed
+    pub async fn note_redirect(
+        &self,
+        url: String,
+        target: Option<String>,
+    ) -> crate::Result<Option<String>> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::NoteRedirect(redirects::NoteRedirect {
+                send,
+                url,
+                target,
+                time: Utc::now(),
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    pub async fn pause(&self, url: String, reason: String) -> crate::Result<()> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::Pause(redirects::Pause {
+                send,
+                url,
+                reason,
+                time: Utc::now(),
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    pub async fn resume(&self, url: String) -> crate::Result<bool> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::Resume(redirects::Resume { send, url }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    pub async fn request_options(
+        &self,
+        url: String,
+    ) -> crate::Result<Option<types::RequestOptions>> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::GetRequestOptions(request::GetRequestOptions {
+                send,
+                url,
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    pub async fn set_request_options(
+        &self,
+        url: String,
+        options: types::RequestOptions,
+    ) -> crate::Result<()> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::SetRequestOptions(request::SetRequestOptions {
+                send,
+                url,
+                options,
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    /// Stores the polling hints a feed published on its last fetch
+    pub async fn set_hints(
+        &self,
+        url: String,
+        ttl: Option<u32>,
+        hints: crate::feed::UpdateHints,
+    ) -> crate::Result<()> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::SetHints(hints::SetHints {
+                send,
+                url,
+                ttl,
+                hints,
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    /// Records the websub hub a feed advertises, or that it no longer has one
+    pub async fn note_hub(&self, url: String, hub: Option<(String, String)>) -> crate::Result<()> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::NoteHub(websub::NoteHub { send, url, hub }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    /// Subscriptions to request, either new or with leases ending within `margin`
+    pub async fn due_subscriptions(
+        &self,
+        margin: chrono::Duration,
+    ) -> crate::Result<Vec<types::Subscription>> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::DueSubscriptions(websub::Due {
+                send,
+                margin,
+                now: Utc::now(),
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    /// Marks a subscription as requested, returning the secret to send, which is kept across renewals
+    pub async fn subscription_requested(
+        &self,
+        feed: i64,
+        secret: String,
+    ) -> crate::Result<Option<String>> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::Requested(websub::Requested {
+                send,
+                feed,
+                secret,
+                time: Utc::now(),
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    /// Handles a hub's verification of intent, returning whether to confirm it
+    pub async fn verify_subscription(
+        &self,
+        feed: i64,
+        topic: String,
+        mode: String,
+        lease: u32,
+        reason: Option<String>,
+    ) -> crate::Result<bool> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::Verify(websub::Verify {
+                send,
+                feed,
+                topic,
+                mode,
+                lease,
+                reason,
+                now: Utc::now(),
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    pub async fn subscription_denied(&self, feed: i64, reason: String) -> crate::Result<()> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::Denied(websub::Denied { send, feed, reason }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    /// The active subscription of a feed
+    pub async fn subscription(&self, feed: i64) -> crate::Result<Option<types::Subscription>> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::Subscription(websub::Lookup { send, feed }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    /// The saved copy of an item's enclosure
+    pub async fn download(
+        &self,
+        feed: String,
+        guid: String,
+        url: String,
+    ) -> crate::Result<Option<types::Download>> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::GetDownload(downloads::GetDownload {
+                send,
+                feed,
+                guid,
+                url,
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    pub async fn record_download(
+        &self,
+        feed: String,
+        guid: String,
+        url: String,
+        download: types::Download,
+    ) -> crate::Result<()> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::RecordDownload(downloads::RecordDownload {
+                send,
+                feed,
+                guid,
+                url,
+                download,
+                time: Utc::now(),
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    /// A value scripts saved with `store.set`, json encoded
+    pub async fn state(&self, key: String) -> crate::Result<Option<String>> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::GetState(state::GetState { send, key }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    pub async fn set_state(&self, key: String, value: Option<String>) -> crate::Result<()> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::SetState(state::SetState {
+                send,
+                key,
+                value,
+                time: Utc::now(),
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        Ok(recv.await?)
+    }
+
+    /// Adds to the number saved under `key`, returning the new one json encoded
+    pub async fn add_state(&self, key: String, by: f64) -> crate::Result<String> {
+        let (send, recv) = oneshot::channel();
+
+        self.send
+            .send(Request::AddState(state::AddState {
+                send,
+                key,
+                by,
+                time: Utc::now(),
+            }))
+            .map_err(|_| Error::RuntimeQuitSend)?;
+
+        recv.await?
+    }
+
     fn main(conn: Connection, recv: std::sync::mpsc::Receiver<Request>) {
         while let Ok(req) = recv.recv() {
-            let _ = req.perform(&conn);
+            if let Err(err) = req.perform(&conn) {
+                tracing::error!("database request failed: {err}");
+            }
         }
     }
 }
@@ -95,6 +623,110 @@ impl Operation for Request {
             Request::Insert(insert) => insert.perform(conn),
             Request::Track(track) => track.perform(conn),
             Request::Untrack(untrack) => untrack.perform(conn),
+            Request::Store(store) => store.perform(conn),
+            Request::Query(query) => query.perform(conn),
+            Request::SetMark(mark) => mark.perform(conn),
+            Request::Record(record) => record.perform(conn),
+            Request::Search(search) => search.perform(conn),
+            Request::Reindex(reindex) => reindex.perform(conn),
+            Request::GetRetention(get) => get.perform(conn),
+            Request::SetRetention(set) => set.perform(conn),
+            Request::Prune(prune) => prune.perform(conn),
+            Request::LogFetch(log) => log.perform(conn),
+            Request::Health(check) => check.perform(conn),
+            Request::NoteRedirect(note) => note.perform(conn),
+            Request::Pause(pause) => pause.perform(conn),
+            Request::Resume(resume) => resume.perform(conn),
+            Request::GetRequestOptions(get) => get.perform(conn),
+            Request::SetRequestOptions(set) => set.perform(conn),
+            Request::SetHints(set) => set.perform(conn),
+            Request::NoteHub(note) => note.perform(conn),
+            Request::DueSubscriptions(due) => due.perform(conn),
+            Request::Requested(requested) => requested.perform(conn),
+            Request::Verify(verify) => verify.perform(conn),
+            Request::Denied(denied) => denied.perform(conn),
+            Request::Subscription(lookup) => lookup.perform(conn),
+            Request::FindDuplicates(find) => find.perform(conn),
+            Request::GetDownload(get) => get.perform(conn),
+            Request::RecordDownload(record) => record.perform(conn),
+            Request::GetState(get) => get.perform(conn),
+            Request::SetState(set) => set.perform(conn),
+            Request::AddState(add) => add.perform(conn),
         }
     }
 }
+
+/// An in-memory database with the schema, for the tests of the operations
+#[cfg(test)]
+pub(crate) fn memory() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(BASE_SCHEMA).unwrap();
+    conn.execute(
+        "insert into feeds (id, url, ttl) values (1, 'https://example.com/feed', 60)",
+        [],
+    )
+    .unwrap();
+    conn
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(conn: &Connection) -> i64 {
+        conn.query_row("pragma user_version", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn backfills_seen_once() {
+        let conn = memory();
+        conn.execute(
+            "insert into items (feed, guid, fetched) values (1, 'a', 0), (1, 'b', 0)",
+            [],
+        )
+        .unwrap();
+
+        Conn::upgrade(&conn).unwrap();
+        let seen: i64 = conn
+            .query_row("select count(*) from seen", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(seen, 2);
+        assert_eq!(version(&conn), MIGRATIONS.len() as i64);
+
+        // already upgraded, so items stored since aren't backfilled again
+        conn.execute_batch(
+            "delete from seen; insert into items (feed, guid, fetched) values (1, 'c', 0);",
+        )
+        .unwrap();
+        Conn::upgrade(&conn).unwrap();
+        let seen: i64 = conn
+            .query_row("select count(*) from seen", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(seen, 0);
+    }
+
+    #[test]
+    fn indexes_existing_items_once() {
+        let conn = memory();
+        conn.execute(
+            "insert into items (feed, guid, title, summary, fetched) values (1, 'a', 'Rust', '<p>borrow <b>checker</b></p>', 0)",
+            [],
+        )
+        .unwrap();
+
+        let indexed = |conn: &Connection| -> i64 {
+            conn.query_row(
+                "select count(*) from items_fts where items_fts match 'checker'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(indexed(&conn), 0);
+
+        Conn::upgrade(&conn).unwrap();
+        assert_eq!(indexed(&conn), 1);
+        assert_eq!(version(&conn), MIGRATIONS.len() as i64);
+    }
+}
@@ -0,0 +1,129 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, named_params};
+use tokio::sync::oneshot;
+
+use crate::db::Operation;
+
+/// Number of consecutive fetches that must agree on a permanent redirect before the feed moves
+const STABLE_REDIRECTS: u32 = 3;
+
+pub struct NoteRedirect {
+    pub(crate) send: oneshot::Sender<Option<String>>,
+    pub(crate) url: String,
+    pub(crate) target: Option<String>,
+    pub(crate) time: DateTime<Utc>,
+}
+
+pub struct Pause {
+    pub(crate) send: oneshot::Sender<()>,
+    pub(crate) url: String,
+    pub(crate) reason: String,
+    pub(crate) time: DateTime<Utc>,
+}
+
+pub struct Resume {
+    pub(crate) send: oneshot::Sender<bool>,
+    pub(crate) url: String,
+}
+
+impl Operation for NoteRedirect {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let Some(target) = self.target else {
+            conn.execute(
+                "delete from redirects where feed in (select id from feeds where feeds.url = :url)",
+                named_params! { ":url": self.url },
+            )?;
+
+            let _ = self.send.send(None);
+            return Ok(());
+        };
+
+        conn.execute(
+            r#"
+            insert into redirects (feed, target, count, since)
+            select id, :target, 1, :time from feeds where feeds.url = :url
+            on conflict (feed) do update set
+              count = case when target = excluded.target then count + 1 else 1 end,
+              since = case when target = excluded.target then since else excluded.since end,
+              target = excluded.target
+            "#,
+            named_params! {
+                ":url": self.url,
+                ":target": target,
+                ":time": self.time,
+            },
+        )?;
+
+        let count: Option<u32> = conn
+            .query_row(
+                r#"
+                select count from redirects inner join feeds on feeds.id = redirects.feed
+                where feeds.url = :url
+                and not exists (select 1 from feeds taken where taken.url = redirects.target)
+                "#,
+                named_params! { ":url": self.url },
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        if count.is_none_or(|count| count < STABLE_REDIRECTS) {
+            let _ = self.send.send(None);
+            return Ok(());
+        }
+
+        let tx = conn.unchecked_transaction()?;
+
+        tx.execute(
+            "delete from redirects where feed in (select id from feeds where feeds.url = :url)",
+            named_params! { ":url": self.url },
+        )?;
+
+        tx.execute(
+            "update feeds set url = :target where url = :url",
+            named_params! {
+                ":url": self.url,
+                ":target": target,
+            },
+        )?;
+
+        tx.commit()?;
+
+        let _ = self.send.send(Some(target));
+
+        Ok(())
+    }
+}
+
+impl Operation for Pause {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        conn.execute(
+            r#"
+            insert into paused (feed, time, reason)
+            select id, :time, :reason from feeds where feeds.url = :url
+            on conflict (feed) do update set time = excluded.time, reason = excluded.reason
+            "#,
+            named_params! {
+                ":url": self.url,
+                ":time": self.time,
+                ":reason": self.reason,
+            },
+        )?;
+
+        let _ = self.send.send(());
+
+        Ok(())
+    }
+}
+
+impl Operation for Resume {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let changed = conn.execute(
+            "delete from paused where feed in (select id from feeds where feeds.url = :url)",
+            named_params! { ":url": self.url },
+        )?;
+
+        let _ = self.send.send(changed > 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use rusqlite::{Connection, OptionalExtension, named_params};
+use tokio::sync::oneshot;
+
+use crate::db::{Operation, types::RequestOptions};
+
+pub struct GetRequestOptions {
+    pub(crate) send: oneshot::Sender<Option<RequestOptions>>,
+    pub(crate) url: String,
+}
+
+pub struct SetRequestOptions {
+    pub(crate) send: oneshot::Sender<()>,
+    pub(crate) url: String,
+    pub(crate) options: RequestOptions,
+}
+
+impl Operation for GetRequestOptions {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let options: Option<String> = conn
+            .query_row(
+                r#"
+                select options from request_options
+                inner join feeds on feeds.id = request_options.feed
+                where feeds.url = :url
+                "#,
+                named_params! { ":url": self.url },
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let _ = self
+            .send
+            .send(options.and_then(|options| serde_json::from_str(&options).ok()));
+
+        Ok(())
+    }
+}
+
+impl Operation for SetRequestOptions {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        if self.options.is_empty() {
+            conn.execute(
+                r#"
+                delete from request_options where feed in
+                (select id from feeds where feeds.url = :url)
+                "#,
+                named_params! { ":url": self.url },
+            )?;
+        } else {
+            let options = serde_json::to_string(&self.options).unwrap_or_default();
+
+            conn.execute(
+                r#"
+                insert into request_options (feed, options)
+                select id, :options from feeds where feeds.url = :url
+                on conflict (feed) do update set options = excluded.options
+                "#,
+                named_params! { ":url": self.url, ":options": options },
+            )?;
+        }
+
+        let _ = self.send.send(());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+use rusqlite::fallible_iterator::FallibleIterator;
+use rusqlite::{Connection, OptionalExtension, named_params};
+use tokio::sync::oneshot;
+
+use crate::db::{
+    Operation, duplicates, items,
+    types::{Item, Retention},
+};
+
+pub struct GetRetention {
+    pub(crate) send: oneshot::Sender<Option<Retention>>,
+    pub(crate) feed: Option<String>,
+}
+
+pub struct SetRetention {
+    pub(crate) send: oneshot::Sender<bool>,
+    pub(crate) feed: Option<String>,
+    pub(crate) retention: Retention,
+    pub(crate) clear: bool,
+}
+
+pub struct Prune {
+    pub(crate) send: oneshot::Sender<Vec<Item>>,
+    pub(crate) dry_run: bool,
+    pub(crate) now: DateTime<Utc>,
+}
+
+impl Operation for GetRetention {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let retention = conn
+            .query_row(
+                r#"
+                select max_items, max_days from retention
+                where feed = case when :url is null then 0
+                  else (select id from feeds where feeds.url = :url) end
+                "#,
+                named_params! { ":url": self.feed },
+                |row| {
+                    Ok(Retention {
+                        items: row.get(0)?,
+                        days: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?;
+
+        let _ = self.send.send(retention);
+
+        Ok(())
+    }
+}
+
+impl Operation for SetRetention {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let source = if self.feed.is_some() {
+            "select id, :items, :days from feeds where feeds.url = :url"
+        } else {
+            "select 0, :items, :days where :url is null"
+        };
+
+        let update = if self.clear {
+            "max_items = excluded.max_items, max_days = excluded.max_days"
+        } else {
+            r#"
+            max_items = coalesce(excluded.max_items, max_items),
+            max_days = coalesce(excluded.max_days, max_days)
+            "#
+        };
+
+        let changed = conn.execute(
+            &format!(
+                r#"
+                insert into retention (feed, max_items, max_days)
+                {source}
+                on conflict (feed) do update set {update}
+                "#
+            ),
+            named_params! {
+                ":url": self.feed,
+                ":items": self.retention.items,
+                ":days": self.retention.days,
+            },
+        )?;
+
+        let _ = self.send.send(changed > 0);
+
+        Ok(())
+    }
+}
+
+impl Operation for Prune {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let mut prep = conn.prepare(
+            r#"
+            with settings as (
+              select feeds.id feed,
+                coalesce(own.max_items, global.max_items) max_items,
+                coalesce(own.max_days, global.max_days) max_days
+              from feeds
+              left join retention own on own.feed = feeds.id
+              left join retention global on global.feed = 0
+            ),
+            ranked as (
+              select items.*, feeds.url,
+                row_number() over (
+                  partition by items.feed
+                  order by coalesce(published, updated, fetched) desc, items.id desc
+                ) pos
+              from items inner join feeds on feeds.id = items.feed
+            )
+            select ranked.id, url, guid, title, link, published, fetched, read_at, starred_at, archived_at
+            from ranked inner join settings on settings.feed = ranked.feed
+            where starred_at is null and recorded_at is null
+            and (
+              (max_items is not null and pos > max_items)
+              or (max_days is not null
+                  and julianday(coalesce(published, updated, fetched)) < julianday(:now) - max_days)
+            )
+            "#,
+        )?;
+
+        let pruned: Vec<Item> = prep
+            .query(named_params! { ":now": self.now })?
+            .map(items::item)
+            .collect()?;
+
+        if !self.dry_run {
+            let tx = conn.unchecked_transaction()?;
+
+            for item in &pruned {
+                tx.execute(
+                    "delete from items_fts where rowid = :id",
+                    named_params! { ":id": item.id },
+                )?;
+
+                duplicates::forget(&tx, item.id)?;
+
+                tx.execute(
+                    "delete from items where id = :id",
+                    named_params! { ":id": item.id },
+                )?;
+            }
+
+            tx.commit()?;
+        }
+
+        let _ = self.send.send(pruned);
+
+        Ok(())
+    }
+}
@@ -12,3 +12,152 @@ create table if not exists tracking(
 
   foreign key(feed) references feeds(id)
 );
+
+create table if not exists items(
+  id integer primary key,
+  feed integer not null,
+  guid varchar not null,
+  title varchar,
+  link varchar,
+  summary varchar,
+  content varchar,
+  authors varchar,
+  categories varchar,
+  published integer,
+  updated integer,
+  fetched integer not null,
+  read_at integer,
+  starred_at integer,
+  archived_at integer,
+  recorded_at integer,
+
+  unique(feed, guid),
+  foreign key(feed) references feeds(id)
+);
+
+create virtual table if not exists items_fts using fts5(
+  title,
+  summary,
+  content,
+  authors,
+  categories,
+  tokenize = 'porter unicode61'
+);
+
+create table if not exists seen(
+  feed integer not null,
+  guid varchar not null,
+
+  primary key(feed, guid),
+  foreign key(feed) references feeds(id)
+);
+
+-- feed 0 holds the global defaults
+create table if not exists retention(
+  feed integer primary key,
+  max_items integer,
+  max_days integer
+);
+
+create table if not exists fetch_log(
+  id integer primary key,
+  feed integer not null,
+  time integer not null,
+  status integer,
+  duration integer not null,
+  bytes integer,
+  items integer,
+  new_items integer,
+  kind varchar,
+  error varchar,
+
+  foreign key(feed) references feeds(id)
+);
+
+create index if not exists fetch_log_feed on fetch_log(feed, id);
+
+create table if not exists redirects(
+  feed integer primary key,
+  target varchar not null,
+  count integer not null,
+  since integer not null,
+
+  foreign key(feed) references feeds(id)
+);
+
+create table if not exists paused(
+  feed integer primary key,
+  time integer not null,
+  reason varchar not null,
+
+  foreign key(feed) references feeds(id)
+);
+
+create table if not exists request_options(
+  feed integer primary key,
+  options varchar not null,
+
+  foreign key(feed) references feeds(id)
+);
+
+create table if not exists hints(
+  feed integer primary key,
+  ttl integer,
+  update_period integer,
+  skip_hours integer not null,
+  skip_days integer not null,
+
+  foreign key(feed) references feeds(id)
+);
+
+create table if not exists websub(
+  feed integer primary key,
+  hub varchar not null,
+  topic varchar not null,
+  secret varchar,
+  state varchar not null,
+  requested integer,
+  expires integer,
+  reason varchar,
+
+  foreign key(feed) references feeds(id)
+);
+
+create table if not exists downloads(
+  id integer primary key,
+  feed integer not null,
+  guid varchar not null,
+  url varchar not null,
+  path varchar not null,
+  bytes integer not null,
+  sha256 varchar not null,
+  time integer not null,
+
+  unique(feed, guid, url),
+  foreign key(feed) references feeds(id)
+);
+
+create table if not exists fingerprints(
+  item integer not null,
+  kind varchar not null,
+  value varchar not null,
+
+  primary key(item, kind),
+  foreign key(item) references items(id)
+);
+
+create index if not exists fingerprints_value on fingerprints(kind, value);
+
+create table if not exists duplicates(
+  item integer primary key,
+  original integer not null,
+
+  foreign key(item) references items(id),
+  foreign key(original) references items(id)
+);
+
+create table if not exists script_state(
+  key varchar primary key,
+  value varchar not null,
+  updated integer not null
+);
@@ -0,0 +1,275 @@
+use rusqlite::fallible_iterator::FallibleIterator;
+use rusqlite::{Connection, OptionalExtension, ToSql, named_params};
+use tokio::sync::oneshot;
+
+use crate::{
+    db::{
+        Operation, items,
+        types::{SearchHit, SearchQuery},
+    },
+    feed::content,
+};
+
+const DEFAULT_LIMIT: u32 = 20;
+
+pub struct Search {
+    pub(crate) send: oneshot::Sender<crate::Result<Vec<SearchHit>>>,
+    pub(crate) query: SearchQuery,
+}
+
+pub struct Reindex {
+    pub(crate) send: oneshot::Sender<usize>,
+}
+
+impl Operation for Search {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let res = search(conn, &self.query);
+        let _ = self.send.send(res);
+
+        Ok(())
+    }
+}
+
+impl Operation for Reindex {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let count = reindex(conn)?;
+        let _ = self.send.send(count);
+
+        Ok(())
+    }
+}
+
+/// Rebuilds the search entries of every item, returning how many there are
+pub(crate) fn reindex(conn: &Connection) -> crate::Result<usize> {
+    conn.execute("delete from items_fts", [])?;
+
+    let ids: Vec<i64> = conn
+        .prepare("select id from items")?
+        .query([])?
+        .map(|row| row.get(0))
+        .collect()?;
+
+    for id in &ids {
+        index(conn, *id)?;
+    }
+
+    Ok(ids.len())
+}
+
+fn search(conn: &Connection, query: &SearchQuery) -> crate::Result<Vec<SearchHit>> {
+    let mut sql = String::from(
+        r#"
+        select items.id, feeds.url, items.guid, items.title, items.link, items.published,
+          items.fetched, items.read_at, items.starred_at, items.archived_at,
+          snippet(items_fts, -1, :open, :close, '...', 16), bm25(items_fts)
+        from items_fts
+        inner join items on items.id = items_fts.rowid
+        inner join feeds on feeds.id = items.feed
+        where items_fts match :query
+        "#,
+    );
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    let mut params: Vec<(&str, &dyn ToSql)> = vec![
+        (":query", &query.query),
+        (":open", &query.highlight.0),
+        (":close", &query.highlight.1),
+        (":limit", &limit),
+    ];
+
+    if let Some(feed) = &query.feed {
+        sql.push_str(" and feeds.url = :feed");
+        params.push((":feed", feed));
+    }
+
+    if let Some(since) = &query.since {
+        sql.push_str(" and coalesce(items.published, items.updated, items.fetched) >= :since");
+        params.push((":since", since));
+    }
+
+    if let Some(until) = &query.until {
+        sql.push_str(" and coalesce(items.published, items.updated, items.fetched) < :until");
+        params.push((":until", until));
+    }
+
+    sql.push_str(" order by bm25(items_fts) limit :limit");
+
+    let mut prep = conn.prepare(&sql)?;
+    let hits = prep
+        .query(&*params)?
+        .map(|row| {
+            Ok(SearchHit {
+                item: items::item(row)?,
+                snippet: row.get(10)?,
+                rank: row.get(11)?,
+            })
+        })
+        .collect()?;
+
+    Ok(hits)
+}
+
+/// Replaces the search entry of an item with the text of its stored columns
+pub(crate) fn index(conn: &Connection, id: i64) -> crate::Result<()> {
+    conn.execute(
+        "delete from items_fts where rowid = :id",
+        named_params! { ":id": id },
+    )?;
+
+    let row = conn
+        .query_row(
+            "select title, summary, content, authors, categories from items where id = :id",
+            named_params! { ":id": id },
+            |row| {
+                Ok((
+                    row.get::<_, Option<String>>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                ))
+            },
+        )
+        .optional()?;
+
+    let Some((title, summary, body, authors, categories)) = row else {
+        return Ok(());
+    };
+
+    conn.execute(
+        r#"
+        insert into items_fts (rowid, title, summary, content, authors, categories)
+        values (:id, :title, :summary, :content, :authors, :categories)
+        "#,
+        named_params! {
+            ":id": id,
+            ":title": title,
+            ":summary": summary.as_deref().map(content::text),
+            ":content": body.as_deref().map(content::text),
+            ":authors": authors,
+            ":categories": categories,
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Drops the search entries of every item belonging to the feed
+pub(crate) fn forget_feed(conn: &Connection, url: &str) -> crate::Result<()> {
+    conn.execute(
+        r#"
+        delete from items_fts where rowid in
+        (select items.id from items inner join feeds on feeds.id = items.feed where feeds.url = :url)
+        "#,
+        named_params! { ":url": url },
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(conn: &Connection, guid: &str, title: &str, summary: &str) -> i64 {
+        conn.execute(
+            "insert into items (feed, guid, title, summary, fetched) values (1, :guid, :title, :summary, :fetched)",
+            named_params! {
+                ":guid": guid,
+                ":title": title,
+                ":summary": summary,
+                ":fetched": chrono::Utc::now(),
+            },
+        )
+        .unwrap();
+
+        let id = conn.last_insert_rowid();
+        index(conn, id).unwrap();
+        id
+    }
+
+    fn query(query: &str) -> SearchQuery {
+        SearchQuery {
+            query: query.to_string(),
+            feed: None,
+            since: None,
+            until: None,
+            limit: None,
+            highlight: ("[".to_string(), "]".to_string()),
+        }
+    }
+
+    fn guids(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().map(|hit| &hit.item.guid[..]).collect()
+    }
+
+    #[test]
+    fn finds_text_without_markup() {
+        let conn = crate::db::memory();
+        insert(&conn, "a", "Ownership", "<p>the <b>borrow</b> checker</p>");
+        insert(&conn, "b", "Lifetimes", "<p class=\"borrow\">elision</p>");
+
+        let hits = search(&conn, &query("borrow")).unwrap();
+        assert_eq!(guids(&hits), ["a"]);
+        assert_eq!(hits[0].snippet, "the [borrow] checker");
+
+        assert!(search(&conn, &query("class")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn ranks_by_relevance_and_filters() {
+        let conn = crate::db::memory();
+        conn.execute(
+            "insert into feeds (id, url, ttl) values (2, 'https://example.org/feed', 60)",
+            [],
+        )
+        .unwrap();
+
+        insert(
+            &conn,
+            "once",
+            "Notes",
+            "tokio mentioned once among many other words here",
+        );
+        insert(&conn, "often", "Tokio", "tokio tokio tokio");
+        insert(&conn, "none", "Async", "futures");
+        conn.execute("update items set feed = 2 where guid = 'once'", [])
+            .unwrap();
+
+        assert_eq!(
+            guids(&search(&conn, &query("tokio")).unwrap()),
+            ["often", "once"]
+        );
+
+        let mut limited = query("tokio");
+        limited.limit = Some(1);
+        assert_eq!(guids(&search(&conn, &limited).unwrap()), ["often"]);
+
+        let mut feed = query("tokio");
+        feed.feed = Some("https://example.org/feed".to_string());
+        assert_eq!(guids(&search(&conn, &feed).unwrap()), ["once"]);
+
+        assert!(search(&conn, &query("\"unclosed")).is_err());
+    }
+
+    #[test]
+    fn reindexes_and_forgets() {
+        let conn = crate::db::memory();
+        let id = insert(&conn, "a", "First", "alpha");
+        insert(&conn, "b", "Second", "beta");
+
+        conn.execute(
+            "update items set summary = 'gamma' where id = :id",
+            named_params! { ":id": id },
+        )
+        .unwrap();
+        assert_eq!(guids(&search(&conn, &query("alpha")).unwrap()), ["a"]);
+
+        assert_eq!(reindex(&conn).unwrap(), 2);
+        assert!(search(&conn, &query("alpha")).unwrap().is_empty());
+        assert_eq!(guids(&search(&conn, &query("gamma")).unwrap()), ["a"]);
+
+        forget_feed(&conn, "https://example.com/feed").unwrap();
+        assert!(search(&conn, &query("gamma OR beta")).unwrap().is_empty());
+    }
+}
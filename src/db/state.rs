@@ -0,0 +1,202 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, named_params};
+use tokio::sync::oneshot;
+
+use crate::{Error, db::Operation};
+
+pub struct GetState {
+    pub(crate) send: oneshot::Sender<Option<String>>,
+    pub(crate) key: String,
+}
+
+pub struct SetState {
+    pub(crate) send: oneshot::Sender<()>,
+    pub(crate) key: String,
+    /// json encoded value, `None` removing the key
+    pub(crate) value: Option<String>,
+    pub(crate) time: DateTime<Utc>,
+}
+
+/// Adds to a number under one request, so workers counting the same key can't lose updates
+pub struct AddState {
+    pub(crate) send: oneshot::Sender<crate::Result<String>>,
+    pub(crate) key: String,
+    pub(crate) by: f64,
+    pub(crate) time: DateTime<Utc>,
+}
+
+impl Operation for GetState {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let value = conn
+            .query_row(
+                "select value from script_state where key = :key",
+                named_params! { ":key": self.key },
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let _ = self.send.send(value);
+
+        Ok(())
+    }
+}
+
+impl Operation for SetState {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        match self.value {
+            Some(value) => conn.execute(
+                r#"
+                insert into script_state (key, value, updated) values (:key, :value, :time)
+                on conflict (key) do update set value = excluded.value, updated = excluded.updated
+                "#,
+                named_params! { ":key": self.key, ":value": value, ":time": self.time },
+            )?,
+            None => conn.execute(
+                "delete from script_state where key = :key",
+                named_params! { ":key": self.key },
+            )?,
+        };
+
+        let _ = self.send.send(());
+
+        Ok(())
+    }
+}
+
+impl Operation for AddState {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let current: Option<String> = conn
+            .query_row(
+                "select value from script_state where key = :key",
+                named_params! { ":key": self.key },
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let current = match current {
+            Some(current) => match serde_json::from_str(&current) {
+                Ok(serde_json::Value::Number(n)) => n,
+                _ => {
+                    let err = Error::Eval(format!("{} doesn't hold a number", self.key));
+                    let _ = self.send.send(Err(err));
+                    return Ok(());
+                }
+            },
+            None => 0.into(),
+        };
+
+        // stay an integer while both sides are one and the sum fits
+        let sum = match current.as_i64() {
+            Some(n)
+                if self.by.fract() == 0.0
+                    && (i64::MIN as f64..i64::MAX as f64).contains(&self.by) =>
+            {
+                n.checked_add(self.by as i64).map(|sum| sum.to_string())
+            }
+            _ => None,
+        };
+
+        let sum = match sum {
+            Some(sum) => sum,
+            None => {
+                let sum = current.as_f64().unwrap_or_default() + self.by;
+                if !sum.is_finite() {
+                    let err =
+                        Error::Eval(format!("adding {} to {} isn't a number", self.by, self.key));
+                    let _ = self.send.send(Err(err));
+                    return Ok(());
+                }
+
+                sum.to_string()
+            }
+        };
+
+        conn.execute(
+            r#"
+            insert into script_state (key, value, updated) values (:key, :value, :time)
+            on conflict (key) do update set value = excluded.value, updated = excluded.updated
+            "#,
+            named_params! { ":key": self.key, ":value": sum, ":time": self.time },
+        )?;
+
+        let _ = self.send.send(Ok(sum));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(conn: &Connection, key: &str, by: f64) -> crate::Result<String> {
+        let (send, mut recv) = oneshot::channel();
+        AddState {
+            send,
+            key: key.to_string(),
+            by,
+            time: Utc::now(),
+        }
+        .perform(conn)
+        .unwrap();
+
+        recv.try_recv().unwrap()
+    }
+
+    fn set(conn: &Connection, key: &str, value: &str) {
+        let (send, _) = oneshot::channel();
+        SetState {
+            send,
+            key: key.to_string(),
+            value: Some(value.to_string()),
+            time: Utc::now(),
+        }
+        .perform(conn)
+        .unwrap();
+    }
+
+    #[test]
+    fn adds_integers_and_floats() {
+        let conn = crate::db::memory();
+
+        assert_eq!(add(&conn, "n", 1.0).unwrap(), "1");
+        assert_eq!(add(&conn, "n", 41.0).unwrap(), "42");
+        assert_eq!(add(&conn, "n", -50.0).unwrap(), "-8");
+        assert_eq!(add(&conn, "n", 0.5).unwrap(), "-7.5");
+        assert_eq!(add(&conn, "n", 7.5).unwrap(), "0");
+    }
+
+    #[test]
+    fn falls_back_to_floats_past_i64() {
+        let conn = crate::db::memory();
+
+        set(&conn, "max", &i64::MAX.to_string());
+        assert_eq!(add(&conn, "max", 1.0).unwrap(), "9223372036854776000");
+
+        set(&conn, "min", &i64::MIN.to_string());
+        assert_eq!(add(&conn, "min", -1.0).unwrap(), "-9223372036854776000");
+
+        assert_eq!(add(&conn, "big", 1e20).unwrap(), "100000000000000000000");
+    }
+
+    #[test]
+    fn refuses_what_isnt_a_number() {
+        let conn = crate::db::memory();
+
+        set(&conn, "text", r#""hello""#);
+        assert!(matches!(add(&conn, "text", 1.0), Err(Error::Eval(_))));
+
+        set(&conn, "huge", "1e308");
+        assert!(matches!(add(&conn, "huge", 1e308), Err(Error::Eval(_))));
+        assert!(matches!(add(&conn, "nan", f64::NAN), Err(Error::Eval(_))));
+
+        let (send, mut recv) = oneshot::channel();
+        GetState {
+            send,
+            key: "huge".to_string(),
+        }
+        .perform(&conn)
+        .unwrap();
+        assert_eq!(recv.try_recv().unwrap().as_deref(), Some("1e308"));
+    }
+}
@@ -49,6 +49,109 @@ impl super::Operation for Untrack {
         )?;
 
         if self.purge {
+            super::search::forget_feed(conn, &self.url)?;
+            super::duplicates::forget_feed(conn, &self.url)?;
+
+            conn.execute(
+                r#"
+                delete from seen where feed in
+                (select id from feeds where feeds.url = :url)
+                "#,
+                named_params! {
+                    ":url": self.url,
+                },
+            )?;
+
+            conn.execute(
+                r#"
+                delete from fetch_log where feed in
+                (select id from feeds where feeds.url = :url)
+                "#,
+                named_params! {
+                    ":url": self.url,
+                },
+            )?;
+
+            conn.execute(
+                r#"
+                delete from redirects where feed in
+                (select id from feeds where feeds.url = :url)
+                "#,
+                named_params! {
+                    ":url": self.url,
+                },
+            )?;
+
+            conn.execute(
+                r#"
+                delete from paused where feed in
+                (select id from feeds where feeds.url = :url)
+                "#,
+                named_params! {
+                    ":url": self.url,
+                },
+            )?;
+
+            conn.execute(
+                r#"
+                delete from downloads where feed in
+                (select id from feeds where feeds.url = :url)
+                "#,
+                named_params! {
+                    ":url": self.url,
+                },
+            )?;
+
+            conn.execute(
+                r#"
+                delete from websub where feed in
+                (select id from feeds where feeds.url = :url)
+                "#,
+                named_params! {
+                    ":url": self.url,
+                },
+            )?;
+
+            conn.execute(
+                r#"
+                delete from hints where feed in
+                (select id from feeds where feeds.url = :url)
+                "#,
+                named_params! {
+                    ":url": self.url,
+                },
+            )?;
+
+            conn.execute(
+                r#"
+                delete from request_options where feed in
+                (select id from feeds where feeds.url = :url)
+                "#,
+                named_params! {
+                    ":url": self.url,
+                },
+            )?;
+
+            conn.execute(
+                r#"
+                delete from retention where feed in
+                (select id from feeds where feeds.url = :url)
+                "#,
+                named_params! {
+                    ":url": self.url,
+                },
+            )?;
+
+            conn.execute(
+                r#"
+                delete from items where feed in
+                (select id from feeds where feeds.url = :url)
+                "#,
+                named_params! {
+                    ":url": self.url,
+                },
+            )?;
+
             conn.execute(
                 "delete from feeds where url = :url",
                 named_params! {
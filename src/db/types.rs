@@ -1,4 +1,5 @@
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone)]
 pub struct Feed {
@@ -6,4 +7,299 @@ pub struct Feed {
     pub ttl: u32,
     pub last_fetch: DateTime<Utc>,
     pub tracking: u32,
+    pub unread: u32,
+    pub paused: Option<String>,
+    /// the shortest interval the feed asks to be polled at, in minutes
+    pub min_interval: Option<u32>,
+    /// bitmask of utc hours to skip
+    pub skip_hours: u32,
+    /// bitmask of weekdays to skip, bit 0 being monday
+    pub skip_days: u32,
+    /// whether a websub hub currently pushes updates for the feed
+    pub pushed: bool,
+}
+
+/// An enclosure saved by a `download` instruction
+#[derive(Debug, Clone)]
+pub struct Download {
+    pub path: String,
+    pub bytes: i64,
+    /// hex encoded sha256 of the file
+    pub sha256: String,
+}
+
+/// A feed's websub subscription
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    pub feed: i64,
+    pub url: String,
+    pub hub: String,
+    pub topic: String,
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Item {
+    pub id: i64,
+    pub feed: String,
+    pub guid: String,
+    pub title: Option<String>,
+    pub link: Option<String>,
+    pub published: Option<DateTime<Utc>>,
+    pub fetched: DateTime<Utc>,
+    pub read: Option<DateTime<Utc>>,
+    pub starred: Option<DateTime<Utc>>,
+    pub archived: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub item: Item,
+    pub snippet: String,
+    pub rank: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    pub query: String,
+    pub feed: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<u32>,
+    pub highlight: (String, String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FetchEntry {
+    pub time: DateTime<Utc>,
+    pub status: Option<u16>,
+    pub duration: u32,
+    pub bytes: Option<u32>,
+    pub items: Option<u32>,
+    pub new_items: Option<u32>,
+    pub kind: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Runs of failures ending with the latest fetch
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Streak {
+    /// consecutive failed fetches of any kind
+    pub failures: u32,
+    /// consecutive fetches answered with a 404
+    pub not_found: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Health {
+    pub url: String,
+    pub last_attempt: Option<DateTime<Utc>>,
+    pub status: Option<u16>,
+    pub kind: Option<String>,
+    pub error: Option<String>,
+    pub failures: u32,
+    pub last_new: Option<DateTime<Utc>>,
+    pub paused: Option<String>,
+    pub moving: Option<(String, u32)>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retention {
+    pub items: Option<u32>,
+    pub days: Option<u32>,
+}
+
+/// Extra settings sent with every request for a feed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestOptions {
+    #[serde(default)]
+    pub headers: Vec<Header>,
+    pub basic: Option<Basic>,
+    pub bearer: Option<Secret>,
+    #[serde(default)]
+    pub cookies: Vec<Secret>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Basic {
+    pub user: String,
+    pub password: Secret,
+}
+
+/// A reference to a secret, resolved when a request is sent so the value never reaches the db
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Secret {
+    /// `env:NAME`, an environment variable
+    Env(String),
+    /// `cred:KEY`, a key in the credentials file
+    Cred(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemState {
+    Unread,
+    Read,
+    Starred,
+    Archived,
+    All,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mark {
+    Read,
+    Unread,
+    Star,
+    Unstar,
+    Archive,
+    Unarchive,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ItemFilter {
+    pub state: Option<ItemState>,
+    pub feed: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<u32>,
+}
+
+impl std::str::FromStr for ItemState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unread" => Ok(ItemState::Unread),
+            "read" => Ok(ItemState::Read),
+            "starred" => Ok(ItemState::Starred),
+            "archived" => Ok(ItemState::Archived),
+            "all" => Ok(ItemState::All),
+            _ => Err(format!(
+                "invalid state {s} (expected unread, read, starred, archived or all)"
+            )),
+        }
+    }
+}
+
+impl std::str::FromStr for Mark {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Mark::Read),
+            "unread" => Ok(Mark::Unread),
+            "star" => Ok(Mark::Star),
+            "unstar" => Ok(Mark::Unstar),
+            "archive" => Ok(Mark::Archive),
+            "unarchive" => Ok(Mark::Unarchive),
+            _ => Err(format!(
+                "invalid mark {s} (expected read, unread, star, unstar, archive or unarchive)"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Mark {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Mark::Read => "read",
+            Mark::Unread => "unread",
+            Mark::Star => "star",
+            Mark::Unstar => "unstar",
+            Mark::Archive => "archive",
+            Mark::Unarchive => "unarchive",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+impl RequestOptions {
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+            && self.basic.is_none()
+            && self.bearer.is_none()
+            && self.cookies.is_empty()
+    }
+}
+
+impl std::str::FromStr for Header {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, value)) = s.split_once(':') else {
+            return Err(format!("invalid header {s} (expected name: value)"));
+        };
+
+        let name = name.trim();
+        reqwest::header::HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| format!("invalid header name {name}"))?;
+
+        Ok(Header {
+            name: name.to_string(),
+            value: value.trim().to_string(),
+        })
+    }
+}
+
+impl std::str::FromStr for Basic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((user, password)) = s.split_once(':') else {
+            return Err(format!(
+                "invalid credentials {s} (expected user:env:NAME or user:cred:KEY)"
+            ));
+        };
+
+        Ok(Basic {
+            user: user.to_string(),
+            password: password.parse()?,
+        })
+    }
+}
+
+impl std::str::FromStr for Secret {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("env", name)) if !name.is_empty() => Ok(Secret::Env(name.to_string())),
+            Some(("cred", key)) if !key.is_empty() => Ok(Secret::Cred(key.to_string())),
+            _ => Err(format!(
+                "invalid secret {s} (expected env:NAME or cred:KEY)"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Secret {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Secret> for String {
+    fn from(value: Secret) -> Self {
+        value.to_string()
+    }
+}
+
+impl std::fmt::Display for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Secret::Env(name) => write!(f, "env:{name}"),
+            Secret::Cred(key) => write!(f, "cred:{key}"),
+        }
+    }
 }
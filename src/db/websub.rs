@@ -0,0 +1,242 @@
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::fallible_iterator::FallibleIterator;
+use rusqlite::{Connection, OptionalExtension, named_params};
+use tokio::sync::oneshot;
+
+use crate::db::{Operation, types::Subscription};
+
+/// How long to wait on a hub that never verified a subscription before asking again
+const RETRY_PENDING: Duration = Duration::hours(1);
+
+/// How long to wait before asking a hub that denied a subscription again
+const RETRY_DENIED: Duration = Duration::days(1);
+
+pub struct NoteHub {
+    pub(crate) send: oneshot::Sender<()>,
+    pub(crate) url: String,
+    pub(crate) hub: Option<(String, String)>,
+}
+
+pub struct Due {
+    pub(crate) send: oneshot::Sender<Vec<Subscription>>,
+    /// renew leases this close to expiring, or halfway through for shorter leases
+    pub(crate) margin: Duration,
+    pub(crate) now: DateTime<Utc>,
+}
+
+pub struct Requested {
+    pub(crate) send: oneshot::Sender<Option<String>>,
+    pub(crate) feed: i64,
+    pub(crate) secret: String,
+    pub(crate) time: DateTime<Utc>,
+}
+
+pub struct Verify {
+    pub(crate) send: oneshot::Sender<bool>,
+    pub(crate) feed: i64,
+    pub(crate) topic: String,
+    pub(crate) mode: String,
+    /// seconds the hub granted, or the ones asked for when it didn't say
+    pub(crate) lease: u32,
+    pub(crate) reason: Option<String>,
+    pub(crate) now: DateTime<Utc>,
+}
+
+pub struct Denied {
+    pub(crate) send: oneshot::Sender<()>,
+    pub(crate) feed: i64,
+    pub(crate) reason: String,
+}
+
+pub struct Lookup {
+    pub(crate) send: oneshot::Sender<Option<Subscription>>,
+    pub(crate) feed: i64,
+}
+
+impl Operation for NoteHub {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        match self.hub {
+            Some((hub, topic)) => {
+                conn.execute(
+                    r#"
+                    insert into websub (feed, hub, topic, state)
+                    select id, :hub, :topic, 'discovered' from feeds where feeds.url = :url
+                    on conflict (feed) do update set
+                      state = case when hub = excluded.hub and topic = excluded.topic
+                        then state else 'discovered' end,
+                      hub = excluded.hub,
+                      topic = excluded.topic
+                    "#,
+                    named_params! { ":url": self.url, ":hub": hub, ":topic": topic },
+                )?;
+            }
+
+            None => {
+                conn.execute(
+                    "delete from websub where feed in (select id from feeds where feeds.url = :url)",
+                    named_params! { ":url": self.url },
+                )?;
+            }
+        }
+
+        let _ = self.send.send(());
+
+        Ok(())
+    }
+}
+
+impl Operation for Due {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let mut prep = conn.prepare(
+            r#"
+            select websub.feed, feeds.url, hub, topic, secret
+            from websub
+            inner join feeds on feeds.id = websub.feed
+            inner join tracking on tracking.feed = websub.feed
+            where state = 'discovered'
+            -- verified before hubs leaving out the lease got the one asked for
+            or (state = 'active' and expires is null)
+            or (state = 'active' and julianday(expires) - julianday(:now)
+                < min(:margin, (julianday(expires) - julianday(requested)) / 2))
+            or (state = 'pending' and julianday(requested) < julianday(:pending))
+            or (state = 'denied' and julianday(requested) < julianday(:denied))
+            "#,
+        )?;
+
+        let due: Vec<Subscription> = prep
+            .query(named_params! {
+                ":now": self.now,
+                ":margin": self.margin.num_seconds() as f64 / 86400.0,
+                ":pending": self.now - RETRY_PENDING,
+                ":denied": self.now - RETRY_DENIED,
+            })?
+            .map(subscription)
+            .collect()?;
+
+        let _ = self.send.send(due);
+
+        Ok(())
+    }
+}
+
+impl Operation for Requested {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        conn.execute(
+            r#"
+            update websub set
+              secret = coalesce(secret, :secret),
+              state = case when state = 'active' then state else 'pending' end,
+              requested = :time
+            where feed = :feed
+            "#,
+            named_params! { ":feed": self.feed, ":secret": self.secret, ":time": self.time },
+        )?;
+
+        let secret = conn
+            .query_row(
+                "select secret from websub where feed = :feed",
+                named_params! { ":feed": self.feed },
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let _ = self.send.send(secret);
+
+        Ok(())
+    }
+}
+
+impl Operation for Verify {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let verified = match self.mode.as_str() {
+            "subscribe" => {
+                let expires = self.now + Duration::seconds(self.lease.into());
+
+                conn.execute(
+                    r#"
+                    update websub set state = 'active', expires = :expires, reason = null
+                    where feed = :feed and topic = :topic and state in ('pending', 'active')
+                    and feed in (select feed from tracking)
+                    "#,
+                    named_params! { ":feed": self.feed, ":topic": self.topic, ":expires": expires },
+                )? > 0
+            }
+
+            // only confirm dropping subscriptions to feeds that are no longer followed
+            "unsubscribe" => conn
+                .query_row(
+                    r#"
+                    select 1 from websub inner join tracking on tracking.feed = websub.feed
+                    where websub.feed = :feed and topic = :topic
+                    "#,
+                    named_params! { ":feed": self.feed, ":topic": self.topic },
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_none(),
+
+            "denied" => {
+                conn.execute(
+                    r#"
+                    update websub set state = 'denied', reason = :reason
+                    where feed = :feed and topic = :topic
+                    "#,
+                    named_params! { ":feed": self.feed, ":topic": self.topic, ":reason": self.reason },
+                )?;
+
+                true
+            }
+
+            _ => false,
+        };
+
+        let _ = self.send.send(verified);
+
+        Ok(())
+    }
+}
+
+impl Operation for Denied {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        conn.execute(
+            "update websub set state = 'denied', reason = :reason where feed = :feed",
+            named_params! { ":feed": self.feed, ":reason": self.reason },
+        )?;
+
+        let _ = self.send.send(());
+
+        Ok(())
+    }
+}
+
+impl Operation for Lookup {
+    fn perform(self, conn: &Connection) -> crate::Result<()> {
+        let sub = conn
+            .query_row(
+                r#"
+                select websub.feed, feeds.url, hub, topic, secret
+                from websub
+                inner join feeds on feeds.id = websub.feed
+                inner join tracking on tracking.feed = websub.feed
+                where websub.feed = :feed and state = 'active'
+                "#,
+                named_params! { ":feed": self.feed },
+                subscription,
+            )
+            .optional()?;
+
+        let _ = self.send.send(sub);
+
+        Ok(())
+    }
+}
+
+fn subscription(row: &rusqlite::Row) -> rusqlite::Result<Subscription> {
+    Ok(Subscription {
+        feed: row.get(0)?,
+        url: row.get(1)?,
+        hub: row.get(2)?,
+        topic: row.get(3)?,
+        secret: row.get(4)?,
+    })
+}
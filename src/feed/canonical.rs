@@ -0,0 +1,287 @@
+use url::{Url, form_urlencoded};
+
+use crate::feed::{Feed, content};
+
+/// Query parameters stripped unless `init.lua` gives its own list, a trailing `*` matching any suffix
+const TRACKING: &[&str] = &[
+    "utm_*", "fbclid", "gclid", "dclid", "msclkid", "yclid", "mc_cid", "mc_eid", "igshid",
+    "_hsenc", "_hsmi",
+];
+
+/// Rules rewriting item links so the same article gets the same url everywhere.
+///
+/// Hosts are always lowercased, that being part of parsing an http(s) url.
+#[derive(Clone, Debug)]
+pub struct Canonical {
+    /// query parameters to drop
+    pub strip: Vec<String>,
+    /// rewrite http links to https
+    pub https: bool,
+}
+
+impl Default for Canonical {
+    fn default() -> Self {
+        Canonical {
+            strip: TRACKING.iter().map(|param| param.to_string()).collect(),
+            https: false,
+        }
+    }
+}
+
+impl Canonical {
+    pub fn apply(&self, url: &Url) -> Url {
+        let mut url = url.clone();
+
+        if !matches!(url.scheme(), "http" | "https") {
+            return url;
+        }
+
+        if self.https && url.scheme() == "http" {
+            let _ = url.set_scheme("https");
+            if url.port() == Some(80) {
+                let _ = url.set_port(None);
+            }
+        }
+
+        // parameters are dropped from the raw query, so the ones kept stay byte for byte
+        if let Some(query) = url.query() {
+            let segments: Vec<&str> = query.split('&').collect();
+            let kept: Vec<&str> = segments
+                .iter()
+                .copied()
+                .filter(|segment| !self.strips_segment(segment))
+                .collect();
+
+            if kept.len() < segments.len() {
+                let kept = kept.join("&");
+                url.set_query((!kept.is_empty()).then_some(&kept[..]));
+            }
+        }
+
+        url
+    }
+
+    fn strips_segment(&self, segment: &str) -> bool {
+        match form_urlencoded::parse(segment.as_bytes()).next() {
+            Some((name, _)) => self.strips(&name),
+            None => false,
+        }
+    }
+
+    fn strips(&self, param: &str) -> bool {
+        self.strip
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => param.starts_with(prefix),
+                None => param == pattern,
+            })
+    }
+}
+
+impl Feed {
+    /// Makes every link absolute and canonicalizes the items' ones.
+    ///
+    /// Entries resolve against their xml:base, then the feed's link, then the url the feed came
+    /// from. Feed links are left as given once absolute, a websub topic having to match exactly.
+    pub fn resolve(&mut self, url: Option<&Url>, canonical: &Canonical) {
+        let url = url.filter(|url| matches!(url.scheme(), "http" | "https" | "file"));
+
+        for link in &mut self.meta.links {
+            if let Some(href) = content::resolve(url, &link.href) {
+                link.href = href.to_string();
+            }
+        }
+
+        let site = self
+            .meta
+            .links
+            .iter()
+            .find(|link| matches!(link.rel.as_deref(), None | Some("alternate")))
+            .and_then(|link| Url::parse(&link.href).ok())
+            .or_else(|| url.cloned());
+
+        for item in &mut self.items {
+            let base = match item.base.take() {
+                Some(base) => content::resolve(site.as_ref(), &base),
+                None => site.clone(),
+            };
+
+            for link in &mut item.links {
+                if let Some(href) = content::resolve(base.as_ref(), &link.href) {
+                    link.href = canonical.apply(&href).to_string();
+                }
+            }
+
+            for media in &mut item.media {
+                if let Some(href) = content::resolve(base.as_ref(), &media.url) {
+                    media.url = href.to_string();
+                }
+            }
+
+            item.base = base.map(String::from);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(canonical: &Canonical, url: &str) -> String {
+        canonical.apply(&Url::parse(url).unwrap()).to_string()
+    }
+
+    #[test]
+    fn strips_tracking_parameters() {
+        let canonical = Canonical::default();
+
+        assert_eq!(
+            apply(
+                &canonical,
+                "https://Example.COM/post?utm_source=rss&id=4&utm_medium=feed&fbclid=x#top"
+            ),
+            "https://example.com/post?id=4#top"
+        );
+        assert_eq!(
+            apply(
+                &canonical,
+                "https://example.com/post?utm_source=rss&gclid=y"
+            ),
+            "https://example.com/post"
+        );
+        assert_eq!(
+            apply(&canonical, "https://example.com/post?utmost=1&fbclid_x=2"),
+            "https://example.com/post?utmost=1&fbclid_x=2"
+        );
+        assert_eq!(
+            apply(&canonical, "https://example.com/post"),
+            "https://example.com/post"
+        );
+    }
+
+    #[test]
+    fn keeps_remaining_parameters_in_order() {
+        let canonical = Canonical {
+            strip: vec!["ref".to_string(), "session*".to_string()],
+            https: false,
+        };
+
+        assert_eq!(
+            apply(
+                &canonical,
+                "http://example.com/?b=2&ref=x&a=1&sessionid=3&utm_source=rss&a=0"
+            ),
+            "http://example.com/?b=2&a=1&utm_source=rss&a=0"
+        );
+    }
+
+    #[test]
+    fn leaves_untouched_queries_as_given() {
+        let canonical = Canonical::default();
+
+        for url in [
+            "https://example.com/search?q=a%20b",
+            "https://example.com/search?q=a+b&lang=en",
+            "https://example.com/list?page",
+            "https://example.com/?a/b",
+            "https://example.com/?x=%7E&&y=",
+        ] {
+            assert_eq!(apply(&canonical, url), url);
+        }
+
+        assert_eq!(
+            apply(
+                &canonical,
+                "https://example.com/?q=a%20b&utm_source=rss&page&a/b"
+            ),
+            "https://example.com/?q=a%20b&page&a/b"
+        );
+        assert_eq!(
+            apply(&canonical, "https://example.com/?utm%5Fsource=rss&q=a+b"),
+            "https://example.com/?q=a+b"
+        );
+    }
+
+    #[test]
+    fn upgrades_to_https() {
+        let canonical = Canonical {
+            https: true,
+            ..Canonical::default()
+        };
+
+        assert_eq!(
+            apply(&canonical, "http://example.com:80/a"),
+            "https://example.com/a"
+        );
+        assert_eq!(
+            apply(&canonical, "http://example.com:8080/a"),
+            "https://example.com:8080/a"
+        );
+        assert_eq!(
+            apply(&Canonical::default(), "http://example.com/a"),
+            "http://example.com/a"
+        );
+    }
+
+    #[test]
+    fn leaves_other_schemes() {
+        let canonical = Canonical {
+            https: true,
+            ..Canonical::default()
+        };
+
+        assert_eq!(
+            apply(&canonical, "mailto:me@example.com?utm_source=rss"),
+            "mailto:me@example.com?utm_source=rss"
+        );
+        assert_eq!(
+            apply(&canonical, "ftp://example.com/f?utm_source=rss"),
+            "ftp://example.com/f?utm_source=rss"
+        );
+    }
+
+    #[test]
+    fn resolves_feed_links() {
+        let mut feed = Feed::parse(
+            br#"<?xml version="1.0" encoding="utf-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+              <id>urn:feed</id>
+              <title>t</title>
+              <updated>2024-01-01T00:00:00Z</updated>
+              <link href="/blog/"/>
+              <link rel="self" href="atom.xml"/>
+              <entry>
+                <id>urn:one</id>
+                <title>one</title>
+                <updated>2024-01-01T00:00:00Z</updated>
+                <link href="posts/one?utm_source=atom"/>
+                <link rel="enclosure" href="one.mp3" length="1"/>
+              </entry>
+            </feed>"#,
+        )
+        .unwrap();
+        let url = Url::parse("https://example.com/feeds/atom.xml").unwrap();
+
+        feed.resolve(Some(&url), &Canonical::default());
+
+        let hrefs: Vec<_> = feed.meta.links.iter().map(|link| &link.href[..]).collect();
+        assert_eq!(
+            hrefs,
+            [
+                "https://example.com/blog/",
+                "https://example.com/feeds/atom.xml"
+            ]
+        );
+
+        let item = &feed.items[0];
+        assert_eq!(item.base.as_deref(), Some("https://example.com/blog/"));
+        let hrefs: Vec<_> = item.links.iter().map(|link| &link.href[..]).collect();
+        assert_eq!(
+            hrefs,
+            [
+                "https://example.com/blog/posts/one",
+                "https://example.com/blog/one.mp3"
+            ]
+        );
+    }
+}
@@ -0,0 +1,444 @@
+use ego_tree::iter::Edge;
+use scraper::{Html, Node};
+use url::Url;
+
+use crate::{FeedItem, feed::Content};
+
+const BLOCKS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "tr",
+    "ul",
+];
+
+const SKIP: &[&str] = &["script", "style", "template", "head", "iframe", "noscript"];
+
+/// Elements dropped with everything inside them when sanitizing
+const DROP: &[&str] = &[
+    "button", "embed", "form", "head", "iframe", "input", "math", "noscript", "object", "script",
+    "select", "style", "svg", "template", "textarea",
+];
+
+/// Elements kept when sanitizing, anything else is replaced by its children
+const SAFE: &[&str] = &[
+    "a",
+    "abbr",
+    "b",
+    "blockquote",
+    "br",
+    "caption",
+    "code",
+    "dd",
+    "del",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "s",
+    "small",
+    "span",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "u",
+    "ul",
+];
+
+const VOID: &[&str] = &["br", "hr", "img"];
+
+/// Attributes kept on safe elements, the urls among them resolved against the base
+const ATTRS: &[&str] = &[
+    "alt", "cite", "colspan", "height", "href", "rowspan", "src", "title", "width",
+];
+
+const URL_ATTRS: &[&str] = &["cite", "href", "src"];
+
+/// Plain text cut to at most this many characters for notifications
+pub const ALERT_EXCERPT: usize = 200;
+
+/// Strips the markup from an html fragment, keeping block elements on separate lines
+pub fn text(html: &str) -> String {
+    let fragment = Html::parse_fragment(html);
+
+    let mut out = String::new();
+    let mut skip = 0;
+
+    for edge in fragment.tree.root().traverse() {
+        match edge {
+            Edge::Open(node) => match node.value() {
+                Node::Element(elem) if SKIP.contains(&elem.name()) => skip += 1,
+                Node::Element(elem) if BLOCKS.contains(&elem.name()) => out.push('\n'),
+                Node::Text(text) if skip == 0 => out.push_str(text),
+                _ => (),
+            },
+
+            Edge::Close(node) => match node.value() {
+                Node::Element(elem) if SKIP.contains(&elem.name()) => skip -= 1,
+                Node::Element(elem) if BLOCKS.contains(&elem.name()) => out.push('\n'),
+                _ => (),
+            },
+        }
+    }
+
+    out.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Rewrites an html fragment to a safe subset: scripts, styles, embeds and event handlers are
+/// dropped, urls are made absolute and only http(s) and mailto links survive.
+pub fn sanitize(html: &str, base: Option<&Url>) -> String {
+    let fragment = Html::parse_fragment(html);
+
+    let mut out = String::new();
+    let mut drop = 0;
+
+    for edge in fragment.tree.root().traverse() {
+        match edge {
+            Edge::Open(node) => match node.value() {
+                Node::Element(elem) if DROP.contains(&elem.name()) => drop += 1,
+                _ if drop > 0 => (),
+                Node::Element(elem) if SAFE.contains(&elem.name()) => {
+                    out.push('<');
+                    out.push_str(elem.name());
+
+                    for (name, value) in elem.attrs() {
+                        if !ATTRS.contains(&name) {
+                            continue;
+                        }
+
+                        let value = if URL_ATTRS.contains(&name) {
+                            match safe_url(base, value) {
+                                Some(url) => url.to_string(),
+                                None => continue,
+                            }
+                        } else {
+                            value.to_string()
+                        };
+
+                        out.push_str(&format!(" {name}=\"{}\"", escape(&value, true)));
+                    }
+
+                    out.push('>');
+                }
+                Node::Text(text) => out.push_str(&escape(text, false)),
+                _ => (),
+            },
+
+            Edge::Close(node) => match node.value() {
+                Node::Element(elem) if DROP.contains(&elem.name()) => drop -= 1,
+                _ if drop > 0 => (),
+                Node::Element(elem)
+                    if SAFE.contains(&elem.name()) && !VOID.contains(&elem.name()) =>
+                {
+                    out.push_str(&format!("</{}>", elem.name()));
+                }
+                _ => (),
+            },
+        }
+    }
+
+    out
+}
+
+/// The absolute urls of the images in an html fragment, in document order
+pub fn images(html: &str, base: Option<&Url>) -> Vec<String> {
+    let fragment = Html::parse_fragment(html);
+
+    fragment
+        .tree
+        .root()
+        .descendants()
+        .filter_map(|node| match node.value() {
+            Node::Element(elem) if elem.name() == "img" => elem.attr("src"),
+            _ => None,
+        })
+        .filter_map(|src| safe_url(base, src))
+        .map(|url| url.to_string())
+        .collect()
+}
+
+/// Cuts plain text to at most `len` characters on a word boundary, joining its lines
+pub fn excerpt(text: &str, len: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let Some((cut, _)) = text.char_indices().nth(len) else {
+        return text;
+    };
+
+    let head = &text[..cut];
+    let head = match head.rfind(' ') {
+        Some(space) if space > 0 => &head[..space],
+        _ => head,
+    };
+    let head = head.trim_end_matches(|c: char| c.is_ascii_punctuation());
+
+    // an ellipsis alone says nothing
+    if head.is_empty() {
+        return String::new();
+    }
+
+    format!("{head}…")
+}
+
+/// Resolves a possibly relative url against the base, if there is one
+pub fn resolve(base: Option<&Url>, href: &str) -> Option<Url> {
+    match Url::parse(href) {
+        Ok(url) => Some(url),
+        Err(url::ParseError::RelativeUrlWithoutBase) => base?.join(href).ok(),
+        Err(_) => None,
+    }
+}
+
+fn safe_url(base: Option<&Url>, href: &str) -> Option<Url> {
+    resolve(base, href.trim()).filter(|url| matches!(url.scheme(), "http" | "https" | "mailto"))
+}
+
+fn escape(text: &str, attr: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if attr => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+impl FeedItem {
+    /// The item's html, its content body when there is one and its summary otherwise
+    pub fn html(&self) -> Option<&str> {
+        match &self.content {
+            Some(Content::Body(body)) => Some(body),
+            _ => self.summary.as_deref(),
+        }
+    }
+
+    /// The url relative links in the item resolve against, its base or the feed's link
+    pub fn base_url(&self) -> Option<Url> {
+        self.base.as_deref().and_then(|base| Url::parse(base).ok())
+    }
+
+    /// The item's html as plain text
+    pub fn text(&self) -> String {
+        self.html().map(text).unwrap_or_default()
+    }
+
+    pub fn excerpt(&self, len: usize) -> String {
+        excerpt(&self.text(), len)
+    }
+
+    pub fn sanitized(&self) -> Option<String> {
+        self.html()
+            .map(|html| sanitize(html, self.base_url().as_ref()))
+    }
+
+    pub fn images(&self) -> Vec<String> {
+        self.html()
+            .map(|html| images(html, self.base_url().as_ref()))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> Url {
+        Url::parse("https://example.com/blog/post.html").unwrap()
+    }
+
+    fn clean(html: &str) -> String {
+        sanitize(html, Some(&base()))
+    }
+
+    #[test]
+    fn drops_script_urls() {
+        assert_eq!(clean(r#"<a href="javascript:alert(1)">x</a>"#), "<a>x</a>");
+        assert_eq!(
+            clean(r#"<a href="  JaVaScRiPt:alert(1)">x</a>"#),
+            "<a>x</a>"
+        );
+        assert_eq!(
+            clean(r#"<a href="java&#x09;script:alert(1)">x</a>"#),
+            "<a>x</a>"
+        );
+        assert_eq!(
+            clean(r#"<img src="data:image/svg+xml;base64,PHN2Zz4=" alt="a">"#),
+            r#"<img alt="a">"#
+        );
+        assert_eq!(
+            clean(r#"<blockquote cite="vbscript:x">q</blockquote>"#),
+            "<blockquote>q</blockquote>"
+        );
+        assert_eq!(
+            clean(r#"<a href="mailto:me@example.com">mail</a>"#),
+            r#"<a href="mailto:me@example.com">mail</a>"#
+        );
+    }
+
+    #[test]
+    fn drops_handlers_and_styles() {
+        assert_eq!(
+            clean(r#"<img src="a.png" onerror="alert(1)" style="x" ONLOAD="y">"#),
+            r#"<img src="https://example.com/blog/a.png">"#
+        );
+        assert_eq!(
+            clean(r#"<p onclick="alert(1)" class="c" id="i">hi</p>"#),
+            "<p>hi</p>"
+        );
+    }
+
+    #[test]
+    fn drops_scripts_inside_safe_elements() {
+        assert_eq!(
+            clean("<p>hi<script>alert(1)</script> there</p>"),
+            "<p>hi there</p>"
+        );
+        assert_eq!(
+            clean("<ul><li><b><style>*{}</style>bold</b></li></ul>"),
+            "<ul><li><b>bold</b></li></ul>"
+        );
+        assert_eq!(
+            clean("<em><svg><script>alert(1)</script></svg>ok</em>"),
+            "<em>ok</em>"
+        );
+        assert_eq!(
+            clean("<p><iframe src=\"https://evil.example\"></iframe><object>o</object>x</p>"),
+            "<p>x</p>"
+        );
+    }
+
+    #[test]
+    fn unwraps_unknown_elements() {
+        assert_eq!(
+            clean("<div><font color=red>x</font><custom-el>y</custom-el></div>"),
+            "xy"
+        );
+    }
+
+    #[test]
+    fn escapes_text_and_attributes() {
+        assert_eq!(
+            clean(r#"<abbr title="say &quot;hi&quot; <b>">a &amp; b &lt; c</abbr>"#),
+            r#"<abbr title="say &quot;hi&quot; &lt;b&gt;">a &amp; b &lt; c</abbr>"#
+        );
+        assert_eq!(
+            clean(r#"<img alt='"><script>alert(1)</script>' src="a.png">"#),
+            r#"<img alt="&quot;&gt;&lt;script&gt;alert(1)&lt;/script&gt;" src="https://example.com/blog/a.png">"#
+        );
+    }
+
+    #[test]
+    fn resolves_relative_urls() {
+        assert_eq!(
+            clean(r#"<a href="../about">a</a><img src="/img/b.png">"#),
+            r#"<a href="https://example.com/about">a</a><img src="https://example.com/img/b.png">"#
+        );
+        assert_eq!(sanitize(r#"<a href="../about">a</a>"#, None), "<a>a</a>");
+
+        assert_eq!(
+            resolve(Some(&base()), "//cdn.example.com/x")
+                .unwrap()
+                .as_str(),
+            "https://cdn.example.com/x"
+        );
+        assert_eq!(
+            resolve(None, "https://example.org/feed").unwrap().as_str(),
+            "https://example.org/feed"
+        );
+        assert_eq!(resolve(None, "feed.xml"), None);
+        assert_eq!(resolve(Some(&base()), "http://[::1"), None);
+    }
+
+    #[test]
+    fn finds_images_in_order() {
+        let html = r#"
+            <p><img src="one.png"><img src="data:image/png;base64,AAAA"></p>
+            <img src="https://cdn.example.com/two.jpg"><img alt="no source">
+        "#;
+
+        assert_eq!(
+            images(html, Some(&base())),
+            [
+                "https://example.com/blog/one.png",
+                "https://cdn.example.com/two.jpg"
+            ]
+        );
+    }
+
+    #[test]
+    fn extracts_text() {
+        assert_eq!(
+            text("<p>first  line</p><p>second<br>third</p><script>x()</script>"),
+            "first line\nsecond\nthird"
+        );
+    }
+
+    #[test]
+    fn cuts_excerpts_on_words() {
+        assert_eq!(excerpt("short text", 200), "short text");
+        assert_eq!(excerpt("joins\n  lines\tup", 200), "joins lines up");
+        assert_eq!(excerpt("the quick brown fox", 12), "the quick…");
+        assert_eq!(excerpt("ends, with a comma", 8), "ends…");
+        assert_eq!(excerpt("unbrokenword", 4), "unbr…");
+        assert_eq!(excerpt("héllo wörld ünïcode", 12), "héllo wörld…");
+        assert_eq!(excerpt("anything", 0), "");
+        assert_eq!(excerpt("...", 1), "");
+    }
+}
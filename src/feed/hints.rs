@@ -0,0 +1,125 @@
+use quick_xml::{Reader, events::Event};
+use serde::{Deserialize, Serialize};
+
+/// Publishing hints from the rss `skipHours`/`skipDays` elements and the syndication module
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateHints {
+    /// minutes between updates, from `sy:updatePeriod` and `sy:updateFrequency`
+    pub update_period: Option<u32>,
+    /// hours (utc) in which the feed should not be polled
+    pub skip_hours: Vec<u32>,
+    /// days in which the feed should not be polled, counted from monday
+    pub skip_days: Vec<u32>,
+}
+
+impl UpdateHints {
+    /// Scans a raw feed for the hints, which `feed_rs` doesn't expose
+    pub(crate) fn parse(body: &[u8]) -> UpdateHints {
+        let mut reader = Reader::from_reader(body);
+        let mut buf = Vec::new();
+        let mut path: Vec<Vec<u8>> = Vec::new();
+
+        let mut hints = UpdateHints::default();
+        let mut period = None;
+        let mut frequency = None;
+
+        loop {
+            match reader.read_event_into(&mut buf) {
+                Ok(Event::Start(elem)) => path.push(elem.local_name().as_ref().to_vec()),
+                Ok(Event::End(_)) => {
+                    path.pop();
+                }
+
+                Ok(Event::Text(text)) => {
+                    let Ok(text) = text.unescape() else {
+                        continue;
+                    };
+                    let text = text.trim();
+
+                    match path.as_slice() {
+                        [.., parent, elem] if parent == b"skipHours" && elem == b"hour" => {
+                            if let Ok(hour) = text.parse::<u32>()
+                                && hour < 24
+                            {
+                                hints.skip_hours.push(hour);
+                            }
+                        }
+
+                        [.., parent, elem] if parent == b"skipDays" && elem == b"day" => {
+                            if let Ok(day) = text.parse::<chrono::Weekday>() {
+                                hints.skip_days.push(day.num_days_from_monday());
+                            }
+                        }
+
+                        [.., elem] if elem == b"updatePeriod" => {
+                            period = match text {
+                                "hourly" => Some(60),
+                                "daily" => Some(60 * 24),
+                                "weekly" => Some(60 * 24 * 7),
+                                "monthly" => Some(60 * 24 * 30),
+                                "yearly" => Some(60 * 24 * 365),
+                                _ => None,
+                            };
+                        }
+
+                        [.., elem] if elem == b"updateFrequency" => {
+                            frequency = text.parse::<u32>().ok().filter(|freq| *freq > 0);
+                        }
+
+                        _ => (),
+                    }
+                }
+
+                Ok(Event::Eof) | Err(_) => break,
+                _ => (),
+            }
+
+            buf.clear();
+        }
+
+        hints.update_period = period.map(|period: u32| period / frequency.unwrap_or(1));
+
+        hints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_skip_hours_and_days() {
+        let body = br#"<?xml version="1.0"?>
+            <rss version="2.0"><channel>
+              <title>t</title>
+              <skipHours><hour>0</hour><hour> 23 </hour><hour>24</hour><hour>x</hour></skipHours>
+              <skipDays><day>Saturday</day><day>Sunday</day><day>Someday</day></skipDays>
+              <item><title>hour</title></item>
+            </channel></rss>"#;
+
+        let hints = UpdateHints::parse(body);
+        assert_eq!(hints.skip_hours, [0, 23]);
+        assert_eq!(hints.skip_days, [5, 6]);
+        assert_eq!(hints.update_period, None);
+    }
+
+    #[test]
+    fn divides_the_update_period_by_its_frequency() {
+        let body = br#"<?xml version="1.0"?>
+            <rss version="2.0" xmlns:sy="http://purl.org/rss/1.0/modules/syndication/">
+              <channel>
+                <sy:updatePeriod>daily</sy:updatePeriod>
+                <sy:updateFrequency>4</sy:updateFrequency>
+              </channel>
+            </rss>"#;
+
+        assert_eq!(UpdateHints::parse(body).update_period, Some(6 * 60));
+    }
+
+    #[test]
+    fn ignores_hours_outside_skip_hours() {
+        let body = br#"<feed><entry><hour>3</hour></entry></feed>"#;
+
+        assert_eq!(UpdateHints::parse(body), UpdateHints::default());
+    }
+}
@@ -1,8 +1,11 @@
-use rlua::{UserData, Value};
+use rlua::{FromLua, Table, UserData, Value};
 
 use crate::{
     FeedItem,
-    feed::{Category, Content, FeedMeta, Link, Person},
+    feed::{
+        Canonical, Category, Content, Duplicate, FeedError, FeedMeta, Generator, Image, Link,
+        Media, Person, content::ALERT_EXCERPT,
+    },
 };
 
 impl UserData for FeedItem {
@@ -17,9 +20,23 @@ impl UserData for FeedItem {
         fields.add_field_method_get("categories", |_, this| Ok(this.categories.clone()));
         fields.add_field_method_get("links", |_, this| Ok(this.links.clone()));
         fields.add_field_method_get("base", |_, this| Ok(this.base.clone()));
+        fields.add_field_method_get("media", |_, this| Ok(this.media.clone()));
+        fields.add_field_method_get("rights", |_, this| Ok(this.rights.clone()));
+        fields.add_field_method_get("language", |_, this| Ok(this.language.clone()));
     }
 
     fn add_methods<'lua, M: rlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("text", |_, this, ()| Ok(this.text()));
+        methods.add_method("excerpt", |_, this, len: Option<usize>| {
+            Ok(this.excerpt(len.unwrap_or(ALERT_EXCERPT)))
+        });
+        methods.add_method("html", |_, this, ()| Ok(this.sanitized()));
+        methods.add_method("images", |_, this, ()| Ok(this.images()));
+        methods.add_method("duplicate_of", |_, this, ()| Ok(this.duplicate_of.clone()));
+        methods.add_method("seen_elsewhere", |_, this, ()| {
+            Ok(this.duplicate_of.is_some())
+        });
+
         methods.add_method("has_category", |_, this, cat: String| {
             for category in &this.categories {
                 if category.term == cat {
@@ -48,6 +65,24 @@ impl UserData for FeedMeta {
         fields.add_field_method_get("links", |_, this| Ok(this.links.clone()));
         fields.add_field_method_get("categories", |_, this| Ok(this.categories.clone()));
         fields.add_field_method_get("ttl", |_, this| Ok(this.ttl));
+        fields.add_field_method_get("icon", |_, this| Ok(this.icon.clone()));
+        fields.add_field_method_get("logo", |_, this| Ok(this.logo.clone()));
+        fields.add_field_method_get("language", |_, this| Ok(this.language.clone()));
+        fields.add_field_method_get("rights", |_, this| Ok(this.rights.clone()));
+        fields.add_field_method_get("generator", |_, this| Ok(this.generator.clone()));
+        fields.add_field_method_get("feed_type", |_, this| Ok(this.feed_type.as_str()));
+    }
+}
+
+impl UserData for FeedError {
+    fn add_fields<'lua, F: rlua::UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("url", |_, this| Ok(this.url.clone()));
+        fields.add_field_method_get("status", |_, this| Ok(this.status));
+        fields.add_field_method_get("kind", |_, this| Ok(this.kind.clone()));
+        fields.add_field_method_get("message", |_, this| Ok(this.message.clone()));
+        fields.add_field_method_get("failures", |_, this| Ok(this.failures));
+        fields.add_field_method_get("paused", |_, this| Ok(this.paused));
+        fields.add_field_method_get("moved", |_, this| Ok(this.moved.clone()));
     }
 }
 
@@ -87,6 +122,45 @@ impl UserData for Content {
     }
 }
 
+impl UserData for Media {
+    fn add_fields<'lua, F: rlua::UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("url", |_, this| Ok(this.url.clone()));
+        fields.add_field_method_get("media_type", |_, this| Ok(this.media_type.clone()));
+        fields.add_field_method_get("length", |_, this| Ok(this.length));
+        fields.add_field_method_get("duration", |_, this| Ok(this.duration));
+        fields.add_field_method_get("thumbnail", |_, this| Ok(this.thumbnail.clone()));
+        fields.add_field_method_get("title", |_, this| Ok(this.title.clone()));
+    }
+}
+
+impl UserData for Duplicate {
+    fn add_fields<'lua, F: rlua::UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("feed", |_, this| Ok(this.feed.clone()));
+        fields.add_field_method_get("id", |_, this| Ok(this.id.clone()));
+        fields.add_field_method_get("title", |_, this| Ok(this.title.clone()));
+        fields.add_field_method_get("link", |_, this| Ok(this.link.clone()));
+        fields.add_field_method_get("fetched", |_, this| Ok(this.fetched.timestamp()));
+    }
+}
+
+impl UserData for Image {
+    fn add_fields<'lua, F: rlua::UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("uri", |_, this| Ok(this.uri.clone()));
+        fields.add_field_method_get("title", |_, this| Ok(this.title.clone()));
+        fields.add_field_method_get("link", |_, this| Ok(this.link.clone()));
+        fields.add_field_method_get("width", |_, this| Ok(this.width));
+        fields.add_field_method_get("height", |_, this| Ok(this.height));
+    }
+}
+
+impl UserData for Generator {
+    fn add_fields<'lua, F: rlua::UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("name", |_, this| Ok(this.name.clone()));
+        fields.add_field_method_get("uri", |_, this| Ok(this.uri.clone()));
+        fields.add_field_method_get("version", |_, this| Ok(this.version.clone()));
+    }
+}
+
 impl UserData for Category {
     fn add_fields<'lua, F: rlua::UserDataFields<'lua, Self>>(fields: &mut F) {
         fields.add_field_method_get("term", |_, this| Ok(this.term.clone()));
@@ -94,3 +168,25 @@ impl UserData for Category {
         fields.add_field_method_get("subcategories", |_, this| Ok(this.subcategories.clone()));
     }
 }
+
+impl<'lua> FromLua<'lua> for Canonical {
+    fn from_lua(value: Value<'lua>, _: &'lua rlua::Lua) -> rlua::Result<Self> {
+        let Value::Table(table) = value else {
+            return Err(rlua::Error::runtime("expected a table for urls"));
+        };
+
+        let mut canonical = Canonical::default();
+
+        if let Some(strip) = table.get::<_, Option<Table>>("strip")? {
+            canonical.strip = strip
+                .sequence_values::<String>()
+                .collect::<rlua::Result<_>>()?;
+        }
+
+        if let Some(https) = table.get("https")? {
+            canonical.https = https;
+        }
+
+        Ok(canonical)
+    }
+}
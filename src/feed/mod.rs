@@ -1,15 +1,21 @@
 use chrono::{DateTime, Utc};
-use serde::{Serialize, ser::SerializeMap};
+use serde::{Deserialize, Serialize, ser::SerializeMap};
 
+mod canonical;
+pub mod content;
+mod hints;
 mod lua;
 
-#[derive(Clone, Debug, Serialize)]
+pub use canonical::Canonical;
+pub use hints::UpdateHints;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Feed {
     pub meta: FeedMeta,
     pub items: Vec<FeedItem>,
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FeedMeta {
     pub id: String,
     pub title: Option<String>,
@@ -21,9 +27,44 @@ pub struct FeedMeta {
     pub ttl: Option<u32>,
     pub updated: Option<DateTime<Utc>>,
     pub published: Option<DateTime<Utc>>,
+    pub hints: UpdateHints,
+    pub icon: Option<Image>,
+    pub logo: Option<Image>,
+    pub language: Option<String>,
+    pub rights: Option<String>,
+    pub generator: Option<Generator>,
+    pub feed_type: FeedType,
+}
+
+/// The format a feed was published in, `source` for feeds built by a lua source
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedType {
+    Atom,
+    Json,
+    Rss0,
+    Rss1,
+    Rss2,
+    Source,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Image {
+    pub uri: String,
+    pub title: Option<String>,
+    pub link: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Generator {
+    pub name: String,
+    pub uri: Option<String>,
+    pub version: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FeedItem {
     pub id: String,
     pub title: Option<String>,
@@ -37,9 +78,35 @@ pub struct FeedItem {
     pub updated: Option<DateTime<Utc>>,
     pub published: Option<DateTime<Utc>>,
     pub base: Option<String>,
+    pub media: Vec<Media>,
+    pub rights: Option<String>,
+    pub language: Option<String>,
+    /// the first copy of this item seen in another tracked feed
+    pub duplicate_of: Option<Duplicate>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Duplicate {
+    pub feed: String,
+    pub id: String,
+    pub title: Option<String>,
+    pub link: Option<String>,
+    pub fetched: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FeedError {
+    pub url: String,
+    pub status: Option<u16>,
+    pub kind: String,
+    pub message: String,
+    pub failures: u32,
+    pub paused: bool,
+    /// where the feed moved to, for the `moved` kind
+    pub moved: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Person {
     pub name: String,
     pub uri: Option<String>,
@@ -75,7 +142,26 @@ impl Serialize for Content {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+impl<'de> Deserialize<'de> for Content {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(tag = "type", rename_all = "lowercase")]
+        enum Tagged {
+            Body { body: String },
+            Link { link: Link },
+        }
+
+        Ok(match Tagged::deserialize(deserializer)? {
+            Tagged::Body { body } => Content::Body(body),
+            Tagged::Link { link } => Content::Link(link),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Link {
     pub href: String,
     pub rel: Option<String>,
@@ -83,22 +169,70 @@ pub struct Link {
     pub title: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// An enclosure or media:content attached to an item
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Media {
+    pub url: String,
+    pub media_type: Option<String>,
+    /// size in bytes
+    pub length: Option<u64>,
+    /// play time in seconds
+    pub duration: Option<u64>,
+    pub thumbnail: Option<String>,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Category {
     pub term: String,
     pub label: Option<String>,
     pub subcategories: Vec<Category>,
 }
 
+impl Feed {
+    /// Parses a raw rss, atom or json feed
+    pub fn parse(body: &[u8]) -> crate::Result<Feed> {
+        let mut feed: Feed = feed_rs::parser::parse(body)?.into();
+        feed.meta.hints = UpdateHints::parse(body);
+
+        Ok(feed)
+    }
+}
+
+impl FeedItem {
+    /// The link stored for the item, its alternate one or else the first it has
+    pub fn link(&self) -> Option<&str> {
+        self.links
+            .iter()
+            .find(|link| link.rel.as_deref().is_none_or(|rel| rel == "alternate"))
+            .or(self.links.first())
+            .map(|link| link.href.as_str())
+    }
+}
+
+impl FeedMeta {
+    /// The websub hub the feed advertises and the topic to subscribe to, its `self` link or `url`
+    pub(crate) fn hub(&self, url: &str) -> Option<(String, String)> {
+        let rel = |rel: &str| {
+            self.links
+                .iter()
+                .find(|link| link.rel.as_deref() == Some(rel))
+                .map(|link| link.href.clone())
+        };
+
+        Some((rel("hub")?, rel("self").unwrap_or_else(|| url.to_string())))
+    }
+}
+
 impl From<feed_rs::model::Feed> for Feed {
     fn from(value: feed_rs::model::Feed) -> Self {
         let title = value.title.map(|t| t.content);
         let description = value.description.map(|t| t.content);
         let authors = value.authors.into_iter().map(Into::into).collect();
         let contributors = value.contributors.into_iter().map(Into::into).collect();
-        let items = value.entries.into_iter().map(Into::into).collect();
         let categories = value.categories.into_iter().map(Into::into).collect();
         let links = value.links.into_iter().map(Into::into).collect();
+        let items = value.entries.into_iter().map(Into::into).collect();
 
         Feed {
             meta: FeedMeta {
@@ -112,6 +246,13 @@ impl From<feed_rs::model::Feed> for Feed {
                 ttl: value.ttl,
                 updated: value.updated,
                 published: value.published,
+                hints: UpdateHints::default(),
+                icon: value.icon.map(Into::into),
+                logo: value.logo.map(Into::into),
+                language: value.language,
+                rights: value.rights.map(|t| t.content),
+                generator: value.generator.map(Into::into),
+                feed_type: value.feed_type.into(),
             },
             items,
         }
@@ -134,6 +275,11 @@ impl From<feed_rs::model::Entry> for FeedItem {
         let contributors = value.contributors.into_iter().map(Into::into).collect();
         let categories = value.categories.into_iter().map(Into::into).collect();
         let links = value.links.into_iter().map(Into::into).collect();
+        let media = value
+            .media
+            .into_iter()
+            .flat_map(Media::from_object)
+            .collect();
         let content = value.content.and_then(|content| {
             if let Some(body) = content.body {
                 Some(Content::Body(body))
@@ -155,6 +301,86 @@ impl From<feed_rs::model::Entry> for FeedItem {
             updated: value.updated,
             published: value.published,
             base: value.base,
+            media,
+            rights: value.rights.map(|t| t.content),
+            language: value.language,
+            duplicate_of: None,
+        }
+    }
+}
+
+impl Media {
+    /// One entry per playable url of a media object, sharing its title and first thumbnail
+    fn from_object(object: feed_rs::model::MediaObject) -> Vec<Media> {
+        let title = object.title.map(|t| t.content);
+        let thumbnail = object
+            .thumbnails
+            .into_iter()
+            .next()
+            .map(|thumb| thumb.image.uri);
+
+        object
+            .content
+            .into_iter()
+            .filter_map(|content| {
+                Some(Media {
+                    url: content.url?.to_string(),
+                    media_type: content.content_type.map(|mime| mime.to_string()),
+                    length: content.size,
+                    duration: content.duration.or(object.duration).map(|d| d.as_secs()),
+                    thumbnail: thumbnail.clone(),
+                    title: title.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl FeedType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeedType::Atom => "atom",
+            FeedType::Json => "json",
+            FeedType::Rss0 => "rss0",
+            FeedType::Rss1 => "rss1",
+            FeedType::Rss2 => "rss2",
+            FeedType::Source => "source",
+        }
+    }
+}
+
+impl From<feed_rs::model::FeedType> for FeedType {
+    fn from(value: feed_rs::model::FeedType) -> Self {
+        use feed_rs::model::FeedType as Model;
+
+        match value {
+            Model::Atom => FeedType::Atom,
+            Model::JSON => FeedType::Json,
+            Model::RSS0 => FeedType::Rss0,
+            Model::RSS1 => FeedType::Rss1,
+            Model::RSS2 => FeedType::Rss2,
+        }
+    }
+}
+
+impl From<feed_rs::model::Image> for Image {
+    fn from(value: feed_rs::model::Image) -> Self {
+        Image {
+            uri: value.uri,
+            title: value.title,
+            link: value.link.map(|link| link.href),
+            width: value.width,
+            height: value.height,
+        }
+    }
+}
+
+impl From<feed_rs::model::Generator> for Generator {
+    fn from(value: feed_rs::model::Generator) -> Self {
+        Generator {
+            name: value.content,
+            uri: value.uri,
+            version: value.version,
         }
     }
 }
@@ -181,3 +407,104 @@ impl From<feed_rs::model::Category> for Category {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xml:lang="en-gb">
+  <id>urn:example:feed</id>
+  <title>Atom Feed</title>
+  <updated>2024-01-02T03:04:05Z</updated>
+  <icon>https://example.com/favicon.ico</icon>
+  <logo>https://example.com/logo.png</logo>
+  <rights>© Example</rights>
+  <generator uri="https://example.com/gen" version="1.2">Gen</generator>
+  <entry>
+    <id>urn:example:1</id>
+    <title>First</title>
+    <updated>2024-01-02T03:04:05Z</updated>
+    <link href="https://example.com/1"/>
+  </entry>
+</feed>"#;
+
+    const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0">
+  <channel>
+    <title>Rss Feed</title>
+    <link>https://example.com</link>
+    <description>About</description>
+    <language>de</language>
+    <copyright>CC BY</copyright>
+    <generator>Hugo</generator>
+    <ttl>45</ttl>
+    <image>
+      <url>https://example.com/image.png</url>
+      <title>Rss Feed</title>
+      <link>https://example.com</link>
+      <width>88</width>
+      <height>31</height>
+    </image>
+    <item><guid>one</guid><title>One</title><link>https://example.com/one</link></item>
+  </channel>
+</rss>"#;
+
+    #[test]
+    fn maps_atom_feeds() {
+        let feed = Feed::parse(ATOM.as_bytes()).unwrap();
+        let meta = &feed.meta;
+
+        assert_eq!(meta.id, "urn:example:feed");
+        assert_eq!(meta.title.as_deref(), Some("Atom Feed"));
+        assert_eq!(
+            meta.icon.as_ref().unwrap().uri,
+            "https://example.com/favicon.ico"
+        );
+        assert_eq!(
+            meta.logo.as_ref().unwrap().uri,
+            "https://example.com/logo.png"
+        );
+        assert_eq!(meta.language.as_deref(), Some("en-gb"));
+        assert_eq!(meta.rights.as_deref(), Some("© Example"));
+
+        let generator = meta.generator.as_ref().unwrap();
+        assert_eq!(generator.name, "Gen");
+        assert_eq!(generator.uri.as_deref(), Some("https://example.com/gen"));
+        assert_eq!(generator.version.as_deref(), Some("1.2"));
+
+        assert_eq!(meta.feed_type, FeedType::Atom);
+        assert_eq!(meta.feed_type.as_str(), "atom");
+        assert_eq!(serde_json::to_string(&meta.feed_type).unwrap(), r#""atom""#);
+
+        assert_eq!(feed.items[0].id, "urn:example:1");
+        assert_eq!(feed.items[0].link(), Some("https://example.com/1"));
+    }
+
+    #[test]
+    fn maps_rss_feeds() {
+        let feed = Feed::parse(RSS.as_bytes()).unwrap();
+        let meta = &feed.meta;
+
+        assert_eq!(meta.title.as_deref(), Some("Rss Feed"));
+        assert_eq!(meta.description.as_deref(), Some("About"));
+        assert_eq!(meta.ttl, Some(45));
+        assert!(meta.icon.is_none());
+
+        let logo = meta.logo.as_ref().unwrap();
+        assert_eq!(logo.uri, "https://example.com/image.png");
+        assert_eq!(logo.link.as_deref(), Some("https://example.com/"));
+        assert_eq!((logo.width, logo.height), (Some(88), Some(31)));
+
+        assert_eq!(meta.language.as_deref(), Some("de"));
+        assert_eq!(meta.rights.as_deref(), Some("CC BY"));
+        assert_eq!(meta.generator.as_ref().unwrap().name, "Hugo");
+
+        assert_eq!(meta.feed_type, FeedType::Rss2);
+        assert_eq!(meta.feed_type.as_str(), "rss2");
+        assert_eq!(serde_json::to_string(&meta.feed_type).unwrap(), r#""rss2""#);
+
+        assert_eq!(feed.items[0].id, "one");
+        assert_eq!(feed.items[0].link(), Some("https://example.com/one"));
+    }
+}
@@ -0,0 +1,88 @@
+use std::{process::Stdio, time::Duration};
+
+use percent_encoding::percent_decode_str;
+use tokio::process::Command;
+use url::Url;
+
+use crate::fetcher::{Fetched, Fetcher};
+
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+impl Fetcher {
+    /// Runs the shell command of an `exec:` url, taking its stdout as the feed
+    pub(crate) async fn run_command(&self, url: Url) -> crate::Result<Fetched> {
+        let sh = command(&url);
+
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(&sh)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let output = tokio::time::timeout(COMMAND_TIMEOUT, child.wait_with_output())
+            .await
+            .map_err(|_| {
+                crate::Error::Command(format!(
+                    "`{sh}` timed out after {}s",
+                    COMMAND_TIMEOUT.as_secs()
+                ))
+            })??;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr.lines().last().unwrap_or_default();
+
+            return Err(crate::Error::Command(format!(
+                "`{sh}` {}: {reason}",
+                output.status
+            )));
+        }
+
+        Ok(Fetched {
+            url,
+            status: None,
+            body: output.stdout,
+            moved: None,
+        })
+    }
+}
+
+/// The shell command of an `exec:` url, including anything the url parsed as a query or fragment
+fn command(url: &Url) -> String {
+    let raw = url.as_str().strip_prefix("exec:").unwrap_or(url.path());
+
+    percent_decode_str(raw).decode_utf8_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_query_and_fragment() {
+        let url = Url::parse("exec:curl 'https://x/feed?page=2#top'").unwrap();
+        assert_eq!(command(&url), "curl 'https://x/feed?page=2#top'");
+    }
+
+    #[test]
+    fn keeps_literal_escapes() {
+        for sh in [
+            "printf '%20s|%d\\n' hi 4",
+            "date +%2d",
+            "curl 'https://x/feed?q=a%20b&n=1' | sed 's/ /%20/g'",
+            "printf 'multi\n\tline ünïcode'",
+        ] {
+            let source: crate::Source = format!("exec:{sh}").parse().unwrap();
+            assert_eq!(command(&source.into_url().unwrap()), sh);
+        }
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        let url = Url::parse("exec:cat%20feed.xml%20%7C%20head").unwrap();
+        assert_eq!(command(&url), "cat feed.xml | head");
+    }
+}
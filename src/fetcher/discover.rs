@@ -0,0 +1,197 @@
+use scraper::{Html, Selector};
+use serde::Serialize;
+use url::Url;
+
+use crate::{
+    db::types::RequestOptions,
+    fetcher::{Fetched, Fetcher},
+};
+
+const FEED_TYPES: &[&str] = &[
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/feed+json",
+];
+
+const COMMON_PATHS: &[&str] = &[
+    "/feed",
+    "/rss.xml",
+    "/atom.xml",
+    "/feed.xml",
+    "/index.xml",
+    "/rss",
+];
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Candidate {
+    pub url: Url,
+    pub title: Option<String>,
+    pub media_type: Option<String>,
+}
+
+impl Fetcher {
+    /// Finds the feeds a url refers to, either the url itself or the feeds a web page links to
+    pub async fn discover(
+        &self,
+        url: Url,
+        options: &RequestOptions,
+    ) -> crate::Result<Vec<Candidate>> {
+        let fetched = self.fetch_with(url.clone(), options).await?;
+
+        let html = match fetched.parse() {
+            Ok(feed) => {
+                return Ok(vec![Candidate {
+                    url: location(url, &fetched),
+                    title: feed.meta.title,
+                    media_type: None,
+                }]);
+            }
+
+            Err(_) => String::from_utf8_lossy(&fetched.body).into_owned(),
+        };
+
+        let linked = linked(&fetched.url, &html);
+        if !linked.is_empty() {
+            return Ok(linked);
+        }
+
+        let mut found = Vec::new();
+        for path in COMMON_PATHS {
+            let Ok(probe) = fetched.url.join(path) else {
+                continue;
+            };
+
+            let Ok(fetched) = self.fetch_with(probe.clone(), options).await else {
+                continue;
+            };
+
+            let Ok(feed) = fetched.parse() else {
+                continue;
+            };
+
+            let url = location(probe, &fetched);
+            if found.iter().any(|c: &Candidate| c.url == url) {
+                continue;
+            }
+
+            found.push(Candidate {
+                url,
+                title: feed.meta.title,
+                media_type: None,
+            });
+        }
+
+        Ok(found)
+    }
+}
+
+/// The url to track a feed by: where it moved if every redirect was permanent, otherwise the
+/// one asked for, as a temporary redirect's target may not last
+fn location(requested: Url, fetched: &Fetched) -> Url {
+    fetched.moved.clone().unwrap_or(requested)
+}
+
+fn linked(url: &Url, html: &str) -> Vec<Candidate> {
+    let doc = Html::parse_document(html);
+
+    let (Ok(links), Ok(base)) = (
+        Selector::parse("link[rel~=alternate][href]"),
+        Selector::parse("base[href]"),
+    ) else {
+        return Vec::new();
+    };
+
+    let base = doc
+        .select(&base)
+        .next()
+        .and_then(|base| base.value().attr("href"))
+        .and_then(|href| url.join(href).ok())
+        .unwrap_or_else(|| url.clone());
+
+    let mut found: Vec<Candidate> = Vec::new();
+    for link in doc.select(&links) {
+        let elem = link.value();
+
+        let Some(media_type) = elem.attr("type").map(str::to_ascii_lowercase) else {
+            continue;
+        };
+
+        if !FEED_TYPES.contains(&media_type.as_str()) {
+            continue;
+        }
+
+        let Some(href) = elem
+            .attr("href")
+            .and_then(|href| base.join(href.trim()).ok())
+        else {
+            continue;
+        };
+
+        if found.iter().any(|c| c.url == href) {
+            continue;
+        }
+
+        found.push(Candidate {
+            url: href,
+            title: elem.attr("title").map(str::to_string),
+            media_type: Some(media_type),
+        });
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    fn fetched(moved: Option<&str>) -> Fetched {
+        Fetched {
+            url: url("https://cdn.example.com/tmp/feed.xml"),
+            status: Some(200),
+            body: Vec::new(),
+            moved: moved.map(url),
+        }
+    }
+
+    #[test]
+    fn tracks_the_requested_url_past_temporary_redirects() {
+        let requested = url("https://example.com/feed.xml");
+
+        assert_eq!(location(requested.clone(), &fetched(None)), requested);
+        assert_eq!(
+            location(requested, &fetched(Some("https://example.org/feed.xml"))),
+            url("https://example.org/feed.xml")
+        );
+    }
+
+    #[test]
+    fn finds_linked_feeds() {
+        let html = r#"
+            <html><head>
+              <base href="/blog/">
+              <link rel="alternate" type="application/rss+xml" title="Posts" href="rss.xml">
+              <link rel="alternate" type="APPLICATION/ATOM+XML" href=" https://example.com/atom ">
+              <link rel="alternate" type="text/html" href="/other">
+              <link rel="alternate stylesheet" type="application/rss+xml" href="rss.xml">
+            </head></html>
+        "#;
+
+        let found = linked(&url("https://example.com/index.html"), html);
+        let urls: Vec<&str> = found.iter().map(|c| c.url.as_str()).collect();
+
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/blog/rss.xml",
+                "https://example.com/atom"
+            ]
+        );
+        assert_eq!(found[0].title.as_deref(), Some("Posts"));
+        assert_eq!(found[1].media_type.as_deref(), Some("application/atom+xml"));
+    }
+}
@@ -1,25 +1,161 @@
+use std::path::PathBuf;
+
+use reqwest::{
+    StatusCode,
+    header::{HeaderMap, LOCATION},
+};
+use tokio::io::AsyncReadExt;
 use url::Url;
 
-use crate::Feed;
+use crate::{Feed, db::types::RequestOptions};
+
+mod command;
+mod discover;
+mod request;
+mod source;
+
+pub use discover::Candidate;
+pub(crate) use request::Resolved;
+pub use source::Source;
+
+const MAX_REDIRECTS: usize = 10;
 
 #[derive(Clone)]
 pub struct Fetcher {
     pub client: reqwest::Client,
+    /// file secrets referenced as `cred:KEY` are read from
+    pub credentials: Option<PathBuf>,
+}
+
+pub struct Fetched {
+    /// the location the body was fetched from, after following redirects
+    pub url: Url,
+    /// the http status, absent for local files
+    pub status: Option<u16>,
+    pub body: Vec<u8>,
+    /// where the feed lives if every redirect followed was permanent
+    pub moved: Option<Url>,
 }
 
 impl Fetcher {
-    pub async fn fetch_items(&self, url: Url) -> crate::Result<Feed> {
-        let resp = self
-            .client
-            .get(url)
-            .send()
-            .await?
-            .error_for_status()?
-            .bytes()
-            .await?;
-
-        let bare_feed = feed_rs::parser::parse(&*resp)?;
-
-        Ok(bare_feed.into())
+    pub async fn fetch_items(&self, url: Url, options: &RequestOptions) -> crate::Result<Feed> {
+        self.fetch_with(url, options).await?.parse()
+    }
+
+    pub async fn fetch_source(&self, source: Source) -> crate::Result<Feed> {
+        match source {
+            Source::Url(url) => self.fetch_items(url, &RequestOptions::default()).await,
+            Source::Stdin => {
+                let mut body = Vec::new();
+                tokio::io::stdin().read_to_end(&mut body).await?;
+
+                Feed::parse(&body)
+            }
+        }
+    }
+
+    pub async fn fetch(&self, url: Url) -> crate::Result<Fetched> {
+        self.fetch_with(url, &RequestOptions::default()).await
+    }
+
+    /// Fetches a url, sending the feed's request options while redirects stay on the same origin
+    pub async fn fetch_with(&self, url: Url, options: &RequestOptions) -> crate::Result<Fetched> {
+        if url.scheme() == "exec" {
+            return self.run_command(url).await;
+        }
+
+        if url.scheme() == "file" {
+            let path = url
+                .to_file_path()
+                .map_err(|_| crate::Error::InvalidPath(url.to_string()))?;
+            let body = tokio::fs::read(path).await?;
+
+            return Ok(Fetched {
+                url,
+                status: None,
+                body,
+                moved: None,
+            });
+        }
+
+        let resolved = self.resolve(options).await?;
+        let (url, resp, moved) = self.get(url, &resolved, HeaderMap::new()).await?;
+        let status = resp.status();
+
+        let resp = resp.error_for_status()?;
+        let body = resp.bytes().await?.to_vec();
+        tracing::debug!(%url, bytes = body.len(), "fetched");
+
+        Ok(Fetched {
+            url,
+            status: Some(status.as_u16()),
+            body,
+            moved,
+        })
+    }
+
+    /// Sends a get request, following redirects and returning the final url and response.
+    ///
+    /// `moved` is only set when every redirect followed was permanent.
+    pub(crate) async fn get(
+        &self,
+        url: Url,
+        resolved: &Resolved,
+        headers: HeaderMap,
+    ) -> crate::Result<(Url, reqwest::Response, Option<Url>)> {
+        let origin = url.origin();
+
+        let mut url = url;
+        let mut moved = None;
+        let mut permanent = true;
+
+        for _ in 0..=MAX_REDIRECTS {
+            let mut req = self.client.get(url.clone()).headers(headers.clone());
+            if url.origin() == origin {
+                req = resolved.apply(req);
+            }
+
+            let resp = req.send().await?;
+            let status = resp.status();
+            tracing::debug!(%url, status = status.as_u16(), "requested");
+
+            if status.is_redirection()
+                && let Some(location) = resp.headers().get(LOCATION)
+            {
+                let next = location
+                    .to_str()
+                    .ok()
+                    .and_then(|location| url.join(location).ok())
+                    .ok_or_else(|| {
+                        crate::Error::Redirect(format!("invalid location from {url}"))
+                    })?;
+
+                permanent &= matches!(
+                    status,
+                    StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT
+                );
+
+                if permanent {
+                    moved = Some(next.clone());
+                }
+
+                tracing::debug!(from = %url, to = %next, permanent, "following redirect");
+
+                url = next;
+                continue;
+            }
+
+            return Ok((url, resp, moved));
+        }
+
+        Err(crate::Error::Redirect(format!(
+            "too many redirects from {url}"
+        )))
+    }
+}
+
+impl Fetched {
+    pub fn parse(&self) -> crate::Result<Feed> {
+        Feed::parse(&self.body)
     }
 }
@@ -0,0 +1,236 @@
+use std::path::Path;
+
+use reqwest::{RequestBuilder, header::COOKIE};
+
+use crate::{
+    db::types::{RequestOptions, Secret},
+    fetcher::Fetcher,
+};
+
+/// Request options with their secrets looked up
+#[derive(Default)]
+pub(crate) struct Resolved {
+    headers: Vec<(String, String)>,
+    basic: Option<(String, String)>,
+    bearer: Option<String>,
+    cookie: Option<String>,
+}
+
+impl Fetcher {
+    pub(crate) async fn resolve(&self, options: &RequestOptions) -> crate::Result<Resolved> {
+        if options.is_empty() {
+            return Ok(Resolved::default());
+        }
+
+        let creds = Credentials::load(self.credentials.as_deref()).await?;
+
+        let basic = match &options.basic {
+            Some(basic) => Some((basic.user.clone(), creds.get(&basic.password)?)),
+            None => None,
+        };
+
+        let bearer = match &options.bearer {
+            Some(bearer) => Some(creds.get(bearer)?),
+            None => None,
+        };
+
+        let cookies = options
+            .cookies
+            .iter()
+            .map(|cookie| creds.get(cookie))
+            .collect::<crate::Result<Vec<_>>>()?;
+
+        Ok(Resolved {
+            headers: options
+                .headers
+                .iter()
+                .map(|header| (header.name.clone(), header.value.clone()))
+                .collect(),
+            basic,
+            bearer,
+            cookie: (!cookies.is_empty()).then(|| cookies.join("; ")),
+        })
+    }
+}
+
+impl Resolved {
+    pub(crate) fn apply(&self, mut req: RequestBuilder) -> RequestBuilder {
+        for (name, value) in &self.headers {
+            req = req.header(name, value);
+        }
+
+        if let Some((user, password)) = &self.basic {
+            req = req.basic_auth(user, Some(password));
+        }
+
+        if let Some(token) = &self.bearer {
+            req = req.bearer_auth(token);
+        }
+
+        if let Some(cookie) = &self.cookie {
+            req = req.header(COOKIE, cookie);
+        }
+
+        req
+    }
+}
+
+/// The credentials file, `key = value` lines with `#` comments
+#[derive(Default)]
+struct Credentials {
+    entries: Vec<(String, String)>,
+}
+
+impl Credentials {
+    async fn load(path: Option<&Path>) -> crate::Result<Credentials> {
+        let Some(path) = path else {
+            return Ok(Credentials::default());
+        };
+
+        let content = match tokio::fs::read_to_string(path).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Credentials::default());
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let entries = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .collect();
+
+        Ok(Credentials { entries })
+    }
+
+    fn get(&self, secret: &Secret) -> crate::Result<String> {
+        let value = match secret {
+            Secret::Env(name) => std::env::var(name).ok(),
+            Secret::Cred(key) => self
+                .entries
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.clone()),
+        };
+
+        value.ok_or_else(|| crate::Error::Secret(secret.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::types::{Basic, Header},
+        testing::Scratch,
+    };
+
+    fn fetcher(credentials: Option<std::path::PathBuf>) -> Fetcher {
+        Fetcher {
+            client: reqwest::Client::new(),
+            credentials,
+        }
+    }
+
+    #[test]
+    fn parses_options() {
+        assert_eq!(
+            "X-Api-Version : 2 ".parse::<Header>().unwrap(),
+            Header {
+                name: "X-Api-Version".into(),
+                value: "2".into(),
+            }
+        );
+        assert_eq!(
+            "Accept: text/html; q=0.9".parse::<Header>().unwrap().value,
+            "text/html; q=0.9"
+        );
+        assert!("no colon".parse::<Header>().is_err());
+        assert!("bad name: 1".parse::<Header>().is_err());
+
+        assert_eq!(
+            "ann:cred:feeds".parse::<Basic>().unwrap(),
+            Basic {
+                user: "ann".into(),
+                password: Secret::Cred("feeds".into()),
+            }
+        );
+        assert!("ann".parse::<Basic>().is_err());
+        assert!("ann:hunter2".parse::<Basic>().is_err());
+
+        assert_eq!(
+            "env:TOKEN".parse::<Secret>().unwrap(),
+            Secret::Env("TOKEN".into())
+        );
+        assert_eq!(
+            "cred:a:b".parse::<Secret>().unwrap().to_string(),
+            "cred:a:b"
+        );
+        for invalid in ["env:", "cred:", "TOKEN", "file:token"] {
+            assert!(invalid.parse::<Secret>().is_err(), "{invalid}");
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_the_credentials_file() {
+        let scratch = Scratch::new();
+        let path = scratch.write(
+            "credentials",
+            "# feeds\n\n  token = abc=def  \nsession=s1\n# cookie = commented\nnot a pair\n",
+        );
+        let fetcher = fetcher(Some(path));
+
+        let resolved = fetcher
+            .resolve(&RequestOptions {
+                headers: vec!["X-A: 1".parse().unwrap()],
+                basic: Some("ann:cred:token".parse().unwrap()),
+                bearer: Some("cred:session".parse().unwrap()),
+                cookies: vec![
+                    "cred:session".parse().unwrap(),
+                    "cred:token".parse().unwrap(),
+                ],
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(resolved.headers, [("X-A".to_string(), "1".to_string())]);
+        assert_eq!(
+            resolved.basic,
+            Some(("ann".to_string(), "abc=def".to_string()))
+        );
+        assert_eq!(resolved.bearer.as_deref(), Some("s1"));
+        assert_eq!(resolved.cookie.as_deref(), Some("s1; abc=def"));
+    }
+
+    #[tokio::test]
+    async fn fails_on_missing_secrets() {
+        let scratch = Scratch::new();
+        let path = scratch.write("credentials", "# cookie = commented\n");
+
+        for secret in ["cred:cookie", "cred:absent", "env:CYND_TEST_UNSET_SECRET"] {
+            let options = RequestOptions {
+                bearer: Some(secret.parse().unwrap()),
+                ..Default::default()
+            };
+            let err = fetcher(Some(path.clone()))
+                .resolve(&options)
+                .await
+                .err()
+                .unwrap();
+            assert!(matches!(err, crate::Error::Secret(s) if s == secret));
+        }
+
+        // a credentials file that doesn't exist holds no secrets
+        let options = RequestOptions {
+            bearer: Some("cred:token".parse().unwrap()),
+            ..Default::default()
+        };
+        let missing = fetcher(Some(scratch.dir("empty").join("credentials")));
+        assert!(missing.resolve(&options).await.is_err());
+        assert!(fetcher(None).resolve(&options).await.is_err());
+        assert!(fetcher(None).resolve(&Default::default()).await.is_ok());
+    }
+}
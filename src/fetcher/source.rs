@@ -0,0 +1,112 @@
+use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
+use url::Url;
+
+/// What `exec:` commands get escaped with: urls leave a literal `%` alone, making it ambiguous
+/// with their own escapes, and drop tabs and newlines outright
+const COMMAND: &AsciiSet = &CONTROLS.add(b'%');
+
+/// Where a feed is read from on the command line: a url, a local path or `-` for stdin
+#[derive(Clone, Debug)]
+pub enum Source {
+    Url(Url),
+    Stdin,
+}
+
+impl Source {
+    pub fn into_url(self) -> Option<Url> {
+        match self {
+            Source::Url(url) => Some(url),
+            Source::Stdin => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "-" {
+            return Ok(Source::Stdin);
+        }
+
+        if let Some(command) = s.strip_prefix("exec:") {
+            return Url::parse(&format!("exec:{}", utf8_percent_encode(command, COMMAND)))
+                .map(Source::Url)
+                .map_err(|err| format!("invalid command {command}: {err}"));
+        }
+
+        // single letter schemes are windows drive letters rather than urls
+        if let Ok(url) = Url::parse(s)
+            && url.scheme().len() > 1
+        {
+            return Ok(Source::Url(url));
+        }
+
+        let path = std::path::absolute(s).map_err(|err| format!("invalid path {s}: {err}"))?;
+
+        Url::from_file_path(&path)
+            .map(Source::Url)
+            .map_err(|_| format!("invalid path {s}"))
+    }
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Url(url) => write!(f, "{url}"),
+            Source::Stdin => write!(f, "-"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> String {
+        match s.parse::<Source>().unwrap() {
+            Source::Url(url) => url.to_string(),
+            Source::Stdin => panic!("{s} parsed as stdin"),
+        }
+    }
+
+    #[test]
+    fn reads_dash_as_stdin() {
+        assert!(matches!("-".parse(), Ok(Source::Stdin)));
+        assert_eq!(Source::Stdin.to_string(), "-");
+        assert!(Source::Stdin.into_url().is_none());
+    }
+
+    #[test]
+    fn keeps_urls() {
+        assert_eq!(
+            url("https://example.com/feed.xml"),
+            "https://example.com/feed.xml"
+        );
+        assert_eq!(url("file:///tmp/feed.xml"), "file:///tmp/feed.xml");
+        assert_eq!(url("lua://releases"), "lua://releases");
+        assert_eq!(url("exec:cat feed.xml"), "exec:cat feed.xml");
+    }
+
+    #[test]
+    fn makes_paths_absolute() {
+        let cwd = std::env::current_dir().unwrap();
+
+        assert_eq!(url("/tmp/feed.xml"), "file:///tmp/feed.xml");
+        assert_eq!(
+            url("feeds/my feed.xml"),
+            Url::from_file_path(cwd.join("feeds/my feed.xml"))
+                .unwrap()
+                .to_string()
+        );
+        assert!(url("feeds/my feed.xml").ends_with("/feeds/my%20feed.xml"));
+        // a drive letter rather than a scheme
+        assert!(url("c:feed.xml").starts_with("file:///"));
+    }
+
+    #[test]
+    fn displays_the_url() {
+        let source: Source = "https://example.com/a b".parse().unwrap();
+        assert_eq!(source.to_string(), "https://example.com/a%20b");
+    }
+}
@@ -1,29 +1,64 @@
 use notify_rust::Notification;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     FeedItem,
-    feed::FeedMeta,
+    feed::{FeedMeta, content::ALERT_EXCERPT},
     interp::{Instruction, InterpInst},
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Alert {
     pub summary: Option<String>,
     pub message: Option<String>,
 }
 
 impl InterpInst for Alert {
-    fn run(&self, _: &FeedMeta, item: &FeedItem, _: &super::Interp) -> crate::Result<()> {
-        let summary = self.summary.as_deref().unwrap_or("Cynd Alert");
-        let message = self
-            .message
-            .clone()
-            .unwrap_or_else(|| format!("{}", item.title.as_deref().unwrap_or(item.id.as_str())));
-
-        let _ = Notification::new().summary(summary).body(&message).show();
+    async fn run(&self, _: &FeedMeta, item: &FeedItem, _: &super::Interp) -> crate::Result<()> {
+        let (summary, message) = Alert::defaults(item);
+        self.show(&summary, &message);
 
         Ok(())
     }
+
+    fn describe(&self, _: &FeedMeta, item: &FeedItem, _: &super::Interp) -> String {
+        let (summary, message) = Alert::defaults(item);
+        self.describe_with(&summary, &message)
+    }
+}
+
+impl Alert {
+    /// The title and an excerpt of the item, or a generic summary when it has no text
+    fn defaults(item: &FeedItem) -> (String, String) {
+        let title = item.title.as_deref().unwrap_or(item.id.as_str());
+        let excerpt = item.excerpt(ALERT_EXCERPT);
+
+        if excerpt.is_empty() {
+            ("Cynd Alert".to_string(), title.to_string())
+        } else {
+            (title.to_string(), excerpt)
+        }
+    }
+
+    fn resolve<'a>(&'a self, summary: &'a str, message: &'a str) -> (&'a str, &'a str) {
+        (
+            self.summary.as_deref().unwrap_or(summary),
+            self.message.as_deref().unwrap_or(message),
+        )
+    }
+
+    pub(crate) fn show(&self, summary: &str, message: &str) {
+        let (summary, message) = self.resolve(summary, message);
+
+        let _ = Notification::new().summary(summary).body(message).show();
+    }
+
+    /// What [`Alert::show`] would put on screen
+    pub(crate) fn describe_with(&self, summary: &str, message: &str) -> String {
+        let (summary, message) = self.resolve(summary, message);
+
+        format!("alert {summary:?}: {message:?}")
+    }
 }
 
 impl std::fmt::Display for Alert {
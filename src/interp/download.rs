@@ -0,0 +1,529 @@
+use std::path::{Path, PathBuf};
+
+use reqwest::{
+    StatusCode,
+    header::{CONTENT_RANGE, HeaderMap, HeaderValue, RANGE},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+use url::Url;
+
+use crate::{
+    Error, FeedItem,
+    db::types,
+    feed::FeedMeta,
+    fetcher::Resolved,
+    interp::{Instruction, InterpInst},
+};
+
+/// Largest enclosure fetched unless the script raises `max_size`
+pub(crate) const DEFAULT_MAX_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Download {
+    pub dir: PathBuf,
+    pub filename: Option<String>,
+    pub max_size: u64,
+}
+
+impl InterpInst for Download {
+    async fn run(
+        &self,
+        _: &FeedMeta,
+        item: &FeedItem,
+        interp: &super::Interp,
+    ) -> crate::Result<()> {
+        let Some(media) = item.media.first() else {
+            return Ok(());
+        };
+
+        // a failed download shouldn't stop the rest of the program
+        if let Err(err) = self.fetch(&media.url, item, interp).await {
+            tracing::warn!(url = %media.url, "failed to download: {err}");
+        }
+
+        Ok(())
+    }
+
+    fn describe(&self, _: &FeedMeta, item: &FeedItem, interp: &super::Interp) -> String {
+        let Some(media) = item.media.first() else {
+            return "download nothing, the item has no enclosure".to_string();
+        };
+
+        let path = match Url::parse(&media.url) {
+            Ok(url) => self.path(&interp.url, &url, item).display().to_string(),
+            Err(err) => format!("nowhere, the url is invalid: {err}"),
+        };
+
+        let mut described = format!("download {} to {path}", media.url);
+        if self.max_size != DEFAULT_MAX_SIZE {
+            described.push_str(&format!(" up to {} bytes", self.max_size));
+        }
+
+        described
+    }
+}
+
+impl Download {
+    /// Where the enclosure at `url` of an item of `feed` is saved
+    fn path(&self, feed: &str, url: &Url, item: &FeedItem) -> PathBuf {
+        let filename = match &self.filename {
+            Some(filename) => filename.replace('/', "_"),
+            None => format!("{}-{}", tag(feed, &item.id), filename(url, item)),
+        };
+
+        self.dir.join(filename)
+    }
+
+    async fn fetch(&self, url: &str, item: &FeedItem, interp: &super::Interp) -> crate::Result<()> {
+        let parsed =
+            Url::parse(url).map_err(|err| Error::Download(format!("invalid url {url}: {err}")))?;
+
+        if let Some(done) = interp
+            .conn
+            .download(interp.url.clone(), item.id.clone(), url.to_string())
+            .await?
+            && fs::try_exists(&done.path).await.unwrap_or(false)
+        {
+            return Ok(());
+        }
+
+        let path = self.path(&interp.url, &parsed, item);
+        let part = suffixed(&path, ".part");
+        let source = suffixed(&path, ".part.url");
+
+        fs::create_dir_all(&self.dir).await?;
+
+        let offset = resumable(&part, &source, url).await;
+        if offset == 0 {
+            fs::write(&source, url).await?;
+        }
+
+        let (size, sha256) = match self.transfer(&parsed, &part, offset, interp).await? {
+            Some(done) => done,
+            None => {
+                tracing::info!(%url, "server can't resume the partial download, starting over");
+                self.transfer(&parsed, &part, 0, interp)
+                    .await?
+                    .ok_or_else(|| Error::Download(format!("{url} couldn't be fetched whole")))?
+            }
+        };
+
+        fs::rename(&part, &path).await?;
+        let _ = fs::remove_file(&source).await;
+
+        interp
+            .conn
+            .record_download(
+                interp.url.clone(),
+                item.id.clone(),
+                url.to_string(),
+                types::Download {
+                    path: path.display().to_string(),
+                    bytes: size as i64,
+                    sha256,
+                },
+            )
+            .await
+    }
+
+    /// Fetches the enclosure into `part`, continuing from `offset` bytes already there.
+    ///
+    /// Returns the size and sha256 of the whole file, or nothing when the server can't pick up
+    /// at `offset` and the download has to start over.
+    async fn transfer(
+        &self,
+        url: &Url,
+        part: &Path,
+        offset: u64,
+        interp: &super::Interp,
+    ) -> crate::Result<Option<(u64, String)>> {
+        let mut headers = HeaderMap::new();
+        if offset > 0
+            && let Ok(range) = HeaderValue::from_str(&format!("bytes={offset}-"))
+        {
+            headers.insert(RANGE, range);
+        }
+
+        let (_, resp, _) = interp
+            .fetcher
+            .get(url.clone(), &Resolved::default(), headers)
+            .await?;
+
+        let mut hasher = Sha256::new();
+        let range = ContentRange::parse(resp.headers());
+
+        // nothing left past the offset, which only means done when that's the whole length
+        if offset > 0 && resp.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            if range.and_then(|range| range.total) != Some(offset) {
+                return Ok(None);
+            }
+
+            let size = hash_file(part, &mut hasher).await?;
+            return Ok(Some((size, hex::encode(hasher.finalize()))));
+        }
+
+        let mut resp = resp.error_for_status()?;
+
+        // servers that ignore the range send everything again
+        let (mut file, mut size) = if resp.status() == StatusCode::PARTIAL_CONTENT {
+            if offset == 0 || range.and_then(|range| range.start) != Some(offset) {
+                return Ok(None);
+            }
+
+            let size = hash_file(part, &mut hasher).await?;
+            if size != offset {
+                return Ok(None);
+            }
+
+            (OpenOptions::new().append(true).open(part).await?, size)
+        } else {
+            (File::create(part).await?, 0)
+        };
+
+        if let Some(len) = resp.content_length()
+            && size + len > self.max_size
+        {
+            return Err(Error::Download(format!(
+                "{url} is {} bytes, over the {} byte limit",
+                size + len,
+                self.max_size
+            )));
+        }
+
+        while let Some(chunk) = resp.chunk().await? {
+            size += chunk.len() as u64;
+            if size > self.max_size {
+                drop(file);
+                let _ = fs::remove_file(part).await;
+
+                return Err(Error::Download(format!(
+                    "{url} is over the {} byte limit",
+                    self.max_size
+                )));
+            }
+
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+
+        file.flush().await?;
+
+        Ok(Some((size, hex::encode(hasher.finalize()))))
+    }
+}
+
+/// The `Content-Range` of a response, `bytes start-end/total` or `bytes */total`
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ContentRange {
+    start: Option<u64>,
+    total: Option<u64>,
+}
+
+impl ContentRange {
+    fn parse(headers: &HeaderMap) -> Option<ContentRange> {
+        let value = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+        let (range, total) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+
+        let start = match range.trim() {
+            "*" => None,
+            range => Some(range.split_once('-')?.0.trim().parse().ok()?),
+        };
+
+        Some(ContentRange {
+            start,
+            total: total.trim().parse().ok(),
+        })
+    }
+}
+
+/// How much of a partial download to keep, nothing when it belongs to another url
+async fn resumable(part: &Path, source: &Path, url: &str) -> u64 {
+    let Ok(meta) = fs::metadata(part).await else {
+        return 0;
+    };
+
+    match fs::read_to_string(source).await {
+        Ok(started) if started == url => meta.len(),
+        _ => {
+            tracing::warn!(part = %part.display(), "not resuming a partial download of another url");
+            0
+        }
+    }
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+async fn hash_file(path: &Path, hasher: &mut Sha256) -> crate::Result<u64> {
+    let mut file = File::open(path).await?;
+    let mut buf = vec![0; 64 * 1024];
+    let mut size = 0;
+
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(size);
+        }
+
+        hasher.update(&buf[..read]);
+        size += read as u64;
+    }
+}
+
+/// A short hash of the feed and item, keeping enclosures with the same name apart
+fn tag(feed: &str, guid: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(feed.as_bytes());
+    hasher.update([0]);
+    hasher.update(guid.as_bytes());
+
+    hex::encode(&hasher.finalize()[..4])
+}
+
+/// The last segment of the enclosure url, or the item id when the url has no path
+fn filename(url: &Url, item: &FeedItem) -> String {
+    let segment = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| percent_encoding::percent_decode_str(segment).decode_utf8_lossy());
+
+    match segment {
+        Some(segment) => segment.replace('/', "_"),
+        None => item.id.replace(['/', ':'], "_"),
+    }
+}
+
+impl std::fmt::Display for Download {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "download(dir = \"{}\"", self.dir.display())?;
+
+        if let Some(filename) = &self.filename {
+            write!(f, " filename = \"{filename}\"")?;
+        }
+
+        if self.max_size != DEFAULT_MAX_SIZE {
+            write!(f, " max_size = {}", self.max_size)?;
+        }
+
+        write!(f, ")")
+    }
+}
+
+impl From<Download> for Instruction {
+    fn from(value: Download) -> Self {
+        Instruction::Download(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        extract::Path as Route,
+        http::{HeaderMap as Headers, StatusCode as Status},
+        response::IntoResponse,
+        routing::get,
+    };
+
+    use super::*;
+    use crate::testing::{Scratch, item};
+
+    const FEED: &str = "https://example.com/feed.xml";
+
+    fn body() -> Vec<u8> {
+        (0..1000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn sha256(bytes: &[u8]) -> String {
+        hex::encode(Sha256::digest(bytes))
+    }
+
+    fn download(dir: &Path) -> Download {
+        Download {
+            dir: dir.to_path_buf(),
+            filename: None,
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    /// Serves `body()`, honouring ranges unless the mode says to misbehave
+    async fn serve(Route(mode): Route<String>, headers: Headers) -> axum::response::Response {
+        let body = body();
+        let len = body.len();
+
+        let Some(start) = headers
+            .get(RANGE)
+            .and_then(|range| range.to_str().ok())
+            .and_then(|range| range.strip_prefix("bytes="))
+            .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok())
+        else {
+            return body.into_response();
+        };
+
+        match mode.as_str() {
+            "shifted" => (
+                Status::PARTIAL_CONTENT,
+                [("content-range", format!("bytes 0-{}/{len}", len - 1))],
+                body,
+            )
+                .into_response(),
+
+            "unsatisfiable" => (
+                Status::RANGE_NOT_SATISFIABLE,
+                [("content-range", format!("bytes */{}", len * 2))],
+            )
+                .into_response(),
+
+            _ if start >= len => (
+                Status::RANGE_NOT_SATISFIABLE,
+                [("content-range", format!("bytes */{len}"))],
+            )
+                .into_response(),
+
+            _ => (
+                Status::PARTIAL_CONTENT,
+                [("content-range", format!("bytes {start}-{}/{len}", len - 1))],
+                body[start..].to_vec(),
+            )
+                .into_response(),
+        }
+    }
+
+    async fn server() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let app = Router::new().route("/{mode}/episode.mp3", get(serve));
+
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        base
+    }
+
+    /// Leaves `len` bytes as a partial download started from `source`, the start of `body()` when
+    /// that's the url
+    fn partial(download: &Download, url: &str, guid: &str, len: usize, source: &str) -> PathBuf {
+        let path = download.path(FEED, &Url::parse(url).unwrap(), &item(guid));
+        let bytes = if source == url {
+            body()[..len].to_vec()
+        } else {
+            vec![0xff; len]
+        };
+
+        std::fs::write(suffixed(&path, ".part"), bytes).unwrap();
+        std::fs::write(suffixed(&path, ".part.url"), source).unwrap();
+        path
+    }
+
+    #[test]
+    fn names_files_apart_per_item() {
+        let download = download(Path::new("/downloads"));
+        let url = Url::parse("https://cdn.example.com/a/episode.mp3").unwrap();
+
+        let first = download.path(FEED, &url, &item("1"));
+        let second = download.path(FEED, &url, &item("2"));
+        let elsewhere = download.path("https://example.org/feed.xml", &url, &item("1"));
+
+        assert_ne!(first, second);
+        assert_ne!(first, elsewhere);
+        assert_eq!(first, download.path(FEED, &url, &item("1")));
+        assert!(first.to_string_lossy().ends_with("-episode.mp3"));
+
+        let named = Download {
+            filename: Some("shows/latest.mp3".to_string()),
+            ..download
+        };
+        assert_eq!(
+            named.path(FEED, &url, &item("1")),
+            Path::new("/downloads/shows_latest.mp3")
+        );
+    }
+
+    #[test]
+    fn parses_content_range() {
+        let range = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_RANGE, HeaderValue::from_str(value).unwrap());
+            ContentRange::parse(&headers)
+        };
+
+        assert_eq!(
+            range("bytes 100-199/200"),
+            Some(ContentRange {
+                start: Some(100),
+                total: Some(200)
+            })
+        );
+        assert_eq!(
+            range("bytes */200"),
+            Some(ContentRange {
+                start: None,
+                total: Some(200)
+            })
+        );
+        assert_eq!(
+            range("bytes 0-99/*"),
+            Some(ContentRange {
+                start: Some(0),
+                total: None
+            })
+        );
+        assert_eq!(range("items 0-1/2"), None);
+        assert_eq!(ContentRange::parse(&HeaderMap::new()), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn resumes_only_what_the_server_continues() {
+        let scratch = Scratch::new();
+        let client = scratch
+            .client("return { process = function() end }", 1)
+            .await;
+        let interp = client.interp(FEED.to_string());
+        let download = download(&scratch.dir("downloads"));
+        let base = server().await;
+        let whole = sha256(&body());
+
+        let cases = [
+            // picks up where the partial file ends
+            ("honest", 400, true),
+            // the partial file is already everything
+            ("honest", 1000, true),
+            // started over, as the server sends from the start
+            ("shifted", 400, true),
+            // started over, as the partial file isn't the whole length
+            ("unsatisfiable", 400, true),
+            // started over, as the partial file is of another url
+            ("honest", 400, false),
+        ];
+
+        for (i, (mode, len, same)) in cases.into_iter().enumerate() {
+            let url = format!("{base}/{mode}/episode.mp3");
+            let guid = i.to_string();
+            let source = if same {
+                url.clone()
+            } else {
+                format!("{base}/other.mp3")
+            };
+
+            let path = partial(&download, &url, &guid, len, &source);
+            let written = download.fetch(&url, &item(&guid), &interp).await;
+
+            assert!(written.is_ok(), "{mode} {len}: {written:?}");
+            assert_eq!(
+                sha256(&std::fs::read(&path).unwrap()),
+                whole,
+                "{mode} {len}"
+            );
+            assert!(!suffixed(&path, ".part").exists());
+            assert!(!suffixed(&path, ".part.url").exists());
+        }
+    }
+}
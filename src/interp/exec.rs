@@ -1,22 +1,84 @@
 use std::process::Command;
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
     FeedItem,
-    feed::FeedMeta,
+    feed::{FeedError, FeedMeta},
     interp::{Instruction, InterpInst},
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Exec {
     pub sh: String,
 }
 
+/// Variables set for a command on top of the environment of cynd
+pub(crate) type Env = Vec<(&'static str, String)>;
+
 impl InterpInst for Exec {
-    fn run(&self, _: &FeedMeta, _: &FeedItem, _: &super::Interp) -> crate::Result<()> {
-        let _ = Command::new("sh").arg("-c").arg(&self.sh).spawn();
+    async fn run(
+        &self,
+        meta: &FeedMeta,
+        item: &FeedItem,
+        interp: &super::Interp,
+    ) -> crate::Result<()> {
+        self.spawn(&Exec::item_env(&interp.url, meta, item));
 
         Ok(())
     }
+
+    fn describe(&self, meta: &FeedMeta, item: &FeedItem, interp: &super::Interp) -> String {
+        self.command(&Exec::item_env(&interp.url, meta, item))
+    }
+}
+
+impl Exec {
+    /// The feed and item the command runs for, so it needn't splice them into the shell line
+    pub(crate) fn item_env(url: &str, meta: &FeedMeta, item: &FeedItem) -> Env {
+        let mut env = vec![("CYND_FEED", url.to_string())];
+
+        let optional = [
+            ("CYND_FEED_TITLE", meta.title.as_deref()),
+            ("CYND_ITEM_ID", Some(item.id.as_str())),
+            ("CYND_ITEM_TITLE", item.title.as_deref()),
+            ("CYND_ITEM_LINK", item.link()),
+        ];
+        env.extend(
+            optional
+                .into_iter()
+                .filter_map(|(name, value)| Some((name, value?.to_string()))),
+        );
+
+        env
+    }
+
+    /// The feed and what went wrong fetching it, for commands run by `on_feed_error`
+    pub(crate) fn error_env(error: &FeedError) -> Env {
+        vec![
+            ("CYND_FEED", error.url.clone()),
+            ("CYND_ERROR_KIND", error.kind.clone()),
+            ("CYND_ERROR", error.message.clone()),
+        ]
+    }
+
+    /// The command [`Exec::spawn`] runs, with the variables it sets
+    pub(crate) fn command(&self, env: &Env) -> String {
+        let mut command = String::from("exec");
+        for (name, value) in env {
+            command.push_str(&format!(" {name}={value:?}"));
+        }
+
+        format!("{command} sh -c {:?}", self.sh)
+    }
+
+    pub(crate) fn spawn(&self, env: &Env) {
+        let _ = Command::new("sh")
+            .arg("-c")
+            .arg(&self.sh)
+            .envs(env.iter().map(|(name, value)| (name, value)))
+            .spawn();
+    }
 }
 
 impl std::fmt::Display for Exec {
@@ -30,3 +92,38 @@ impl From<Exec> for Instruction {
         Instruction::Exec(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        feed::Link,
+        testing::{item, meta},
+    };
+
+    #[test]
+    fn shows_the_variables_it_sets() {
+        let mut linked = item("urn:1");
+        linked.title = Some("A \"quoted\" title".to_string());
+        linked.links.push(Link {
+            href: "https://example.com/1".to_string(),
+            rel: None,
+            media_type: None,
+            title: None,
+        });
+
+        let env = Exec::item_env("https://example.com/feed", &meta(), &linked);
+        let exec = Exec {
+            sh: "notify \"$CYND_ITEM_TITLE\"".to_string(),
+        };
+
+        assert_eq!(
+            exec.command(&env),
+            r#"exec CYND_FEED="https://example.com/feed" CYND_FEED_TITLE="Feed" CYND_ITEM_ID="urn:1" CYND_ITEM_TITLE="A \"quoted\" title" CYND_ITEM_LINK="https://example.com/1" sh -c "notify \"$CYND_ITEM_TITLE\"""#
+        );
+
+        let env = Exec::item_env("https://example.com/feed", &meta(), &item("bare"));
+        let names: Vec<_> = env.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["CYND_FEED", "CYND_FEED_TITLE", "CYND_ITEM_ID"]);
+    }
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    FeedItem,
+    db::{Target, types},
+    feed::FeedMeta,
+    interp::{Instruction, InterpInst},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mark {
+    pub mark: types::Mark,
+}
+
+impl InterpInst for Mark {
+    async fn run(
+        &self,
+        _: &FeedMeta,
+        item: &FeedItem,
+        interp: &super::Interp,
+    ) -> crate::Result<()> {
+        let target = Target::Guid {
+            url: interp.url.clone(),
+            guid: item.id.clone(),
+        };
+
+        interp.conn.mark(target, self.mark).await?;
+
+        Ok(())
+    }
+
+    fn describe(&self, _: &FeedMeta, item: &FeedItem, interp: &super::Interp) -> String {
+        format!("mark {} as {} of {}", item.id, self.mark, interp.url)
+    }
+}
+
+impl std::fmt::Display for Mark {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "mark({})", self.mark)
+    }
+}
+
+impl From<Mark> for Instruction {
+    fn from(value: Mark) -> Self {
+        Instruction::Mark(value)
+    }
+}
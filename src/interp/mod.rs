@@ -1,42 +1,199 @@
-use crate::{FeedItem, feed::FeedMeta};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    FeedItem,
+    db::Conn,
+    feed::{FeedError, FeedMeta},
+    fetcher::Fetcher,
+    metrics::Metrics,
+};
 
 mod alert;
+mod download;
 mod exec;
+mod mark;
 mod record;
 
 pub use alert::Alert;
+pub(crate) use download::DEFAULT_MAX_SIZE;
+pub use download::Download;
 pub use exec::Exec;
+pub use mark::Mark;
 pub use record::Record;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Program {
     pub instructions: Vec<Instruction>,
 }
 
-pub struct Interp {}
+pub struct Interp {
+    pub(crate) conn: Conn,
+    pub(crate) url: String,
+    pub(crate) fetcher: Fetcher,
+    pub(crate) mode: Mode,
+    pub(crate) metrics: Metrics,
+}
+
+/// What the interpreter does with a program
+#[derive(Clone, Debug, Default)]
+pub enum Mode {
+    /// perform every instruction
+    #[default]
+    Execute,
+    /// log what each instruction would do, without doing it
+    DryRun,
+    /// append each item and its program to a file as json lines, for `cynd replay`
+    Record(PathBuf),
+}
+
+/// An item and its program as saved in record mode
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Recorded {
+    pub url: String,
+    pub time: DateTime<Utc>,
+    pub meta: FeedMeta,
+    pub item: FeedItem,
+    pub program: Program,
+}
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum Instruction {
     Alert(Alert),
     Record(Record),
     Exec(Exec),
+    Mark(Mark),
+    Download(Download),
 }
 
 impl Interp {
-    pub fn run(&self, meta: &FeedMeta, item: &FeedItem, prog: &Program) -> crate::Result<()> {
+    pub async fn run(&self, meta: &FeedMeta, item: &FeedItem, prog: &Program) -> crate::Result<()> {
+        match &self.mode {
+            Mode::Execute => (),
+            Mode::DryRun => {
+                self.dry_run(meta, item, prog);
+                return Ok(());
+            }
+            Mode::Record(path) => return self.record(path, meta, item, prog).await,
+        }
+
+        for inst in &prog.instructions {
+            self.metrics.executed(&self.url, inst.name());
+
+            match inst {
+                Instruction::Alert(alert) => alert.run(meta, item, self).await?,
+                Instruction::Record(record) => record.run(meta, item, self).await?,
+                Instruction::Exec(exec) => exec.run(meta, item, self).await?,
+                Instruction::Mark(mark) => mark.run(meta, item, self).await?,
+                Instruction::Download(download) => download.run(meta, item, self).await?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Logs what each instruction would do under the `dry_run` target, in a span of the item
+    fn dry_run(&self, meta: &FeedMeta, item: &FeedItem, prog: &Program) {
+        let span = tracing::info_span!(
+            "item",
+            feed = %self.url,
+            id = %item.id,
+            title = item.title.as_deref().unwrap_or_default()
+        );
+        let _span = span.enter();
+
+        for inst in &prog.instructions {
+            let described = match inst {
+                Instruction::Alert(alert) => alert.describe(meta, item, self),
+                Instruction::Record(record) => record.describe(meta, item, self),
+                Instruction::Exec(exec) => exec.describe(meta, item, self),
+                Instruction::Mark(mark) => mark.describe(meta, item, self),
+                Instruction::Download(download) => download.describe(meta, item, self),
+            };
+
+            tracing::info!(target: "dry_run", "{described}");
+        }
+    }
+
+    /// Saves every item, even those without instructions, so a replay can evaluate them again
+    async fn record(
+        &self,
+        path: &PathBuf,
+        meta: &FeedMeta,
+        item: &FeedItem,
+        prog: &Program,
+    ) -> crate::Result<()> {
+        let recorded = Recorded {
+            url: self.url.clone(),
+            time: Utc::now(),
+            meta: meta.clone(),
+            item: item.clone(),
+            program: prog.clone(),
+        };
+
+        let mut line = serde_json::to_vec(&recorded).map_err(std::io::Error::other)?;
+        line.push(b'\n');
+
+        // a single append per item, so concurrent fetches don't interleave lines
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(&line).await?;
+
+        Ok(())
+    }
+
+    /// Runs the program produced by `on_feed_error`, skipping instructions that need an item.
+    ///
+    /// Record mode only saves items, so it describes these like a dry run.
+    pub async fn run_error(&self, error: &FeedError, prog: &Program) -> crate::Result<()> {
+        let message = format!("{}: {}", error.url, error.message);
+        let env = Exec::error_env(error);
+
+        if !matches!(self.mode, Mode::Execute) {
+            let span = tracing::info_span!("feed_error", feed = %error.url);
+            let _span = span.enter();
+
+            for inst in &prog.instructions {
+                let described = match inst {
+                    Instruction::Alert(alert) => alert.describe_with("Cynd Alert", &message),
+                    Instruction::Exec(exec) => exec.command(&env),
+                    Instruction::Record(_) | Instruction::Mark(_) | Instruction::Download(_) => {
+                        continue;
+                    }
+                };
+
+                tracing::info!(target: "dry_run", "{described}");
+            }
+
+            return Ok(());
+        }
+
         for inst in &prog.instructions {
             match inst {
-                Instruction::Alert(alert) => alert.run(meta, item, self)?,
-                Instruction::Record(record) => record.run(meta, item, self)?,
-                Instruction::Exec(exec) => exec.run(meta, item, self)?,
+                Instruction::Alert(alert) => alert.show("Cynd Alert", &message),
+                Instruction::Exec(exec) => exec.spawn(&env),
+                Instruction::Record(_) | Instruction::Mark(_) | Instruction::Download(_) => {
+                    continue;
+                }
             }
+
+            self.metrics.executed(&self.url, inst.name());
         }
         Ok(())
     }
 }
 
 trait InterpInst {
-    fn run(&self, meta: &FeedMeta, item: &FeedItem, interp: &Interp) -> crate::Result<()>;
+    async fn run(&self, meta: &FeedMeta, item: &FeedItem, interp: &Interp) -> crate::Result<()>;
+
+    /// What running the instruction would do, with everything it depends on resolved
+    fn describe(&self, meta: &FeedMeta, item: &FeedItem, interp: &Interp) -> String;
 }
 
 impl Program {
@@ -45,14 +202,35 @@ impl Program {
     }
 }
 
+impl Instruction {
+    /// The lua function emitting the instruction
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::Alert(_) => "alert",
+            Instruction::Record(_) => "record",
+            Instruction::Exec(_) => "exec",
+            Instruction::Mark(_) => "mark",
+            Instruction::Download(_) => "download",
+        }
+    }
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Instruction::Alert(alert) => write!(f, "{alert}"),
+            Instruction::Record(record) => write!(f, "{record}"),
+            Instruction::Exec(exec) => write!(f, "{exec}"),
+            Instruction::Mark(mark) => write!(f, "{mark}"),
+            Instruction::Download(download) => write!(f, "{download}"),
+        }
+    }
+}
+
 impl std::fmt::Display for Program {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for inst in &self.instructions {
-            match inst {
-                Instruction::Alert(alert) => writeln!(f, "  {alert}")?,
-                Instruction::Record(record) => writeln!(f, "  {record}")?,
-                Instruction::Exec(exec) => writeln!(f, "  {exec}")?,
-            }
+            writeln!(f, "  {inst}")?;
         }
 
         Ok(())
@@ -1,16 +1,35 @@
+use serde::{Deserialize, Serialize};
+
 use crate::{
     FeedItem,
+    db::Target,
     feed::FeedMeta,
     interp::{Instruction, InterpInst},
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Record {}
 
 impl InterpInst for Record {
-    fn run(&self, _: &FeedMeta, _: &FeedItem, _: &super::Interp) -> crate::Result<()> {
+    async fn run(
+        &self,
+        _: &FeedMeta,
+        item: &FeedItem,
+        interp: &super::Interp,
+    ) -> crate::Result<()> {
+        let target = Target::Guid {
+            url: interp.url.clone(),
+            guid: item.id.clone(),
+        };
+
+        interp.conn.record(target).await?;
+
         Ok(())
     }
+
+    fn describe(&self, _: &FeedMeta, item: &FeedItem, interp: &super::Interp) -> String {
+        format!("record {} of {}", item.id, interp.url)
+    }
 }
 
 impl std::fmt::Display for Record {
@@ -5,10 +5,21 @@ mod db;
 mod feed;
 mod fetcher;
 mod interp;
+mod metrics;
 mod runtime;
+mod spec;
+#[cfg(test)]
+mod testing;
 
-pub use client::Client;
+pub use client::{Client, ClientBuilder, FetchLimits};
+pub use db::types::{
+    Basic, Feed as TrackedFeed, Header, Health, Item as StoredItem, ItemFilter, ItemState, Mark,
+    RequestOptions, Retention, SearchHit, SearchQuery, Secret,
+};
 pub use feed::{Feed, FeedItem};
+pub use fetcher::{Candidate, Source};
+pub use interp::{Mode, Recorded};
+pub use spec::{Fixture, Line, Meta, Mismatch, Outcome, Spec};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -32,6 +43,61 @@ pub enum Error {
 
     #[error("runtime quit (send)")]
     RuntimeQuitSend,
+
+    #[error("failed to follow redirect: {0}")]
+    Redirect(String),
+
+    #[error("failed to read: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("not a local path: {0}")]
+    InvalidPath(String),
+
+    #[error("feed command failed: {0}")]
+    Command(String),
+
+    #[error("source failed: {0}")]
+    Script(String),
+
+    #[error("missing secret {0}")]
+    Secret(String),
+
+    #[error("download failed: {0}")]
+    Download(String),
+
+    #[error("script failed: {0}")]
+    Eval(String),
+
+    #[error("invalid spec {0}")]
+    Spec(String),
+
+    #[error("invalid recording {0}")]
+    Replay(String),
+
+    #[error("failed to collect metrics: {0}")]
+    Metrics(#[from] prometheus::Error),
+}
+
+impl Error {
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Error::Reqwest(_)
+            | Error::Redirect(_)
+            | Error::Io(_)
+            | Error::InvalidPath(_)
+            | Error::Command(_)
+            | Error::Script(_) => "fetch",
+            Error::FeedParse(_) => "parse",
+            _ => "other",
+        }
+    }
+
+    pub(crate) fn status(&self) -> Option<u16> {
+        match self {
+            Error::Reqwest(err) => err.status().map(|status| status.as_u16()),
+            _ => None,
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,77 @@
+use std::{fs::OpenOptions, path::PathBuf, sync::Mutex};
+
+use eyre::WrapErr;
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry, layer::SubscriberExt, util::SubscriberInitExt,
+};
+
+/// Filters for each count of `-v`, `RUST_LOG` overriding them.
+///
+/// cynd's own spans are info, so that is as quiet as it gets without losing the feed and item
+/// script logs are attached to.
+const FILTERS: [&str; 4] = [
+    "warn,cyndikator=info,lua=info,dry_run=info",
+    "info,cyndikator=debug,lua=debug,dry_run=info",
+    "debug,cyndikator=trace,lua=trace,dry_run=info",
+    "trace",
+];
+
+#[derive(clap::Args)]
+pub struct LogArgs {
+    /// log more, up to -vvv
+    #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    #[clap(long, global = true, value_enum, default_value = "text")]
+    log_format: LogFormat,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+pub enum Sink {
+    Stderr,
+    Journald,
+    File(PathBuf),
+}
+
+type Boxed = Box<dyn Layer<Registry> + Send + Sync>;
+
+pub fn init(args: &LogArgs, sink: Sink) -> eyre::Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new(FILTERS[usize::from(args.verbose).min(FILTERS.len() - 1)])
+    });
+
+    let layer: Boxed = match sink {
+        Sink::Stderr => format(args.log_format, std::io::stderr, true),
+        // journald keeps its own timestamps and levels
+        Sink::Journald => tracing_journald::layer()
+            .wrap_err("failed to connect to journald")?
+            .boxed(),
+        Sink::File(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            format(args.log_format, Mutex::new(file), false)
+        }
+    };
+
+    tracing_subscriber::registry()
+        .with(layer.with_filter(filter))
+        .try_init()?;
+
+    Ok(())
+}
+
+fn format<W>(format: LogFormat, writer: W, ansi: bool) -> Boxed
+where
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+
+    match format {
+        LogFormat::Text => layer.with_ansi(ansi).boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
@@ -1,18 +1,21 @@
 use clap::Parser;
+use cyndikator::{Client, ClientBuilder};
 
 use crate::cmd::Cli;
 
 mod cmd;
+mod logging;
 
 trait Runner {
-    async fn run(self) -> eyre::Result<()>;
+    async fn run(self, client: ClientBuilder) -> eyre::Result<()>;
 }
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     let cli = Cli::parse();
+    logging::init(&cli.log, cli.sink())?;
 
-    cli.run().await?;
+    cli.run(Client::builder()).await?;
 
     Ok(())
 }
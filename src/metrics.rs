@@ -0,0 +1,189 @@
+use std::time::Duration;
+
+use prometheus::{Histogram, HistogramOpts, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+
+/// Buckets of fetch latency, in seconds
+const FETCH_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Buckets of the time `process` takes on an item, in seconds
+const EVAL_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0, 5.0,
+];
+
+/// Counters and histograms of what cynd does, rendered in the prometheus text format.
+///
+/// Counters are labelled by feed url, there being as many as tracked feeds. Histograms and the
+/// overdue gauge aren't, a set of buckets per feed costing more than it tells.
+#[derive(Clone)]
+pub(crate) struct Metrics {
+    registry: Registry,
+    fetches: IntCounterVec,
+    fetch_seconds: Histogram,
+    fetch_bytes: IntCounterVec,
+    items: IntCounterVec,
+    new_items: IntCounterVec,
+    eval_seconds: Histogram,
+    eval_errors: IntCounterVec,
+    instructions: IntCounterVec,
+    overdue: IntGauge,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> crate::Result<Metrics> {
+        let registry = Registry::new_custom(Some("cynd".to_string()), None)?;
+
+        let counter = |name: &str, help: &str, labels: &[&str]| -> crate::Result<IntCounterVec> {
+            let counter = IntCounterVec::new(Opts::new(name, help), labels)?;
+            registry.register(Box::new(counter.clone()))?;
+            Ok(counter)
+        };
+
+        let histogram = |name: &str, help: &str, buckets: &[f64]| -> crate::Result<Histogram> {
+            let opts = HistogramOpts::new(name, help).buckets(buckets.to_vec());
+            let histogram = Histogram::with_opts(opts)?;
+            registry.register(Box::new(histogram.clone()))?;
+            Ok(histogram)
+        };
+
+        let metrics = Metrics {
+            fetches: counter(
+                "fetches_total",
+                "Fetches of tracked feeds by outcome, ok or the kind of error",
+                &["feed", "outcome"],
+            )?,
+            fetch_seconds: histogram(
+                "fetch_duration_seconds",
+                "Time taken fetching and storing a feed",
+                FETCH_BUCKETS,
+            )?,
+            fetch_bytes: counter(
+                "fetch_bytes_total",
+                "Bytes of feed bodies fetched",
+                &["feed"],
+            )?,
+            items: counter(
+                "items_total",
+                "Items parsed from fetched and pushed feeds",
+                &["feed"],
+            )?,
+            new_items: counter(
+                "new_items_total",
+                "Items not seen before, handed to process",
+                &["feed"],
+            )?,
+            eval_seconds: histogram(
+                "eval_duration_seconds",
+                "Time process spends on an item",
+                EVAL_BUCKETS,
+            )?,
+            eval_errors: counter(
+                "eval_errors_total",
+                "Items process failed on or ran out of its limits for",
+                &["feed"],
+            )?,
+            instructions: counter(
+                "instructions_total",
+                "Instructions executed by the lua function emitting them",
+                &["feed", "instruction"],
+            )?,
+            overdue: IntGauge::new(
+                "overdue_feeds",
+                "Feeds past their next fetch still waiting for their turn",
+            )?,
+            registry,
+        };
+        metrics
+            .registry
+            .register(Box::new(metrics.overdue.clone()))?;
+
+        Ok(metrics)
+    }
+
+    pub(crate) fn fetched(&self, feed: &str, outcome: &str, time: Duration, bytes: Option<u32>) {
+        self.fetches.with_label_values(&[feed, outcome]).inc();
+        self.fetch_seconds.observe(time.as_secs_f64());
+
+        if let Some(bytes) = bytes {
+            self.fetch_bytes
+                .with_label_values(&[feed])
+                .inc_by(bytes.into());
+        }
+    }
+
+    pub(crate) fn ingested(&self, feed: &str, items: usize, new: usize) {
+        self.items
+            .with_label_values(&[feed])
+            .inc_by(items.try_into().unwrap_or(u64::MAX));
+        self.new_items
+            .with_label_values(&[feed])
+            .inc_by(new.try_into().unwrap_or(u64::MAX));
+    }
+
+    pub(crate) fn evaluated(&self, feed: &str, time: Duration, failed: bool) {
+        self.eval_seconds.observe(time.as_secs_f64());
+
+        if failed {
+            self.eval_errors.with_label_values(&[feed]).inc();
+        }
+    }
+
+    pub(crate) fn executed(&self, feed: &str, instruction: &str) {
+        self.instructions
+            .with_label_values(&[feed, instruction])
+            .inc();
+    }
+
+    pub(crate) fn overdue(&self, feeds: usize) {
+        self.overdue.set(feeds.try_into().unwrap_or(i64::MAX));
+    }
+
+    /// Every series in the prometheus text exposition format
+    pub(crate) fn render(&self) -> crate::Result<String> {
+        Ok(TextEncoder::new().encode_to_string(&self.registry.gather())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_series() {
+        let metrics = Metrics::new().unwrap();
+        let feed = "https://example.com/feed";
+
+        metrics.fetched(feed, "ok", Duration::from_millis(300), Some(512));
+        metrics.fetched(feed, "timeout", Duration::from_secs(40), None);
+        metrics.ingested(feed, 10, 2);
+        metrics.evaluated(feed, Duration::from_millis(2), false);
+        metrics.evaluated(feed, Duration::from_millis(2), true);
+        metrics.executed(feed, "alert");
+        metrics.overdue(3);
+
+        let text = metrics.render().unwrap();
+        for line in [
+            r#"cynd_fetches_total{feed="https://example.com/feed",outcome="ok"} 1"#,
+            r#"cynd_fetches_total{feed="https://example.com/feed",outcome="timeout"} 1"#,
+            r#"cynd_fetch_bytes_total{feed="https://example.com/feed"} 512"#,
+            r#"cynd_items_total{feed="https://example.com/feed"} 10"#,
+            r#"cynd_new_items_total{feed="https://example.com/feed"} 2"#,
+            r#"cynd_eval_errors_total{feed="https://example.com/feed"} 1"#,
+            r#"cynd_instructions_total{feed="https://example.com/feed",instruction="alert"} 1"#,
+            r#"cynd_overdue_feeds 3"#,
+            r#"cynd_fetch_duration_seconds_bucket{le="0.5"} 1"#,
+            r#"cynd_fetch_duration_seconds_bucket{le="+Inf"} 2"#,
+            r#"cynd_fetch_duration_seconds_count 2"#,
+            r#"cynd_eval_duration_seconds_bucket{le="0.0025"} 2"#,
+            r#"cynd_eval_duration_seconds_count 2"#,
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {line} in\n{text}");
+        }
+
+        // histograms aren't split by feed
+        assert!(
+            !text
+                .lines()
+                .any(|l| l.contains("duration_seconds") && l.contains("feed="))
+        );
+    }
+}
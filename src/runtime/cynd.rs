@@ -0,0 +1,350 @@
+use regex::Regex as Pattern;
+use rlua::{Lua, Table, UserData, Value};
+use url::Url;
+
+use crate::{feed::content, runtime::json};
+
+/// Registry key of the lua function templates index values with
+const INDEX: &str = "cynd.index";
+
+/// A compiled regular expression handed to scripts by `cynd.regex`
+struct Regex(Pattern);
+
+/// Builds the `cynd` module, set as a global and returned by `require("cynd")`
+pub(crate) fn module(lua: &Lua) -> rlua::Result<Table<'_>> {
+    let module = lua.create_table()?;
+
+    module.set(
+        "regex",
+        lua.create_function(|_, pattern: String| {
+            Pattern::new(&pattern)
+                .map(Regex)
+                .map_err(|err| rlua::Error::runtime(format!("invalid regex {pattern}: {err}")))
+        })?,
+    )?;
+
+    let json = lua.create_table()?;
+    json.set(
+        "decode",
+        lua.create_function(|lua, body: rlua::String| json::decode(lua, body.as_bytes()))?,
+    )?;
+    json.set(
+        "encode",
+        lua.create_function(|_, value: Value| json::encode(value))?,
+    )?;
+    module.set("json", json)?;
+
+    let url = lua.create_table()?;
+    url.set(
+        "parse",
+        lua.create_function(|lua, href: String| match Url::parse(&href) {
+            Ok(url) => parsed(lua, &url).map(Some),
+            Err(_) => Ok(None),
+        })?,
+    )?;
+    url.set(
+        "join",
+        lua.create_function(|_, (base, href): (String, String)| {
+            let base = Url::parse(&base).ok();
+            Ok(content::resolve(base.as_ref(), &href).map(String::from))
+        })?,
+    )?;
+    module.set("url", url)?;
+
+    let html = lua.create_table()?;
+    html.set(
+        "escape",
+        lua.create_function(|_, text: String| Ok(escape(&text)))?,
+    )?;
+    html.set(
+        "text",
+        lua.create_function(|_, html: String| Ok(content::text(&html)))?,
+    )?;
+    module.set("html", html)?;
+
+    // indexing from lua goes through __index, so templates can reach into entries and feeds
+    let index: rlua::Function = lua.load("return function(t, k) return t[k] end").eval()?;
+    lua.set_named_registry_value(INDEX, index)?;
+    module.set(
+        "template",
+        lua.create_function(|lua, (template, vars): (String, Value)| {
+            render(lua, &lua.named_registry_value(INDEX)?, &template, vars)
+        })?,
+    )?;
+
+    Ok(module)
+}
+
+impl UserData for Regex {
+    fn add_methods<'lua, M: rlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("is_match", |_, this, text: String| {
+            Ok(this.0.is_match(&text))
+        });
+
+        methods.add_method("find", |_, this, text: String| {
+            Ok(this.0.find(&text).map(|found| found.as_str().to_string()))
+        });
+
+        methods.add_method("find_all", |_, this, text: String| {
+            Ok(this
+                .0
+                .find_iter(&text)
+                .map(|found| found.as_str().to_string())
+                .collect::<Vec<_>>())
+        });
+
+        methods.add_method("captures", |lua, this, text: String| {
+            let Some(caps) = this.0.captures(&text) else {
+                return Ok(None);
+            };
+
+            let table = lua.create_table()?;
+            for (i, group) in caps.iter().enumerate().skip(1) {
+                if let Some(group) = group {
+                    table.raw_set(i, group.as_str())?;
+                }
+            }
+
+            for name in this.0.capture_names().flatten() {
+                if let Some(group) = caps.name(name) {
+                    table.raw_set(name, group.as_str())?;
+                }
+            }
+
+            Ok(Some(table))
+        });
+
+        methods.add_method(
+            "replace",
+            |_, this, (text, replacement): (String, String)| {
+                Ok(this.0.replace_all(&text, replacement.as_str()).into_owned())
+            },
+        );
+    }
+}
+
+fn parsed<'lua>(lua: &'lua Lua, url: &Url) -> rlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+
+    table.set("href", url.as_str())?;
+    table.set("scheme", url.scheme())?;
+    table.set("host", url.host_str())?;
+    table.set("port", url.port_or_known_default())?;
+    table.set("path", url.path())?;
+    table.set("query", url.query())?;
+    table.set("fragment", url.fragment())?;
+
+    let params = lua.create_table()?;
+    for (name, value) in url.query_pairs() {
+        if !params.contains_key(name.as_ref())? {
+            params.set(name.as_ref(), value.as_ref())?;
+        }
+    }
+    table.set("params", params)?;
+
+    Ok(table)
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Replaces each `{name}` or `{name.field}` with its value, `{{` and `}}` being literal braces
+fn render(lua: &Lua, index: &rlua::Function, template: &str, vars: Value) -> rlua::Result<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(pos) = rest.find(['{', '}']) {
+        out.push_str(&rest[..pos]);
+        let brace = &rest[pos..];
+
+        if brace.starts_with("{{") || brace.starts_with("}}") {
+            out.push_str(&brace[..1]);
+            rest = &brace[2..];
+            continue;
+        }
+
+        if brace.starts_with('}') {
+            return Err(rlua::Error::runtime("unmatched } in template"));
+        }
+
+        let Some(end) = brace.find('}') else {
+            return Err(rlua::Error::runtime("unclosed { in template"));
+        };
+
+        let mut value = vars.clone();
+        for key in brace[1..end].trim().split('.') {
+            value = match value {
+                Value::Nil => Value::Nil,
+                value => index.call((value, key))?,
+            };
+        }
+
+        match value {
+            Value::Nil => (),
+            Value::Boolean(b) => out.push_str(if b { "true" } else { "false" }),
+            value => match lua.coerce_string(value.clone())? {
+                Some(s) => out.push_str(s.to_str()?),
+                None => {
+                    return Err(rlua::Error::runtime(format!(
+                        "cannot put a {} in a template",
+                        value.type_name()
+                    )));
+                }
+            },
+        }
+
+        rest = &brace[end + 1..];
+    }
+
+    out.push_str(rest);
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        lua.globals().set("cynd", module(&lua).unwrap()).unwrap();
+        lua
+    }
+
+    fn eval(lua: &Lua, chunk: &str) -> rlua::Result<String> {
+        lua.load(chunk).eval()
+    }
+
+    #[test]
+    fn renders_templates() {
+        let lua = lua();
+
+        assert_eq!(
+            eval(
+                &lua,
+                r#"return cynd.template("{ title } by {author.name}, {count} {{ok}}", {
+                    title = "Post", author = { name = "Ann" }, count = 3,
+                })"#
+            )
+            .unwrap(),
+            "Post by Ann, 3 {ok}"
+        );
+        assert_eq!(
+            eval(
+                &lua,
+                r#"return cynd.template("[{missing}][{missing.deeper}][{flag}]", { flag = false })"#
+            )
+            .unwrap(),
+            "[][][false]"
+        );
+        assert_eq!(
+            eval(
+                &lua,
+                r#"return cynd.template("{x}", setmetatable({}, { __index = function(_, k) return k .. "!" end }))"#
+            )
+            .unwrap(),
+            "x!"
+        );
+        assert_eq!(
+            eval(&lua, r#"return cynd.template("no vars", nil)"#).unwrap(),
+            "no vars"
+        );
+    }
+
+    #[test]
+    fn rejects_broken_templates() {
+        let lua = lua();
+
+        for (template, message) in [
+            ("{open", "unclosed {"),
+            ("close}", "unmatched }"),
+            ("{t}", "cannot put a table"),
+        ] {
+            let err = eval(
+                &lua,
+                &format!("return cynd.template({template:?}, {{ t = {{}} }})"),
+            )
+            .unwrap_err();
+            assert!(err.to_string().contains(message), "{template}: {err}");
+        }
+    }
+
+    #[test]
+    fn escapes_html() {
+        let lua = lua();
+
+        assert_eq!(
+            eval(
+                &lua,
+                r#"return cynd.html.escape([[<a href="x">Tom & 'Jerry'</a>]])"#
+            )
+            .unwrap(),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;"
+        );
+        assert_eq!(
+            eval(&lua, r#"return cynd.html.text("<p>one</p><p>two</p>")"#).unwrap(),
+            "one\ntwo"
+        );
+    }
+
+    #[test]
+    fn parses_and_joins_urls() {
+        let lua = lua();
+
+        assert_eq!(
+            eval(
+                &lua,
+                r#"local u = cynd.url.parse("https://Example.com/a/b?x=1&x=2&y=3#f")
+                return table.concat({ u.host, u.port, u.path, u.params.x, u.params.y, u.fragment }, " ")"#
+            )
+            .unwrap(),
+            "example.com 443 /a/b 1 3 f"
+        );
+        assert_eq!(
+            eval(&lua, r#"return tostring(cynd.url.parse("not a url"))"#).unwrap(),
+            "nil"
+        );
+        assert_eq!(
+            eval(
+                &lua,
+                r#"return cynd.url.join("https://example.com/a/b", "../c")"#
+            )
+            .unwrap(),
+            "https://example.com/c"
+        );
+    }
+
+    #[test]
+    fn matches_regexes() {
+        let lua = lua();
+
+        assert_eq!(
+            eval(
+                &lua,
+                r#"local re = cynd.regex("(?<year>\\d{4})-(\\d{2})")
+                local caps = re:captures("on 2024-05")
+                return table.concat({
+                    tostring(re:is_match("x")), re:find("a 1999-12 b"), caps.year, caps[2],
+                    table.concat(re:find_all("2020-01 2021-02"), ","), re:replace("2020-01", "$2/$year"),
+                }, " ")"#
+            )
+            .unwrap(),
+            "false 1999-12 2024 05 2020-01,2021-02 01/2020"
+        );
+        assert!(eval(&lua, r#"return cynd.regex("(")"#).is_err());
+    }
+}
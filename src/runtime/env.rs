@@ -1,9 +1,14 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
 use rlua::{FromLua, ToLua, Value};
+use tracing::Level;
 
 use crate::{
-    interp::{Alert, Exec, Record},
+    db::types,
+    interp::{Alert, DEFAULT_MAX_SIZE, Download, Exec, Mark, Record},
     runtime::Instruction,
 };
 
@@ -37,10 +42,59 @@ impl<'lua> FromLua<'lua> for AlertOptions {
     }
 }
 
+struct DownloadOptions {
+    dir: Option<String>,
+    filename: Option<String>,
+    max_size: Option<u64>,
+}
+
+impl<'lua> FromLua<'lua> for DownloadOptions {
+    fn from_lua(value: Value<'lua>, _: &'lua rlua::Lua) -> rlua::Result<Self> {
+        if let Some(table) = value.as_table() {
+            Ok(DownloadOptions {
+                dir: table.get("dir")?,
+                filename: table.get("filename")?,
+                max_size: table.get("max_size")?,
+            })
+        } else {
+            Err(rlua::Error::RuntimeError(
+                "invalid type signature".to_string(),
+            ))
+        }
+    }
+}
+
+impl DownloadOptions {
+    /// `dir` defaults to the user's download directory, and may start with `~`
+    fn dir(&self) -> rlua::Result<PathBuf> {
+        let home = dirs::home_dir();
+
+        let dir = match self.dir.as_deref() {
+            Some("~") => home,
+            Some(dir) => match dir.strip_prefix("~/") {
+                Some(rest) => home.map(|home| home.join(rest)),
+                None => Some(PathBuf::from(dir)),
+            },
+            None => dirs::download_dir(),
+        };
+
+        dir.ok_or_else(|| rlua::Error::runtime("no directory to download to"))
+    }
+}
+
 impl<'lua> ToLua<'lua> for Env {
     fn into_lua(self, lua: &'lua rlua::Lua) -> rlua::Result<rlua::Value<'lua>> {
         let table = lua.globals();
 
+        table.set("sources", lua.create_table()?)?;
+
+        let cynd = super::cynd::module(lua)?;
+        table
+            .get::<_, rlua::Table>("package")?
+            .get::<_, rlua::Table>("loaded")?
+            .set("cynd", cynd.clone())?;
+        table.set("cynd", cynd)?;
+
         let inst = self.inst.clone();
         table.set(
             "record",
@@ -94,15 +148,111 @@ impl<'lua> ToLua<'lua> for Env {
             })?,
         )?;
 
+        let inst = self.inst.clone();
+        table.set(
+            "mark_read",
+            lua.create_function(move |_, _: ()| {
+                let Ok(mut inst) = inst.lock() else {
+                    return Err(rlua::Error::runtime("failed to lock instructions"));
+                };
+
+                inst.push(
+                    Mark {
+                        mark: types::Mark::Read,
+                    }
+                    .into(),
+                );
+
+                Ok(Value::Nil)
+            })?,
+        )?;
+
+        let inst = self.inst.clone();
+        table.set(
+            "star",
+            lua.create_function(move |_, _: ()| {
+                let Ok(mut inst) = inst.lock() else {
+                    return Err(rlua::Error::runtime("failed to lock instructions"));
+                };
+
+                inst.push(
+                    Mark {
+                        mark: types::Mark::Star,
+                    }
+                    .into(),
+                );
+
+                Ok(Value::Nil)
+            })?,
+        )?;
+
+        let inst = self.inst.clone();
         table.set(
-            "log",
-            lua.create_function(move |_, msg: String| {
-                println!("log: {}", msg);
+            "download",
+            lua.create_function(move |_, opts: Option<DownloadOptions>| {
+                let Ok(mut inst) = inst.lock() else {
+                    return Err(rlua::Error::runtime("failed to lock instructions"));
+                };
+
+                let opts = opts.unwrap_or(DownloadOptions {
+                    dir: None,
+                    filename: None,
+                    max_size: None,
+                });
+
+                inst.push(
+                    Download {
+                        dir: opts.dir()?,
+                        filename: opts.filename,
+                        max_size: opts.max_size.unwrap_or(DEFAULT_MAX_SIZE),
+                    }
+                    .into(),
+                );
 
                 Ok(Value::Nil)
             })?,
         )?;
 
+        // `log(msg)` logs at info, `log.warn(msg)` and friends at their level
+        let log = lua.create_table()?;
+        for level in [
+            Level::TRACE,
+            Level::DEBUG,
+            Level::INFO,
+            Level::WARN,
+            Level::ERROR,
+        ] {
+            log.set(
+                level.as_str().to_lowercase(),
+                lua.create_function(move |_, msg: String| {
+                    emit(level, &msg);
+                    Ok(Value::Nil)
+                })?,
+            )?;
+        }
+
+        let meta = lua.create_table()?;
+        meta.set(
+            "__call",
+            lua.create_function(|_, (_, msg): (Value, String)| {
+                emit(Level::INFO, &msg);
+                Ok(Value::Nil)
+            })?,
+        )?;
+        log.set_metatable(Some(meta));
+        table.set("log", log)?;
+
         Ok(Value::Table(table))
     }
 }
+
+/// Logs a message from a script under the `lua` target, inside the span of the call running it
+fn emit(level: Level, msg: &str) {
+    match level {
+        Level::TRACE => tracing::trace!(target: "lua", "{msg}"),
+        Level::DEBUG => tracing::debug!(target: "lua", "{msg}"),
+        Level::INFO => tracing::info!(target: "lua", "{msg}"),
+        Level::WARN => tracing::warn!(target: "lua", "{msg}"),
+        Level::ERROR => tracing::error!(target: "lua", "{msg}"),
+    }
+}
@@ -0,0 +1,173 @@
+use rlua::{Lua, Table, Value};
+
+/// Deepest nesting encoded, so a table containing itself errors instead of overflowing
+const MAX_DEPTH: usize = 128;
+
+/// Decodes json into lua values, objects and arrays both becoming tables
+pub(crate) fn decode<'lua>(lua: &'lua Lua, body: &[u8]) -> rlua::Result<Value<'lua>> {
+    let json: serde_json::Value = serde_json::from_slice(body)
+        .map_err(|err| rlua::Error::runtime(format!("invalid json: {err}")))?;
+
+    to_lua(lua, &json)
+}
+
+fn to_lua<'lua>(lua: &'lua Lua, json: &serde_json::Value) -> rlua::Result<Value<'lua>> {
+    use serde_json::Value as Json;
+
+    Ok(match json {
+        Json::Null => Value::Nil,
+        Json::Bool(b) => Value::Boolean(*b),
+        Json::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Number(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Json::String(s) => Value::String(lua.create_string(s)?),
+        Json::Array(values) => {
+            let table = lua.create_table_with_capacity(values.len(), 0)?;
+            for value in values {
+                table.raw_push(to_lua(lua, value)?)?;
+            }
+            Value::Table(table)
+        }
+        Json::Object(fields) => {
+            let table = lua.create_table_with_capacity(0, fields.len())?;
+            for (key, value) in fields {
+                table.raw_set(key.as_str(), to_lua(lua, value)?)?;
+            }
+            Value::Table(table)
+        }
+    })
+}
+
+/// Encodes a lua value as json, sequences becoming arrays and other tables objects
+pub(crate) fn encode(value: Value) -> rlua::Result<String> {
+    let json = to_json(value, 0)?;
+
+    serde_json::to_string(&json).map_err(|err| rlua::Error::runtime(err.to_string()))
+}
+
+fn to_json(value: Value, depth: usize) -> rlua::Result<serde_json::Value> {
+    use serde_json::Value as Json;
+
+    if depth > MAX_DEPTH {
+        return Err(rlua::Error::runtime(
+            "json nested too deeply, or a table contains itself",
+        ));
+    }
+
+    Ok(match value {
+        Value::Nil => Json::Null,
+        Value::Boolean(b) => Json::Bool(b),
+        Value::Integer(i) => Json::from(i),
+        Value::Number(n) => serde_json::Number::from_f64(n)
+            .map(Json::Number)
+            .ok_or_else(|| rlua::Error::runtime(format!("cannot encode {n} as json")))?,
+        Value::String(s) => Json::String(s.to_str()?.to_string()),
+        Value::Table(table) if is_sequence(&table)? => Json::Array(
+            table
+                .sequence_values::<Value>()
+                .map(|value| to_json(value?, depth + 1))
+                .collect::<rlua::Result<_>>()?,
+        ),
+        Value::Table(table) => {
+            let mut object = serde_json::Map::new();
+            for pair in table.pairs::<Value, Value>() {
+                let (key, value) = pair?;
+                let key = match key {
+                    Value::String(s) => s.to_str()?.to_string(),
+                    Value::Integer(i) => i.to_string(),
+                    key => {
+                        return Err(rlua::Error::runtime(format!(
+                            "cannot encode a {} key as json",
+                            key.type_name()
+                        )));
+                    }
+                };
+
+                object.insert(key, to_json(value, depth + 1)?);
+            }
+            Json::Object(object)
+        }
+        value => {
+            return Err(rlua::Error::runtime(format!(
+                "cannot encode a {} as json",
+                value.type_name()
+            )));
+        }
+    })
+}
+
+/// A non-empty table whose keys are exactly 1..n; empty tables encode as objects
+fn is_sequence(table: &Table) -> rlua::Result<bool> {
+    let len = table.raw_len();
+    if len == 0 {
+        return Ok(false);
+    }
+
+    let mut count = 0;
+    for pair in table.clone().pairs::<Value, Value>() {
+        match pair? {
+            (Value::Integer(i), _) if i >= 1 && i as usize <= len => count += 1,
+            _ => return Ok(false),
+        }
+    }
+
+    Ok(count == len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(chunk: &str) -> rlua::Result<serde_json::Value> {
+        let lua = Lua::new();
+        let json = encode(lua.load(chunk).eval()?)?;
+        Ok(serde_json::from_str(&json).unwrap())
+    }
+
+    #[test]
+    fn encodes_tables() {
+        assert_eq!(
+            encoded(r#"return { 1, 2.5, "three", true }"#).unwrap(),
+            serde_json::json!([1, 2.5, "three", true])
+        );
+        assert_eq!(
+            encoded(
+                r#"return { name = "a", tags = { "x" }, nested = { [1] = "one", [3] = "three" } }"#
+            )
+            .unwrap(),
+            serde_json::json!({ "name": "a", "tags": ["x"], "nested": { "1": "one", "3": "three" } })
+        );
+        assert_eq!(encoded("return {}").unwrap(), serde_json::json!({}));
+        assert_eq!(encoded("return nil").unwrap(), serde_json::Value::Null);
+    }
+
+    #[test]
+    fn refuses_what_json_cannot_hold() {
+        for (chunk, message) in [
+            ("local t = {}; t.self = t; return t", "nested too deeply"),
+            ("return { x = 0/0 }", "cannot encode"),
+            ("return { f = print }", "cannot encode a function"),
+            ("return { [true] = 1 }", "cannot encode a boolean key"),
+        ] {
+            let err = encoded(chunk).unwrap_err();
+            assert!(err.to_string().contains(message), "{chunk}: {err}");
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let lua = Lua::new();
+        let body = r#"{"a":[1,{"b":null}],"c":"d","e":-0.5}"#;
+
+        let value = decode(&lua, body.as_bytes()).unwrap();
+        let again: serde_json::Value = serde_json::from_str(&encode(value).unwrap()).unwrap();
+
+        // nulls become nils, which tables cannot hold
+        assert_eq!(
+            again,
+            serde_json::json!({ "a": [1, {}], "c": "d", "e": -0.5 })
+        );
+        assert!(decode(&lua, b"{").is_err());
+    }
+}
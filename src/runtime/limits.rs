@@ -0,0 +1,342 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rlua::{FromLua, HookTriggers, Lua, Table, Value};
+
+/// How many instructions run between checks of the budget
+const CHECK_EVERY: u32 = 1000;
+
+/// Limits applied to every call into the configuration, and to loading it.
+///
+/// Instructions only count on the main lua thread, so loops inside coroutines are caught by the
+/// time limit once control comes back rather than by the instruction limit.
+#[derive(Clone, Debug)]
+pub(crate) struct Limits {
+    pub(crate) instructions: Option<u64>,
+    /// wall time for `process` and `on_feed_error`
+    pub(crate) time: Duration,
+    /// wall time for a source, which spends most of it waiting on `http.get`
+    pub(crate) source_time: Duration,
+    /// bytes the lua state may allocate
+    pub(crate) memory: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            instructions: None,
+            time: Duration::from_secs(10),
+            source_time: Duration::from_secs(120),
+            memory: Some(256 * 1024 * 1024),
+        }
+    }
+}
+
+/// What the call in progress may still spend, checked from an instruction hook
+#[derive(Clone, Default)]
+pub(crate) struct Budget {
+    inner: Arc<Mutex<Option<Spent>>>,
+}
+
+struct Spent {
+    start: Instant,
+    time: Duration,
+    instructions: Option<u64>,
+    count: u64,
+}
+
+impl Budget {
+    /// Installs the hook enforcing the budget of whichever call is running
+    pub(crate) fn install(&self, lua: &Lua) {
+        let inner = self.inner.clone();
+
+        lua.set_hook(
+            HookTriggers::new().every_nth_instruction(CHECK_EVERY),
+            move |_, _| {
+                let Ok(mut spent) = inner.lock() else {
+                    return Ok(());
+                };
+
+                let Some(spent) = spent.as_mut() else {
+                    return Ok(());
+                };
+
+                spent.count += u64::from(CHECK_EVERY);
+
+                if let Some(max) = spent.instructions
+                    && spent.count > max
+                {
+                    return Err(rlua::Error::runtime(format!(
+                        "exceeded the limit of {max} instructions"
+                    )));
+                }
+
+                if spent.start.elapsed() > spent.time {
+                    return Err(rlua::Error::runtime(format!(
+                        "timed out after {:?}",
+                        spent.time
+                    )));
+                }
+
+                Ok(())
+            },
+        );
+    }
+
+    /// Runs `f` with a fresh budget of `time` and the instruction limit
+    pub(crate) fn run<T>(&self, limits: &Limits, time: Duration, f: impl FnOnce() -> T) -> T {
+        self.set(Some(Spent {
+            start: Instant::now(),
+            time,
+            instructions: limits.instructions,
+            count: 0,
+        }));
+
+        let res = f();
+
+        self.set(None);
+
+        res
+    }
+
+    fn set(&self, spent: Option<Spent>) {
+        if let Ok(mut inner) = self.inner.lock() {
+            *inner = spent;
+        }
+    }
+}
+
+impl Limits {
+    /// Applies the memory cap
+    pub(crate) fn apply(&self, lua: &Lua) -> rlua::Result<()> {
+        lua.set_memory_limit(self.memory.unwrap_or(0))?;
+
+        Ok(())
+    }
+}
+
+/// Removes what lets a script touch files, run programs or load native code.
+///
+/// Done before init.lua loads, so nothing it runs at the top level can keep a reference.
+pub(crate) fn sandbox(lua: &Lua) -> rlua::Result<()> {
+    let globals = lua.globals();
+
+    for name in ["io", "dofile", "loadfile"] {
+        globals.set(name, Value::Nil)?;
+    }
+
+    if let Some(os) = globals.get::<_, Option<Table>>("os")? {
+        for name in [
+            "execute",
+            "exit",
+            "remove",
+            "rename",
+            "tmpname",
+            "setlocale",
+        ] {
+            os.set(name, Value::Nil)?;
+        }
+    }
+
+    if let Some(package) = globals.get::<_, Option<Table>>("package")? {
+        package.set("loadlib", Value::Nil)?;
+        package.set("cpath", "")?;
+
+        // `require` hands out what's loaded, io included, whatever the globals say
+        if let Some(loaded) = package.get::<_, Option<Table>>("loaded")? {
+            loaded.set("io", Value::Nil)?;
+        }
+
+        // keep the preload and lua file searchers, dropping the ones loading shared libraries
+        if let Some(searchers) = package.get::<_, Option<Table>>("searchers")? {
+            while searchers.raw_len() > 2 {
+                searchers.raw_remove(searchers.raw_len())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl<'lua> FromLua<'lua> for Limits {
+    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> rlua::Result<Self> {
+        let Value::Table(table) = value else {
+            return Err(rlua::Error::runtime("expected a table for limits"));
+        };
+
+        let mut limits = Limits::default();
+
+        if let Some(instructions) = table.get("instructions")? {
+            limits.instructions = Some(instructions);
+        }
+
+        if let Some(seconds) = table.get::<_, Option<f64>>("seconds")? {
+            limits.time = seconds_to_duration(seconds)?;
+        }
+
+        if let Some(seconds) = table.get::<_, Option<f64>>("source_seconds")? {
+            limits.source_time = seconds_to_duration(seconds)?;
+        }
+
+        // 0 lifts the cap
+        if let Some(memory) = table.get::<_, Option<usize>>("memory")? {
+            limits.memory = (memory > 0).then_some(memory);
+        }
+
+        Ok(limits)
+    }
+}
+
+fn seconds_to_duration(seconds: f64) -> rlua::Result<Duration> {
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| rlua::Error::runtime(format!("invalid number of seconds {seconds}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interp::Program,
+        runtime::Runtime,
+        testing::{Scratch, item, meta},
+    };
+
+    async fn process(runtime: &Runtime, id: &str) -> crate::Result<Program> {
+        runtime
+            .process("https://example.com/feed".to_string(), meta(), item(id))
+            .await
+    }
+
+    fn failed(res: crate::Result<Program>, message: &str) {
+        match res {
+            Err(crate::Error::Eval(err)) => assert!(err.contains(message), "{err}"),
+            res => panic!("expected an error with {message:?}, got {res:?}"),
+        }
+    }
+
+    const PROBES: &str = r#"
+        -- references taken while loading, which the sandbox has to beat
+        local run = os.execute
+        local ok, lib = pcall(require, "io")
+
+        local probes = {
+            io = function() io.write("") end,
+            execute = function() os.execute() end,
+            require = function() require("io").write("") end,
+            loaded = function() package.loaded.io.write("") end,
+            dofile = function() assert(dofile) end,
+            captured = function() run() end,
+            required = function() assert(ok, lib); lib.write("") end,
+            time = function() assert(os.time()) end,
+        }
+
+        return {
+            process = function(item) probes[item.id]() end,
+        }
+    "#;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sandbox_keeps_scripts_in() {
+        let (one, other) = (Scratch::new(), Scratch::new());
+        let open = one.runtime(PROBES, 1);
+        let sandboxed = other.sandboxed(PROBES, 1);
+
+        for probe in [
+            "io", "execute", "require", "loaded", "dofile", "captured", "required",
+        ] {
+            let res = process(&open, probe).await;
+            assert!(res.is_ok(), "{probe} unsandboxed: {res:?}");
+
+            let res = process(&sandboxed, probe).await;
+            assert!(
+                matches!(res, Err(crate::Error::Eval(_))),
+                "{probe} sandboxed: {res:?}"
+            );
+        }
+
+        assert!(process(&sandboxed, "time").await.is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn refuses_to_load_asking_for_the_sandbox_too_late() {
+        let init = r#"return { sandbox = true, process = function() end }"#;
+        let (one, other) = (Scratch::new(), Scratch::new());
+
+        assert!(matches!(
+            process(&one.runtime(init, 1), "item").await,
+            Err(crate::Error::RuntimeShutdown)
+        ));
+        assert!(process(&other.sandboxed(init, 1), "item").await.is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn times_out_runaway_items() {
+        let scratch = Scratch::new();
+        let runtime = scratch.runtime(
+            r#"return {
+                limits = { seconds = 0.2 },
+                process = function(item)
+                    if item.id == "loop" then
+                        while true do end
+                    end
+                    record()
+                end,
+            }"#,
+            1,
+        );
+
+        failed(process(&runtime, "loop").await, "timed out");
+
+        let prog = process(&runtime, "next").await.unwrap();
+        assert_eq!(prog.instructions.len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn caps_memory() {
+        let scratch = Scratch::new();
+        let runtime = scratch.runtime(
+            r#"return {
+                limits = { memory = 8 * 1024 * 1024 },
+                process = function(item)
+                    if item.id == "hog" then
+                        local hoard = {}
+                        for i = 1, 1e7 do
+                            hoard[i] = string.rep("x", 100) .. i
+                        end
+                    end
+                    record()
+                end,
+            }"#,
+            1,
+        );
+
+        failed(process(&runtime, "hog").await, "memory");
+
+        let prog = process(&runtime, "next").await.unwrap();
+        assert_eq!(prog.instructions.len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn counts_instructions_per_item() {
+        let scratch = Scratch::new();
+        let runtime = scratch.runtime(
+            r#"return {
+                limits = { instructions = 100000 },
+                process = function(item)
+                    local n = item.id == "long" and 1e7 or 1e3
+                    for _ = 1, n do end
+                    record()
+                end,
+            }"#,
+            1,
+        );
+
+        failed(process(&runtime, "long").await, "100000 instructions");
+
+        for _ in 0..3 {
+            let prog = process(&runtime, "short").await.unwrap();
+            assert_eq!(prog.instructions.len(), 1);
+        }
+    }
+}
@@ -1,69 +1,241 @@
-use std::path::PathBuf;
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex, mpsc},
+    time::Instant,
+};
 
 use rlua::{FromLua, Value};
+use tokio::runtime::Handle;
+use tracing::Span;
+use url::Url;
 
-use crate::FeedItem;
+use crate::{Feed, FeedItem, db::Conn, fetcher::Fetcher, metrics::Metrics};
 
+mod cynd;
 mod env;
+mod json;
+mod limits;
+mod source;
+mod store;
 
-use crate::feed::FeedMeta;
+use crate::feed::{Canonical, FeedError, FeedMeta};
 use crate::interp::{Instruction, Program};
 
+/// A pool of lua states loaded from the same config, each on its own thread, taking messages
+/// from a shared queue.
+#[derive(Clone)]
 pub(crate) struct Runtime {
-    send: std::sync::mpsc::Sender<Message>,
+    send: mpsc::Sender<Message>,
 }
 
+/// What a worker thread needs to load the config and start more of itself
+#[derive(Clone)]
+struct Worker {
+    recv: Arc<Mutex<mpsc::Receiver<Message>>>,
+    path: PathBuf,
+    fetcher: Fetcher,
+    conn: Conn,
+    metrics: Metrics,
+    handle: Option<Handle>,
+    /// strip the libraries that reach outside the process before init.lua loads
+    sandbox: bool,
+}
+
+#[allow(clippy::large_enum_variant)]
 enum Message {
-    Process(FeedMeta, FeedItem, tokio::sync::oneshot::Sender<Program>),
+    Process(
+        String,
+        FeedMeta,
+        FeedItem,
+        Span,
+        tokio::sync::oneshot::Sender<crate::Result<Program>>,
+    ),
+    FeedError(
+        FeedError,
+        Span,
+        tokio::sync::oneshot::Sender<Option<Program>>,
+    ),
+    Source(Url, Span, tokio::sync::oneshot::Sender<crate::Result<Feed>>),
+    Canonical(tokio::sync::oneshot::Sender<Canonical>),
 }
 
 impl Runtime {
-    pub(crate) fn new(path: PathBuf) -> Runtime {
-        let (send, recv) = std::sync::mpsc::channel();
+    /// Starts `workers` lua states, the first loading the config alone so a broken one is only
+    /// reported once
+    pub(crate) fn new(
+        path: PathBuf,
+        fetcher: Fetcher,
+        conn: Conn,
+        metrics: Metrics,
+        workers: usize,
+        sandbox: bool,
+    ) -> Runtime {
+        let (send, recv) = mpsc::channel();
+        let worker = Worker {
+            recv: Arc::new(Mutex::new(recv)),
+            path,
+            fetcher,
+            conn,
+            metrics,
+            handle: Handle::try_current().ok(),
+            sandbox,
+        };
 
-        std::thread::spawn(|| runtime_main(recv, path));
+        std::thread::spawn(move || runtime_main(worker, workers.saturating_sub(1)));
 
         Self { send }
     }
 
-    pub(crate) async fn process(&self, meta: FeedMeta, item: FeedItem) -> crate::Result<Program> {
+    /// Runs `process` for an item of the feed at `url`, failing with [`crate::Error::Eval`] when
+    /// the script errors or runs out of its limits.
+    ///
+    /// Spans are handed to the worker, so what the script logs lands under the item and the
+    /// fetch it came from.
+    pub(crate) async fn process(
+        &self,
+        url: String,
+        meta: FeedMeta,
+        item: FeedItem,
+    ) -> crate::Result<Program> {
+        let span = tracing::info_span!("item", id = %item.id);
+        let (send, recv) = tokio::sync::oneshot::channel();
+        self.send
+            .send(Message::Process(url, meta, item, span, send))
+            .map_err(|_| crate::Error::RuntimeShutdown)?;
+
+        recv.await.map_err(|_| crate::Error::RuntimeShutdown)?
+    }
+
+    /// Runs the `on_feed_error` hook, if the configuration defines one
+    pub(crate) async fn feed_error(&self, error: FeedError) -> crate::Result<Option<Program>> {
+        let span = tracing::info_span!("feed_error", feed = %error.url);
+        let (send, recv) = tokio::sync::oneshot::channel();
+        self.send
+            .send(Message::FeedError(error, span, send))
+            .map_err(|_| crate::Error::RuntimeShutdown)?;
+
+        recv.await.map_err(|_| crate::Error::RuntimeShutdown)
+    }
+
+    /// Runs the source registered under the host of a `lua://` url
+    pub(crate) async fn source(&self, url: Url) -> crate::Result<Feed> {
+        let span = tracing::info_span!("source", %url);
         let (send, recv) = tokio::sync::oneshot::channel();
         self.send
-            .send(Message::Process(meta, item, send))
+            .send(Message::Source(url, span, send))
+            .map_err(|_| crate::Error::RuntimeShutdown)?;
+
+        recv.await.map_err(|_| crate::Error::RuntimeShutdown)?
+    }
+
+    /// The url canonicalization rules from the `urls` table of the configuration
+    pub(crate) async fn canonical(&self) -> crate::Result<Canonical> {
+        let (send, recv) = tokio::sync::oneshot::channel();
+        self.send
+            .send(Message::Canonical(send))
             .map_err(|_| crate::Error::RuntimeShutdown)?;
 
         recv.await.map_err(|_| crate::Error::RuntimeShutdown)
     }
 }
 
-fn runtime_main(recv: std::sync::mpsc::Receiver<Message>, path: PathBuf) {
+fn runtime_main(worker: Worker, more: usize) {
+    let Worker {
+        recv,
+        path,
+        fetcher,
+        conn,
+        metrics,
+        handle,
+        sandbox,
+    } = worker.clone();
+
     let interp = rlua::Lua::new();
     let env = env::Env::default();
     let inst = env.inst.clone();
 
-    if let Some(base) = path.parent() {
-        if let Err(err) = interp
+    // loading runs under the default limits, so a runaway init.lua can't wedge the thread
+    let budget = limits::Budget::default();
+    budget.install(&interp);
+    let defaults = limits::Limits::default();
+    if let Err(err) = defaults.apply(&interp) {
+        tracing::error!("failed to limit the lua runtime: {err}");
+    }
+
+    if sandbox && let Err(err) = limits::sandbox(&interp) {
+        tracing::error!("failed to sandbox the lua runtime: {err}");
+        return;
+    }
+
+    if let Some(base) = path.parent()
+        && let Err(err) = interp
             .load(format!(
                 "package.path = \"{}\" .. package.path",
                 import_paths(base)
             ))
             .exec()
-        {
-            dbg!(err);
+    {
+        tracing::error!("failed to set the lua package path: {err}");
+    }
+
+    // shared state lives in the database, globals being private to each worker
+    let store = match store::store(&interp, conn, handle.clone()) {
+        Ok(store) => store,
+        Err(err) => {
+            tracing::error!("failed to set up the store: {err}");
+            return;
         }
+    };
+    if let Err(err) = interp.globals().set("store", store) {
+        tracing::error!("failed to set up the store: {err}");
+        return;
     }
 
-    let conf = match interp.load(path).set_environment(env).eval::<Conf>() {
+    let conf = match budget.run(&defaults, defaults.time, || {
+        interp
+            .load(path.as_path())
+            .set_environment(env)
+            .eval::<Conf>()
+    }) {
         Ok(conf) => conf,
         Err(err) => {
-            dbg!(err);
+            tracing::error!("failed to load {}: {err}", path.display());
             return;
         }
     };
 
-    while let Ok(msg) = recv.recv() {
+    // the sandbox has to be in place before init.lua runs, too late once it asks for it
+    if conf.sandbox && !sandbox {
+        tracing::error!(
+            "{} asks for the sandbox, which needs cynd --sandbox",
+            path.display()
+        );
+        return;
+    }
+
+    if let Err(err) = conf.limits.apply(&interp) {
+        tracing::error!("failed to apply the configured limits: {err}");
+        return;
+    }
+
+    let http = match source::http(&interp, fetcher, handle) {
+        Ok(http) => http,
+        Err(err) => {
+            tracing::error!("failed to set up http for sources: {err}");
+            return;
+        }
+    };
+
+    for _ in 0..more {
+        let worker = worker.clone();
+        std::thread::spawn(move || runtime_main(worker, 0));
+    }
+
+    while let Some(msg) = next(&recv) {
         match msg {
-            Message::Process(meta, feed_item, sender) => {
+            Message::Process(url, meta, feed_item, span, sender) => {
+                let _span = span.enter();
+
                 {
                     let Ok(mut guard) = inst.lock() else {
                         continue;
@@ -71,11 +243,48 @@ fn runtime_main(recv: std::sync::mpsc::Receiver<Message>, path: PathBuf) {
                     guard.clear();
                 }
 
-                if let Err(e) = conf
-                    .func
-                    .call::<(FeedItem, FeedMeta), Value>((feed_item, meta))
+                let start = Instant::now();
+                let res = budget.run(&conf.limits, conf.limits.time, || {
+                    conf.func
+                        .call::<(FeedItem, FeedMeta), Value>((feed_item, meta))
+                });
+                metrics.evaluated(&url, start.elapsed(), res.is_err());
+
+                if let Err(e) = res {
+                    let _ = sender.send(Err(crate::Error::Eval(e.to_string())));
+                    continue;
+                }
+
+                {
+                    let Ok(guard) = inst.lock() else {
+                        continue;
+                    };
+
+                    let _ = sender.send(Ok(Program {
+                        instructions: (*guard).clone(),
+                    }));
+                }
+            }
+
+            Message::FeedError(error, span, sender) => {
+                let _span = span.enter();
+
+                let Some(on_error) = &conf.on_error else {
+                    let _ = sender.send(None);
+                    continue;
+                };
+
                 {
-                    dbg!(e);
+                    let Ok(mut guard) = inst.lock() else {
+                        continue;
+                    };
+                    guard.clear();
+                }
+
+                if let Err(e) = budget.run(&conf.limits, conf.limits.time, || {
+                    on_error.call::<FeedError, Value>(error)
+                }) {
+                    tracing::error!("on_feed_error failed: {e}");
                     continue;
                 }
 
@@ -84,24 +293,79 @@ fn runtime_main(recv: std::sync::mpsc::Receiver<Message>, path: PathBuf) {
                         continue;
                     };
 
-                    let _ = sender.send(Program {
+                    let _ = sender.send(Some(Program {
                         instructions: (*guard).clone(),
-                    });
+                    }));
                 }
             }
+
+            Message::Source(url, span, sender) => {
+                let _span = span.enter();
+
+                let name = url.host_str().unwrap_or_default();
+                let func = interp
+                    .globals()
+                    .get::<_, Option<rlua::Table>>("sources")
+                    .and_then(|sources| match sources {
+                        Some(sources) => sources.get::<_, Option<rlua::Function>>(name),
+                        None => Ok(None),
+                    });
+
+                let res = match func {
+                    Ok(Some(func)) => budget
+                        .run(&conf.limits, conf.limits.source_time, || {
+                            func.call::<_, Value>(http.clone())
+                        })
+                        .and_then(|value| source::feed(&url, value))
+                        .map_err(|err| crate::Error::Script(err.to_string())),
+                    Ok(None) => Err(crate::Error::Script(format!("no source named {name:?}"))),
+                    Err(err) => Err(crate::Error::Script(err.to_string())),
+                };
+
+                let _ = sender.send(res);
+            }
+
+            Message::Canonical(sender) => {
+                let _ = sender.send(conf.canonical.clone());
+            }
         }
     }
 }
 
+/// Waits for the next message, the lock only held by the idle worker first in line
+fn next(recv: &Mutex<mpsc::Receiver<Message>>) -> Option<Message> {
+    recv.lock().ok()?.recv().ok()
+}
+
 struct Conf<'lua> {
     func: rlua::Function<'lua>,
+    on_error: Option<rlua::Function<'lua>>,
+    canonical: Canonical,
+    limits: limits::Limits,
+    sandbox: bool,
 }
 
 impl<'lua> FromLua<'lua> for Conf<'lua> {
     fn from_lua(value: rlua::Value<'lua>, _: &'lua rlua::Lua) -> rlua::Result<Self> {
         if let rlua::Value::Table(table) = value {
             let func = table.get("process")?;
-            Ok(Self { func })
+            let on_error = table.get("on_feed_error")?;
+            let canonical = table
+                .get::<_, Option<Canonical>>("urls")?
+                .unwrap_or_default();
+
+            let limits = table
+                .get::<_, Option<limits::Limits>>("limits")?
+                .unwrap_or_default();
+            let sandbox = table.get::<_, Option<bool>>("sandbox")?.unwrap_or(false);
+
+            Ok(Self {
+                func,
+                on_error,
+                canonical,
+                limits,
+                sandbox,
+            })
         } else {
             Err(rlua::Error::runtime("expected an object for configuration"))
         }
@@ -114,3 +378,42 @@ fn import_paths(base: &std::path::Path) -> String {
 
     format!("{base}/?.lua;{base}/?/init.lua;")
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interp::Instruction,
+        testing::{Scratch, item, meta},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pool_answers_each_item_in_order() {
+        let scratch = Scratch::new();
+        // earlier items take longer, so workers finish out of order
+        let runtime = scratch.runtime(
+            r#"return {
+                process = function(item)
+                    local until_ = os.clock() + (20 - tonumber(item.id)) / 1000
+                    while os.clock() < until_ do end
+                    alert(item.id)
+                end,
+            }"#,
+            4,
+        );
+
+        let ids: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let progs =
+            futures::future::join_all(ids.iter().map(|id| {
+                runtime.process("https://example.com/feed".to_string(), meta(), item(id))
+            }))
+            .await;
+
+        for (id, prog) in ids.iter().zip(progs) {
+            let prog = prog.unwrap();
+            let [Instruction::Alert(alert)] = &prog.instructions[..] else {
+                panic!("expected one alert for {id}: {prog:?}");
+            };
+            assert_eq!(alert.message.as_ref(), Some(id));
+        }
+    }
+}
@@ -0,0 +1,426 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rlua::{Lua, Table, UserData, Value};
+use scraper::{ElementRef, Html, Selector};
+use tokio::runtime::Handle;
+use url::Url;
+
+use crate::{
+    Feed, FeedItem,
+    feed::{Category, Content, FeedMeta, FeedType, Link, Person},
+    fetcher::Fetcher,
+    runtime::json,
+};
+
+/// How long a source may wait on a single `http.get`
+const GET_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Response handed back by `http.get`
+struct Response {
+    url: String,
+    status: Option<u16>,
+    body: Vec<u8>,
+}
+
+/// A parsed html document, kept as markup so it can be handed between lua calls
+struct Document {
+    html: String,
+}
+
+/// An element matched by `select`
+struct Element {
+    name: String,
+    html: String,
+    inner: String,
+    attrs: Vec<(String, String)>,
+}
+
+/// Builds the `http` table passed to source functions.
+///
+/// Only http(s) urls may be fetched, so a source can't read local files or run commands.
+pub(crate) fn http<'lua>(
+    lua: &'lua Lua,
+    fetcher: Fetcher,
+    handle: Option<Handle>,
+) -> rlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+
+    table.set(
+        "get",
+        lua.create_function(move |_, url: String| {
+            let url = Url::parse(&url)
+                .map_err(|err| rlua::Error::runtime(format!("invalid url {url}: {err}")))?;
+
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(rlua::Error::runtime(format!(
+                    "sources may only fetch http(s) urls, not {url}"
+                )));
+            }
+
+            let Some(handle) = &handle else {
+                return Err(rlua::Error::runtime("no async runtime to fetch with"));
+            };
+
+            let fetched = handle
+                .block_on(async {
+                    tokio::time::timeout(GET_TIMEOUT, fetcher.fetch(url.clone())).await
+                })
+                .map_err(|_| rlua::Error::runtime(format!("timed out fetching {url}")))?
+                .map_err(|err| rlua::Error::runtime(err.to_string()))?;
+
+            Ok(Response {
+                url: fetched.url.to_string(),
+                status: fetched.status,
+                body: fetched.body,
+            })
+        })?,
+    )?;
+
+    table.set(
+        "html",
+        lua.create_function(|_, html: String| Ok(Document { html }))?,
+    )?;
+
+    table.set(
+        "json",
+        lua.create_function(|lua, body: rlua::String| json::decode(lua, body.as_bytes()))?,
+    )?;
+
+    Ok(table)
+}
+
+impl UserData for Response {
+    fn add_fields<'lua, F: rlua::UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("url", |_, this| Ok(this.url.clone()));
+        fields.add_field_method_get("status", |_, this| Ok(this.status));
+        fields.add_field_method_get("body", |lua, this| lua.create_string(&this.body));
+    }
+
+    fn add_methods<'lua, M: rlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("html", |_, this, ()| {
+            Ok(Document {
+                html: String::from_utf8_lossy(&this.body).into_owned(),
+            })
+        });
+
+        methods.add_method("json", |lua, this, ()| json::decode(lua, &this.body));
+    }
+}
+
+impl UserData for Document {
+    fn add_methods<'lua, M: rlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("select", |_, this, css: String| {
+            select(&Html::parse_document(&this.html), &css)
+        });
+
+        methods.add_method("text", |_, this, ()| {
+            Ok(crate::feed::content::text(&this.html))
+        });
+    }
+}
+
+impl UserData for Element {
+    fn add_fields<'lua, F: rlua::UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("name", |_, this| Ok(this.name.clone()));
+        fields.add_field_method_get("html", |_, this| Ok(this.inner.clone()));
+        fields.add_field_method_get("text", |_, this| {
+            Ok(crate::feed::content::text(&this.inner))
+        });
+    }
+
+    fn add_methods<'lua, M: rlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("attr", |_, this, name: String| {
+            Ok(this
+                .attrs
+                .iter()
+                .find(|(attr, _)| *attr == name)
+                .map(|(_, value)| value.clone()))
+        });
+
+        methods.add_method("select", |_, this, css: String| {
+            select(&Html::parse_fragment(&this.html), &css)
+        });
+    }
+}
+
+fn select(html: &Html, css: &str) -> rlua::Result<Vec<Element>> {
+    let selector = Selector::parse(css)
+        .map_err(|err| rlua::Error::runtime(format!("invalid selector {css}: {err}")))?;
+
+    Ok(html.select(&selector).map(element).collect())
+}
+
+fn element(elem: ElementRef) -> Element {
+    Element {
+        name: elem.value().name().to_string(),
+        html: elem.html(),
+        inner: elem.inner_html(),
+        attrs: elem
+            .value()
+            .attrs()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+    }
+}
+
+/// Converts what a source function returned into a feed.
+///
+/// Sources return either a list of items or a table with feed fields and an `items` list.
+pub(crate) fn feed(url: &Url, value: Value) -> rlua::Result<Feed> {
+    let Value::Table(table) = value else {
+        return Err(rlua::Error::runtime("source must return a table of items"));
+    };
+
+    let (meta, items) = match table.get::<_, Option<Table>>("items")? {
+        Some(items) => (Some(table), items),
+        None => (None, table),
+    };
+
+    let (title, description, link, ttl) = match meta {
+        Some(meta) => (
+            meta.get("title")?,
+            meta.get("description")?,
+            meta.get::<_, Option<String>>("link")?,
+            meta.get("ttl")?,
+        ),
+        None => (None, None, None, None),
+    };
+
+    let items = items
+        .sequence_values::<Table>()
+        .map(|item| item.and_then(self::item))
+        .collect::<rlua::Result<Vec<_>>>()?;
+
+    Ok(Feed {
+        meta: FeedMeta {
+            id: url.to_string(),
+            title: title.or_else(|| url.host_str().map(str::to_string)),
+            description,
+            authors: Vec::new(),
+            contributors: Vec::new(),
+            links: link.map(self::link).into_iter().collect(),
+            categories: Vec::new(),
+            ttl,
+            updated: None,
+            published: None,
+            hints: Default::default(),
+            icon: None,
+            logo: None,
+            language: None,
+            rights: None,
+            generator: None,
+            feed_type: FeedType::Source,
+        },
+        items,
+    })
+}
+
+fn item(table: Table) -> rlua::Result<FeedItem> {
+    let title: Option<String> = table.get("title")?;
+    let link: Option<String> = table.get("link")?;
+
+    let id = match table.get::<_, Option<String>>("id")? {
+        Some(id) => id,
+        None => link
+            .clone()
+            .or_else(|| title.clone())
+            .ok_or_else(|| rlua::Error::runtime("source items need an id, link or title"))?,
+    };
+
+    let authors = strings(table.get("authors")?, table.get("author")?)?
+        .into_iter()
+        .map(|name| Person {
+            name,
+            uri: None,
+            email: None,
+        })
+        .collect();
+
+    let categories = strings(table.get("categories")?, None)?
+        .into_iter()
+        .map(|term| Category {
+            term,
+            label: None,
+            subcategories: Vec::new(),
+        })
+        .collect();
+
+    Ok(FeedItem {
+        id,
+        title,
+        authors,
+        contributors: Vec::new(),
+        summary: table.get("summary")?,
+        content: table
+            .get::<_, Option<String>>("content")?
+            .map(Content::Body),
+        source: None,
+        categories,
+        links: link.map(self::link).into_iter().collect(),
+        updated: date(table.get("updated")?)?,
+        published: date(table.get("published")?)?,
+        base: None,
+        media: Vec::new(),
+        rights: None,
+        language: None,
+        duplicate_of: None,
+    })
+}
+
+fn strings(list: Option<Table>, single: Option<String>) -> rlua::Result<Vec<String>> {
+    let mut out = Vec::new();
+    if let Some(list) = list {
+        for value in list.sequence_values::<String>() {
+            out.push(value?);
+        }
+    }
+
+    out.extend(single);
+
+    Ok(out)
+}
+
+fn link(href: String) -> Link {
+    Link {
+        href,
+        rel: None,
+        media_type: None,
+        title: None,
+    }
+}
+
+/// Dates may be given as rfc3339 or rfc2822 strings, or as unix timestamps
+fn date(value: Value) -> rlua::Result<Option<DateTime<Utc>>> {
+    let date = match value {
+        Value::Nil => return Ok(None),
+        Value::Integer(secs) => DateTime::from_timestamp(secs, 0),
+        Value::Number(secs) => DateTime::from_timestamp(secs as i64, 0),
+        Value::String(s) => {
+            let s = s.to_str()?;
+            DateTime::parse_from_rfc3339(s)
+                .or_else(|_| DateTime::parse_from_rfc2822(s))
+                .ok()
+                .map(|date| date.with_timezone(&Utc))
+        }
+        _ => None,
+    };
+
+    date.map(Some)
+        .ok_or_else(|| rlua::Error::runtime("invalid date in source item"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(chunk: &str) -> rlua::Result<Feed> {
+        let lua = Lua::new();
+        let url = Url::parse("lua://releases").unwrap();
+        feed(&url, lua.load(chunk).eval()?)
+    }
+
+    fn dated(value: &str) -> rlua::Result<Option<DateTime<Utc>>> {
+        let lua = Lua::new();
+        date(lua.load(value).eval()?)
+    }
+
+    #[test]
+    fn builds_feeds_from_item_lists() {
+        let feed = eval(
+            r#"return {
+                { title = "First", link = "https://example.com/1" },
+                { id = "two", title = "Second", author = "Ann", categories = { "rust" } },
+                { title = "Third" },
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(feed.meta.id, "lua://releases");
+        assert_eq!(feed.meta.title.as_deref(), Some("releases"));
+        assert!(matches!(feed.meta.feed_type, FeedType::Source));
+
+        let ids: Vec<_> = feed.items.iter().map(|item| item.id.as_str()).collect();
+        assert_eq!(ids, ["https://example.com/1", "two", "Third"]);
+        assert_eq!(feed.items[0].link(), Some("https://example.com/1"));
+        assert_eq!(feed.items[1].authors[0].name, "Ann");
+        assert_eq!(feed.items[1].categories[0].term, "rust");
+    }
+
+    #[test]
+    fn reads_feed_fields() {
+        let feed = eval(
+            r#"return {
+                title = "Releases", description = "Tagged builds",
+                link = "https://example.com", ttl = 30,
+                items = { { title = "v1", summary = "first", content = "<p>hi</p>" } },
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(feed.meta.title.as_deref(), Some("Releases"));
+        assert_eq!(feed.meta.description.as_deref(), Some("Tagged builds"));
+        assert_eq!(feed.meta.links[0].href, "https://example.com");
+        assert_eq!(feed.meta.ttl, Some(30));
+        assert_eq!(feed.items[0].summary.as_deref(), Some("first"));
+        assert!(matches!(&feed.items[0].content, Some(Content::Body(body)) if body == "<p>hi</p>"));
+    }
+
+    #[test]
+    fn rejects_malformed_results() {
+        assert!(eval(r#"return "items""#).is_err());
+        assert!(eval(r#"return { { summary = "no id" } }"#).is_err());
+        assert!(eval(r#"return { { title = "t", updated = "yesterday" } }"#).is_err());
+    }
+
+    #[test]
+    fn parses_dates() {
+        let expected = DateTime::from_timestamp(1_700_000_000, 0);
+
+        assert_eq!(dated("return nil").unwrap(), None);
+        assert_eq!(dated("return 1700000000").unwrap(), expected);
+        assert_eq!(dated("return 1700000000.5").unwrap(), expected);
+        assert_eq!(dated(r#"return "2023-11-14T22:13:20Z""#).unwrap(), expected);
+        assert_eq!(
+            dated(r#"return "2023-11-14T23:13:20+01:00""#).unwrap(),
+            expected
+        );
+        assert_eq!(
+            dated(r#"return "Tue, 14 Nov 2023 22:13:20 +0000""#).unwrap(),
+            expected
+        );
+        assert!(dated(r#"return "14/11/2023""#).is_err());
+        assert!(dated("return true").is_err());
+    }
+
+    #[test]
+    fn only_gets_http_urls() {
+        let lua = Lua::new();
+        let fetcher = Fetcher {
+            client: reqwest::Client::new(),
+            credentials: None,
+        };
+        lua.globals()
+            .set("http", http(&lua, fetcher, None).unwrap())
+            .unwrap();
+
+        for url in ["file:///etc/passwd", "exec:id", "lua://releases"] {
+            let err = lua
+                .load(format!("return http.get({url:?})"))
+                .exec()
+                .unwrap_err();
+            assert!(
+                err.to_string().contains("only fetch http(s)"),
+                "{url}: {err}"
+            );
+        }
+
+        // allowed through to the fetch, which has no runtime here
+        let err = lua
+            .load(r#"return http.get("https://example.com")"#)
+            .exec()
+            .unwrap_err();
+        assert!(err.to_string().contains("no async runtime"), "{err}");
+        assert!(lua.load(r#"return http.get("not a url")"#).exec().is_err());
+    }
+}
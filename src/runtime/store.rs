@@ -0,0 +1,100 @@
+use rlua::{Lua, Table, Value};
+use tokio::runtime::Handle;
+
+use crate::{db::Conn, runtime::json};
+
+/// Builds the `store` table, json values kept in the database so every runtime worker and every
+/// run of cynd sees the same ones.
+pub(crate) fn store(lua: &Lua, conn: Conn, handle: Option<Handle>) -> rlua::Result<Table<'_>> {
+    let table = lua.create_table()?;
+
+    let get_conn = conn.clone();
+    let get_handle = handle.clone();
+    table.set(
+        "get",
+        lua.create_function(move |lua, key: String| {
+            let handle = runtime(&get_handle)?;
+            let value = handle
+                .block_on(get_conn.state(key))
+                .map_err(|err| rlua::Error::runtime(err.to_string()))?;
+
+            match value {
+                Some(value) => json::decode(lua, value.as_bytes()),
+                None => Ok(Value::Nil),
+            }
+        })?,
+    )?;
+
+    let set_conn = conn.clone();
+    let set_handle = handle.clone();
+    table.set(
+        "set",
+        lua.create_function(move |_, (key, value): (String, Value)| {
+            let handle = runtime(&set_handle)?;
+            let value = match value {
+                Value::Nil => None,
+                value => Some(json::encode(value)?),
+            };
+
+            handle
+                .block_on(set_conn.set_state(key, value))
+                .map_err(|err| rlua::Error::runtime(err.to_string()))
+        })?,
+    )?;
+
+    table.set(
+        "add",
+        lua.create_function(move |lua, (key, by): (String, Option<f64>)| {
+            let handle = runtime(&handle)?;
+            let sum = handle
+                .block_on(conn.add_state(key, by.unwrap_or(1.0)))
+                .map_err(|err| rlua::Error::runtime(err.to_string()))?;
+
+            json::decode(lua, sum.as_bytes())
+        })?,
+    )?;
+
+    Ok(table)
+}
+
+fn runtime(handle: &Option<Handle>) -> rlua::Result<&Handle> {
+    handle
+        .as_ref()
+        .ok_or_else(|| rlua::Error::runtime("no async runtime to reach the store with"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        interp::Instruction,
+        testing::{Scratch, item, meta},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn workers_adding_lose_no_updates() {
+        let scratch = Scratch::new();
+        let runtime = scratch.runtime(
+            r#"return {
+                process = function(item)
+                    if item.id == "total" then
+                        alert(tostring(store.get("count")))
+                    else
+                        store.add("count")
+                    end
+                end,
+            }"#,
+            4,
+        );
+        let process =
+            |id: &str| runtime.process("https://example.com/feed".to_string(), meta(), item(id));
+
+        let added = futures::future::join_all((0..200).map(|i| process(&i.to_string()))).await;
+        assert!(added.iter().all(Result::is_ok));
+
+        let prog = process("total").await.unwrap();
+        let [Instruction::Alert(alert)] = &prog.instructions[..] else {
+            panic!("expected one alert: {prog:?}");
+        };
+        assert_eq!(alert.message.as_deref(), Some("200"));
+    }
+}
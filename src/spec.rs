@@ -0,0 +1,482 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use rlua::{Lua, Table};
+
+use crate::{
+    Error,
+    feed::{Category, FeedMeta},
+    interp::Program,
+};
+
+/// A case from a spec file: a fixture feed and the program expected for each of its items.
+///
+/// Spec files are lua returning a list of cases:
+///
+/// ```lua
+/// return {
+///   {
+///     name = "alerts on releases",
+///     feed = "fixtures/blog.xml",
+///     items = { "post-1", "post-2" },
+///     meta = { title = "Releases" },
+///     expect = {
+///       ["post-1"] = { "alert", "record" },
+///     },
+///   },
+/// }
+/// ```
+///
+/// `feed` is relative to the spec file, or `body` gives the feed inline. `items` picks the items
+/// to process, all of them by default, and those missing from `expect` must produce nothing.
+/// `meta` sets the feed's `id`, `title`, `description`, `language`, `rights`, `ttl` or
+/// `categories` over the fixture's, for scripts telling feeds apart by them.
+/// An expected instruction matches on its name or on exactly how `cynd eval` prints it.
+#[derive(Debug, Clone)]
+pub struct Spec {
+    pub name: String,
+    pub fixture: Fixture,
+    pub items: Option<Vec<String>>,
+    pub meta: Meta,
+    pub expect: HashMap<String, Vec<String>>,
+}
+
+/// Feed metadata a case sets over what the fixture has
+#[derive(Debug, Clone, Default)]
+pub struct Meta {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub rights: Option<String>,
+    pub ttl: Option<u32>,
+    /// category terms
+    pub categories: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Fixture {
+    File(PathBuf),
+    Body(String),
+}
+
+/// What running a spec turned up
+#[derive(Debug, Default)]
+pub struct Outcome {
+    pub mismatches: Vec<Mismatch>,
+    /// items named by the spec that the fixture doesn't have
+    pub missing: Vec<String>,
+}
+
+/// An item whose program differs from the expected one
+#[derive(Debug)]
+pub struct Mismatch {
+    pub id: String,
+    pub title: Option<String>,
+    /// the script failed instead of producing a program
+    pub error: Option<String>,
+    pub diff: Vec<Line>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Line {
+    Same(String),
+    /// expected but not produced
+    Missing(String),
+    /// produced but not expected
+    Extra(String),
+}
+
+impl Spec {
+    /// Reads every case of a spec file, without the runtime of `init.lua`
+    pub fn load(path: &Path) -> crate::Result<Vec<Spec>> {
+        let invalid = |err: rlua::Error| Error::Spec(format!("{}: {err}", path.display()));
+
+        let lua = Lua::new();
+        let cases: Vec<Table> = lua.load(path).eval().map_err(invalid)?;
+        let base = path.parent().unwrap_or(Path::new("."));
+
+        cases
+            .into_iter()
+            .enumerate()
+            .map(|(i, case)| Spec::from_table(base, i + 1, case).map_err(invalid))
+            .collect()
+    }
+
+    fn from_table(base: &Path, n: usize, case: Table) -> rlua::Result<Spec> {
+        let name = case
+            .get::<_, Option<String>>("name")?
+            .unwrap_or_else(|| format!("case {n}"));
+
+        let fixture = match (
+            case.get::<_, Option<String>>("feed")?,
+            case.get::<_, Option<String>>("body")?,
+        ) {
+            (Some(feed), None) => Fixture::File(base.join(feed)),
+            (None, Some(body)) => Fixture::Body(body),
+            _ => {
+                return Err(rlua::Error::runtime(format!(
+                    "{name} needs exactly one of feed or body"
+                )));
+            }
+        };
+
+        let mut expect = HashMap::new();
+        if let Some(table) = case.get::<_, Option<Table>>("expect")? {
+            for pair in table.pairs::<String, Vec<String>>() {
+                let (id, instructions) = pair?;
+                expect.insert(id, instructions);
+            }
+        }
+
+        let meta = match case.get::<_, Option<Table>>("meta")? {
+            Some(table) => Meta::from_table(&name, table)?,
+            None => Meta::default(),
+        };
+
+        Ok(Spec {
+            name,
+            fixture,
+            items: case.get("items")?,
+            meta,
+            expect,
+        })
+    }
+
+    /// Compares the program of an item against what the spec expects of it
+    pub(crate) fn check(&self, id: &str, prog: &Program) -> Option<Vec<Line>> {
+        let expected = self.expect.get(id).map(Vec::as_slice).unwrap_or_default();
+        let diff = diff(expected, prog);
+
+        diff.iter()
+            .any(|line| !matches!(line, Line::Same(_)))
+            .then_some(diff)
+    }
+}
+
+impl Meta {
+    const FIELDS: &[&str] = &[
+        "id",
+        "title",
+        "description",
+        "language",
+        "rights",
+        "ttl",
+        "categories",
+    ];
+
+    fn from_table(name: &str, table: Table) -> rlua::Result<Meta> {
+        // a misspelled field would otherwise quietly leave the fixture's value
+        for pair in table.clone().pairs::<String, rlua::Value>() {
+            let (key, _) = pair?;
+            if !Meta::FIELDS.contains(&key.as_str()) {
+                return Err(rlua::Error::runtime(format!(
+                    "{name} sets unknown meta field {key}"
+                )));
+            }
+        }
+
+        Ok(Meta {
+            id: table.get("id")?,
+            title: table.get("title")?,
+            description: table.get("description")?,
+            language: table.get("language")?,
+            rights: table.get("rights")?,
+            ttl: table.get("ttl")?,
+            categories: table.get("categories")?,
+        })
+    }
+
+    /// Sets the fields the case gives on the fixture's metadata
+    pub(crate) fn apply(&self, meta: &mut FeedMeta) {
+        if let Some(id) = &self.id {
+            meta.id = id.clone();
+        }
+
+        let strings = [
+            (&self.title, &mut meta.title),
+            (&self.description, &mut meta.description),
+            (&self.language, &mut meta.language),
+            (&self.rights, &mut meta.rights),
+        ];
+        for (set, field) in strings {
+            if set.is_some() {
+                field.clone_from(set);
+            }
+        }
+
+        if self.ttl.is_some() {
+            meta.ttl = self.ttl;
+        }
+
+        if let Some(categories) = &self.categories {
+            meta.categories = categories
+                .iter()
+                .map(|term| Category {
+                    term: term.clone(),
+                    label: None,
+                    subcategories: Vec::new(),
+                })
+                .collect();
+        }
+    }
+}
+
+/// Lines of the longest common subsequence of expected and produced instructions
+fn diff(expected: &[String], prog: &Program) -> Vec<Line> {
+    let actual: Vec<(&str, String)> = prog
+        .instructions
+        .iter()
+        .map(|inst| (inst.name(), inst.to_string()))
+        .collect();
+
+    let matches = |e: &String, (name, shown): &(&str, String)| e == name || e == shown;
+
+    // lengths of the common subsequences of the suffixes
+    let mut lcs = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if matches(&expected[i], &actual[j]) {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && matches(&expected[i], &actual[j]) {
+            lines.push(Line::Same(actual[j].1.clone()));
+            i += 1;
+            j += 1;
+        } else if i < expected.len() && (j == actual.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(Line::Missing(expected[i].clone()));
+            i += 1;
+        } else {
+            lines.push(Line::Extra(actual[j].1.clone()));
+            j += 1;
+        }
+    }
+
+    lines
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty() && self.missing.is_empty()
+    }
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.title {
+            Some(title) => writeln!(f, "[{title}]({})", self.id)?,
+            None => writeln!(f, "({})", self.id)?,
+        }
+
+        if let Some(error) = &self.error {
+            for (i, line) in error.lines().enumerate() {
+                let lead = if i == 0 { "error: " } else { "  " };
+                writeln!(f, "  {lead}{line}")?;
+            }
+        }
+
+        for line in &self.diff {
+            match line {
+                Line::Same(inst) => writeln!(f, "    {inst}")?,
+                Line::Missing(inst) => writeln!(f, "  - {inst}")?,
+                Line::Extra(inst) => writeln!(f, "  + {inst}")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        interp::{Alert, Instruction, Record},
+        testing::meta,
+    };
+
+    fn specs(chunk: &str) -> rlua::Result<Vec<Spec>> {
+        let lua = Lua::new();
+        let cases: Vec<Table> = lua.load(chunk).eval()?;
+
+        cases
+            .into_iter()
+            .enumerate()
+            .map(|(i, case)| Spec::from_table(Path::new("specs"), i + 1, case))
+            .collect()
+    }
+
+    fn alert(message: &str) -> Instruction {
+        Alert {
+            summary: None,
+            message: Some(message.to_string()),
+        }
+        .into()
+    }
+
+    fn program(instructions: Vec<Instruction>) -> Program {
+        Program { instructions }
+    }
+
+    #[test]
+    fn reads_cases() {
+        let specs = specs(
+            r#"return {
+                {
+                    name = "releases",
+                    feed = "fixtures/blog.xml",
+                    items = { "a", "b" },
+                    meta = { title = "Releases", ttl = 5, categories = { "rust" } },
+                    expect = { a = { "alert", "record" } },
+                },
+                { body = "<rss/>" },
+            }"#,
+        )
+        .unwrap();
+
+        let [releases, inline] = &specs[..] else {
+            panic!("expected two cases: {specs:?}");
+        };
+
+        assert_eq!(releases.name, "releases");
+        assert!(
+            matches!(&releases.fixture, Fixture::File(path) if path == Path::new("specs/fixtures/blog.xml"))
+        );
+        assert_eq!(
+            releases.items.as_deref(),
+            Some(&["a".to_string(), "b".to_string()][..])
+        );
+        assert_eq!(releases.expect["a"], ["alert", "record"]);
+        assert_eq!(releases.meta.title.as_deref(), Some("Releases"));
+
+        assert_eq!(inline.name, "case 2");
+        assert!(matches!(&inline.fixture, Fixture::Body(body) if body == "<rss/>"));
+        assert!(inline.items.is_none());
+        assert!(inline.expect.is_empty());
+        assert!(inline.meta.title.is_none());
+    }
+
+    #[test]
+    fn rejects_broken_cases() {
+        for (chunk, message) in [
+            (
+                r#"return { { name = "x" } }"#,
+                "x needs exactly one of feed or body",
+            ),
+            (
+                r#"return { { feed = "a.xml", body = "<rss/>" } }"#,
+                "case 1 needs exactly one of feed or body",
+            ),
+            (
+                r#"return { { body = "", meta = { tilte = "x" } } }"#,
+                "unknown meta field tilte",
+            ),
+        ] {
+            let err = specs(chunk).unwrap_err();
+            assert!(err.to_string().contains(message), "{chunk}: {err}");
+        }
+    }
+
+    #[test]
+    fn sets_meta_over_the_fixture() {
+        let spec = &specs(
+            r#"return { {
+                body = "",
+                meta = { id = "urn:x", title = "Other", ttl = 5, categories = { "a", "b" } },
+            } }"#,
+        )
+        .unwrap()[0];
+
+        let mut meta = meta();
+        meta.language = Some("en".to_string());
+        spec.meta.apply(&mut meta);
+
+        assert_eq!(meta.id, "urn:x");
+        assert_eq!(meta.title.as_deref(), Some("Other"));
+        assert_eq!(meta.ttl, Some(5));
+        assert_eq!(meta.language.as_deref(), Some("en"));
+        let terms: Vec<_> = meta.categories.iter().map(|c| &c.term[..]).collect();
+        assert_eq!(terms, ["a", "b"]);
+    }
+
+    #[test]
+    fn matches_names_or_printed_instructions() {
+        let hi = alert("hi").to_string();
+        let prog = program(vec![alert("hi"), Record {}.into()]);
+
+        let expected =
+            |insts: &[&str]| -> Vec<String> { insts.iter().map(|inst| inst.to_string()).collect() };
+
+        assert!(
+            diff(&expected(&["alert", "record"]), &prog)
+                .iter()
+                .all(|line| matches!(line, Line::Same(_)))
+        );
+        assert!(
+            diff(&expected(&[&hi, "record"]), &prog)
+                .iter()
+                .all(|line| matches!(line, Line::Same(_)))
+        );
+
+        // close to the printed form isn't enough
+        assert_eq!(
+            diff(&expected(&["alert hi", "record"]), &prog),
+            [
+                Line::Missing("alert hi".to_string()),
+                Line::Extra(hi.clone()),
+                Line::Same("record".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn orders_missing_and_extra_lines() {
+        let prog = program(vec![alert("one"), Record {}.into(), alert("two")]);
+        let one = alert("one").to_string();
+        let two = alert("two").to_string();
+
+        assert_eq!(
+            diff(&["record".to_string(), "download".to_string()], &prog),
+            [
+                Line::Extra(one.clone()),
+                Line::Same("record".to_string()),
+                Line::Missing("download".to_string()),
+                Line::Extra(two.clone()),
+            ]
+        );
+        assert_eq!(
+            diff(&["mark".to_string()], &program(Vec::new())),
+            [Line::Missing("mark".to_string())]
+        );
+        assert_eq!(
+            diff(&[], &prog),
+            [
+                Line::Extra(one),
+                Line::Extra("record".to_string()),
+                Line::Extra(two)
+            ]
+        );
+    }
+
+    #[test]
+    fn only_reports_differences() {
+        let spec = &specs(r#"return { { body = "", expect = { a = { "record" } } } }"#).unwrap()[0];
+
+        assert!(spec.check("a", &program(vec![Record {}.into()])).is_none());
+        assert!(spec.check("b", &program(Vec::new())).is_none());
+        assert_eq!(
+            spec.check("b", &program(vec![Record {}.into()])),
+            Some(vec![Line::Extra("record".to_string())])
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    Client, ClientBuilder, Feed, FeedItem, db::Conn, feed::FeedMeta, fetcher::Fetcher,
+    metrics::Metrics, runtime::Runtime,
+};
+
+/// A directory of its own for a test, removed once dropped
+pub(crate) struct Scratch {
+    dir: PathBuf,
+}
+
+impl Scratch {
+    pub(crate) fn new() -> Scratch {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "cynd-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        Scratch { dir }
+    }
+
+    /// Makes a directory within, returning its path
+    pub(crate) fn dir(&self, name: &str) -> PathBuf {
+        let dir = self.dir.join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes a file in the directory, returning its path
+    pub(crate) fn write(&self, name: &str, contents: &str) -> PathBuf {
+        let path = self.dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// A client on a fresh database in the directory, running `init` with `workers` lua states
+    pub(crate) async fn client(&self, init: &str, workers: usize) -> Client {
+        self.builder(init).workers(workers).build().await.unwrap()
+    }
+
+    /// A client builder set up like [`Scratch::client`], for the tests changing more of it
+    pub(crate) fn builder(&self, init: &str) -> ClientBuilder {
+        Client::builder()
+            .runtime(self.write("init.lua", init))
+            .database(self.dir.join("db.sqlite"))
+            .credentials(self.dir.join("credentials"))
+            .migrate()
+    }
+
+    /// The lua workers alone running `init`, on a fresh database in the directory
+    pub(crate) fn runtime(&self, init: &str, workers: usize) -> Runtime {
+        self.spawn(init, workers, false)
+    }
+
+    /// Like [`Scratch::runtime`], sandboxed before `init` loads
+    pub(crate) fn sandboxed(&self, init: &str, workers: usize) -> Runtime {
+        self.spawn(init, workers, true)
+    }
+
+    fn spawn(&self, init: &str, workers: usize, sandbox: bool) -> Runtime {
+        let conn = rusqlite::Connection::open(self.dir.join("db.sqlite")).unwrap();
+
+        Runtime::new(
+            self.write("init.lua", init),
+            Fetcher {
+                client: reqwest::Client::new(),
+                credentials: None,
+            },
+            Conn::new(conn, true).unwrap(),
+            Metrics::new().unwrap(),
+            workers,
+            sandbox,
+        )
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// An item with nothing but an id
+pub(crate) fn item(id: &str) -> FeedItem {
+    FeedItem {
+        id: id.to_string(),
+        title: None,
+        authors: Vec::new(),
+        contributors: Vec::new(),
+        summary: None,
+        content: None,
+        source: None,
+        categories: Vec::new(),
+        links: Vec::new(),
+        updated: None,
+        published: None,
+        base: None,
+        media: Vec::new(),
+        rights: None,
+        language: None,
+        duplicate_of: None,
+    }
+}
+
+/// The metadata of a feed with nothing but a title
+pub(crate) fn meta() -> FeedMeta {
+    Feed::parse(br#"<rss version="2.0"><channel><title>Feed</title></channel></rss>"#)
+        .unwrap()
+        .meta
+}